@@ -2,7 +2,7 @@
 
 // This file provides the single function build_info.
 
-use enclone_core::barcode_fate::BarcodeFate;
+use enclone_core::barcode_fate::{BarcodeFate, FateRecord};
 use vdj_ann::refx;
 
 use self::refx::RefData;
@@ -22,7 +22,7 @@ pub fn build_info(
     refdata: &RefData,
     ctl: &EncloneControl,
     exact_clonotypes: &mut [ExactClonotype],
-    fate: &mut [HashMap<String, BarcodeFate>],
+    fate: &mut [HashMap<String, FateRecord>],
 ) -> Vec<CloneInfo> {
     // Build info about clonotypes.  We create a data structure info.
     // An entry in info is a clonotype having appropriate properties.
@@ -35,7 +35,7 @@ pub fn build_info(
         usize,
         Vec<CloneInfo>,
         ExactClonotype,
-        Vec<(usize, String, BarcodeFate)>,
+        Vec<(usize, String, FateRecord)>,
     )>::new();
     for (i, ct) in exact_clonotypes.iter().enumerate() {
         results.push((i, Vec::new(), ct.clone(), Vec::new()));
@@ -325,7 +325,7 @@ pub fn build_info(
                 res.3.push((
                     ex.clones[j][0].dataset_index,
                     ex.clones[j][0].barcode.clone(),
-                    BarcodeFate::Improper,
+                    FateRecord::from(BarcodeFate::Improper).exact(&ex.share),
                 ));
             }
         }
@@ -3,7 +3,7 @@
 // Miscellaneous functions.
 
 use enclone_core::{
-    barcode_fate::BarcodeFate,
+    barcode_fate::{BarcodeFate, FateRecord},
     defs::{CloneInfo, EncloneControl, ExactClonotype, TigData},
 };
 use equiv::EquivRel;
@@ -14,9 +14,7 @@ use perf_stats::elapsed;
 use std::collections::HashMap;
 use std::time::Instant;
 use string_utils::stringme;
-use vector_utils::{
-    bin_member, bin_position, erase_if, next_diff, next_diff1_3, unique_sort, VecUtils,
-};
+use vector_utils::{bin_position, erase_if, next_diff, next_diff1_3, unique_sort, VecUtils};
 
 // ▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓
 
@@ -198,7 +196,7 @@ pub fn lookup_heavy_chain_reuse(
 pub fn cross_filter(
     ctl: &EncloneControl,
     tig_bc: &mut Vec<Vec<TigData>>,
-    fate: &mut [HashMap<String, BarcodeFate>],
+    fate: &mut [HashMap<String, FateRecord>],
 ) {
     // Get the list of dataset origins.  Here we allow the same origin name to have been used
     // for more than one donor, as we haven't explicitly prohibited that.
@@ -263,7 +261,7 @@ pub fn cross_filter(
 
     // Now do the cross filter.
 
-    let mut blacklist = Vec::<&[u8]>::new();
+    let mut blacklist = Vec::<(&[u8], f64)>::new(); // (V..J, probability)
     let mut i = 0;
     while i < vjx.len() {
         let j = next_diff1_3(&vjx, i as i32) as usize;
@@ -275,19 +273,27 @@ pub fn cross_filter(
             if y > 0 {
                 let p = (x as f64 / y as f64).powi(n as i32);
                 if p <= 1.0e-6 {
-                    blacklist.push(vjx[i].0);
+                    blacklist.push((vjx[i].0, p));
                 }
             }
         }
         i = j;
     }
-    blacklist.sort();
+    blacklist.sort_by(|a, b| a.0.cmp(b.0));
     let mut to_delete = vec![false; tig_bc.len()];
     const UMIS_SAVE: usize = 100;
     for (i, tigi) in tig_bc.iter().enumerate() {
         for tig in tigi {
-            if tig.umi_count < UMIS_SAVE && bin_member(&blacklist, &tig.seq()) {
-                fate[tigi[0].dataset_index].insert(tigi[0].barcode.clone(), BarcodeFate::Cross);
+            if tig.umi_count >= UMIS_SAVE {
+                continue;
+            }
+            if let Ok(b) = blacklist.binary_search_by(|b| b.0.cmp(tig.seq())) {
+                fate[tigi[0].dataset_index].insert(
+                    tigi[0].barcode.clone(),
+                    FateRecord::from(BarcodeFate::Cross)
+                        .evidence("probability", format!("{:.3e}", blacklist[b].1))
+                        .evidence("umis", tig.umi_count),
+                );
                 if !ctl.clono_filt_opt_def.ncross {
                     to_delete[i] = true;
                 }
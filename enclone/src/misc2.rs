@@ -6,7 +6,7 @@ use crate::innate::mark_innate;
 use crate::misc3::study_consensus;
 use amino::aa_seq;
 use debruijn::dna_string::DnaString;
-use enclone_core::barcode_fate::BarcodeFate;
+use enclone_core::barcode_fate::{BarcodeFate, FateRecord};
 use enclone_core::defs::{EncloneControl, ExactClonotype, Junction, TigData, TigData0, TigData1};
 use io_utils::{fwriteln, open_for_write_new};
 use rayon::prelude::*;
@@ -31,8 +31,9 @@ use vector_utils::{
 
 pub fn filter_gelbead_contamination(
     ctl: &EncloneControl,
+    share: &[TigData1],
     clones: &mut Vec<Vec<TigData0>>,
-    fate: &mut Vec<(usize, String, BarcodeFate)>,
+    fate: &mut Vec<(usize, String, FateRecord)>,
 ) {
     const GB_UMI_MULT: usize = 10;
     const GB_MIN_FRAC: f64 = 0.2;
@@ -78,7 +79,7 @@ pub fn filter_gelbead_contamination(
             fate.push((
                 clone[0].dataset_index,
                 clone[0].barcode.clone(),
-                BarcodeFate::GelBeadContamination,
+                FateRecord::from(BarcodeFate::GelBeadContamination).exact(share),
             ));
         }
     }
@@ -273,7 +274,7 @@ pub fn find_exact_subclonotypes(
     ctl: &EncloneControl,
     tig_bc: &[Vec<TigData>],
     refdata: &RefData,
-    fate: &mut [HashMap<String, BarcodeFate>],
+    fate: &mut [HashMap<String, FateRecord>],
 ) -> Vec<ExactClonotype> {
     let mut exact_clonotypes = Vec::<ExactClonotype>::new();
     let mut r = 0;
@@ -323,11 +324,7 @@ pub fn find_exact_subclonotypes(
     }
     ctl.perf_stats(&t, "finding exact subclonotypes one");
     let t = Instant::now();
-    let mut results = Vec::<(
-        usize,
-        Vec<ExactClonotype>,
-        Vec<(usize, String, BarcodeFate)>,
-    )>::new();
+    let mut results = Vec::<(usize, Vec<ExactClonotype>, Vec<(usize, String, FateRecord)>)>::new();
     for i in 0..groups.len() {
         results.push((i, Vec::new(), Vec::new()));
     }
@@ -385,7 +382,7 @@ pub fn find_exact_subclonotypes(
                     res.2.push((
                         tig_bc[t][0].dataset_index,
                         tig_bc[t][0].barcode.clone(),
-                        BarcodeFate::DuplicatedBarcode,
+                        BarcodeFate::DuplicatedBarcode.into(),
                     ));
                 }
             }
@@ -412,7 +409,7 @@ pub fn find_exact_subclonotypes(
 
         // Filter out putative gel bead contamination.
 
-        filter_gelbead_contamination(ctl, &mut clones, &mut res.2);
+        filter_gelbead_contamination(ctl, &share, &mut clones, &mut res.2);
 
         // Save exact subclonotype.
 
@@ -0,0 +1,162 @@
+// Copyright (c) 2021 10X Genomics, Inc. All rights reserved.
+
+// Errors in individual contig records, as found by read_json.  Rather than stopping at the
+// first bad record, read_json collects the errors in all records and renders the first of them
+// as one report, so that the records can be found in a large file.
+
+use std::fmt;
+
+/// The most errors that are reported from one file.
+pub const MAX_CONTIG_PARSE_ERRORS: usize = 20;
+
+/// Advice for the user that applies to a kind of error.  It is given once in a report, however
+/// many errors call for it.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum ParseAdvice {
+    /// The annotations do not fit the reference, which may not be the one used to make the file.
+    CheckReference,
+    /// The reference gene names differ from those in the file.
+    InconsistentReference,
+    /// The file has no annotations, so they have to be recomputed.
+    Reannotate,
+}
+
+#[derive(Clone, Debug)]
+pub struct ContigParseError {
+    pub file: String,
+    /// The index of the record in the file, starting at zero.
+    pub record: usize,
+    pub barcode: Option<String>,
+    pub contig_name: Option<String>,
+    /// The field that could not be used, and its value, if the error is about one field.
+    pub field: Option<String>,
+    pub value: Option<String>,
+    pub problem: String,
+    pub advice: Option<ParseAdvice>,
+}
+
+impl ContigParseError {
+    pub fn new(file: &str, record: usize, problem: impl Into<String>) -> ContigParseError {
+        ContigParseError {
+            file: file.to_string(),
+            record,
+            barcode: None,
+            contig_name: None,
+            field: None,
+            value: None,
+            problem: problem.into(),
+            advice: None,
+        }
+    }
+
+    pub fn barcode(mut self, barcode: &str) -> Self {
+        self.barcode = Some(barcode.to_string());
+        self
+    }
+
+    pub fn contig(mut self, contig_name: &str) -> Self {
+        self.contig_name = Some(contig_name.to_string());
+        self
+    }
+
+    /// Record the offending field and value.  Long values are truncated.
+    pub fn field(mut self, field: &str, value: impl ToString) -> Self {
+        const MAX_VALUE_LEN: usize = 100;
+        let mut value = value.to_string();
+        if let Some((i, _)) = value.char_indices().nth(MAX_VALUE_LEN) {
+            value.truncate(i);
+            value += "...";
+        }
+        self.field = Some(field.to_string());
+        self.value = Some(value);
+        self
+    }
+
+    pub fn advice(mut self, advice: ParseAdvice) -> Self {
+        self.advice = Some(advice);
+        self
+    }
+}
+
+impl fmt::Display for ContigParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}, record {}", self.file, self.record + 1)?;
+        if let Some(barcode) = &self.barcode {
+            write!(f, ", barcode {barcode}")?;
+        }
+        if let Some(contig_name) = &self.contig_name {
+            write!(f, ", contig {contig_name}")?;
+        }
+        if let Some(field) = &self.field {
+            write!(
+                f,
+                ", field {field} = {}",
+                self.value.as_deref().unwrap_or_default()
+            )?;
+        }
+        write!(f, ": {}", self.problem)
+    }
+}
+
+/// Render errors, which must be in record order, as a report.  The errors are the first `total`
+/// found in the file, or all of them if there are no more.  The advice for each kind of error is
+/// given once, using `advice_text`.
+pub fn contig_parse_report(
+    errors: &[ContigParseError],
+    total: usize,
+    advice_text: impl Fn(ParseAdvice, &ContigParseError) -> String,
+) -> String {
+    let mut report = if total == errors.len() {
+        format!("\nFound {total} bad contig record(s):\n\n")
+    } else {
+        format!(
+            "\nFound {total} bad contig records.  These are the first {}:\n\n",
+            errors.len()
+        )
+    };
+    for e in errors.iter() {
+        report += &format!("• {e}\n");
+    }
+    let mut advice: Vec<(ParseAdvice, &ContigParseError)> = errors
+        .iter()
+        .filter_map(|e| e.advice.map(|a| (a, e)))
+        .collect();
+    advice.sort_by_key(|a| a.0);
+    advice.dedup_by_key(|a| a.0);
+    for (a, e) in advice {
+        report += &advice_text(a, e);
+    }
+    report
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{contig_parse_report, ContigParseError, ParseAdvice};
+
+    #[test]
+    fn test_contig_parse_report() {
+        let errors = vec![
+            ContigParseError::new("a.json", 2, "not valid JSON")
+                .advice(ParseAdvice::CheckReference),
+            ContigParseError::new("a.json", 7, "not an integer")
+                .barcode("AAAC-1")
+                .contig("AAAC-1_contig_2")
+                .field("umi_count", "\"x\"")
+                .advice(ParseAdvice::CheckReference),
+        ];
+        let report = contig_parse_report(&errors, 2, |a, _| format!("\n{a:?}\n"));
+        assert_eq!(
+            report,
+            "\nFound 2 bad contig record(s):\n\n\
+             • a.json, record 3: not valid JSON\n\
+             • a.json, record 8, barcode AAAC-1, contig AAAC-1_contig_2, field umi_count = \"x\": \
+             not an integer\n\
+             \nCheckReference\n"
+        );
+        let report = contig_parse_report(&errors[..1], 2, |a, _| format!("\n{a:?}\n"));
+        assert!(report.starts_with(
+            "\nFound 2 bad contig records.  These are the first 1:\n\n\
+             • a.json, record 3: not valid JSON\n"
+        ));
+    }
+}
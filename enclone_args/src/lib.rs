@@ -3,6 +3,7 @@
 
 use io_utils::path_exists;
 
+pub mod contig_parse_error;
 pub mod load_gex;
 pub mod load_gex_core;
 pub mod load_gex_util;
@@ -13,7 +14,10 @@ pub mod proc_args_check;
 pub mod proc_args_post;
 pub mod process_special_arg1;
 pub mod process_special_arg2;
+pub mod read_airr;
+pub mod read_contig_csv;
 pub mod read_json;
+pub mod vdj_source;
 
 // parse_csv_pure: same as parse_csv, but don't strip out quotes
 
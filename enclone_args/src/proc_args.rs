@@ -468,6 +468,7 @@ pub fn proc_args(ctl: &mut EncloneControl, args: &[String]) -> Result<(), String
         ("PRE_EVAL", &mut ctl.gen_opt.pre_eval),
         ("PRE_EVAL_SHOW", &mut ctl.gen_opt.pre_eval_show),
         ("PROTECT_BADS", &mut ctl.clono_filt_opt.protect_bads),
+        ("PROTO_INDEX", &mut ctl.gen_opt.proto_index),
         ("QUAD_HIVE", &mut ctl.plot_opt.plot_quad),
         ("RE", &mut ctl.gen_opt.reannotate),
         ("REPROD", &mut ctl.gen_opt.reprod),
@@ -562,6 +563,7 @@ pub fn proc_args(ctl: &mut EncloneControl, args: &[String]) -> Result<(), String
         ("PFREQ", &mut ctl.join_print_opt.pfreq),
         ("SUPER_COMP_FILT", &mut ctl.join_alg_opt.super_comp_filt),
         ("SPLIT_MAX_CHAINS", &mut ctl.join_alg_opt.split_max_chains),
+        ("SUBSAMPLE_SEED", &mut ctl.gen_opt.subsample_seed),
     ];
 
     // Define arguments that set something to an i32.
@@ -602,6 +604,7 @@ pub fn proc_args(ctl: &mut EncloneControl, args: &[String]) -> Result<(), String
         ("PCHAINS", &mut ctl.parseable_opt.pchains),
         ("SESSION_NAME", &mut ctl.gen_opt.session_name),
         ("TRACE_BARCODE", &mut ctl.gen_opt.trace_barcode),
+        ("VDJ_FORMAT", &mut ctl.gen_opt.vdj_format),
     ];
 
     // Define arguments that set something to a string that is an output file name.
@@ -618,6 +621,7 @@ pub fn proc_args(ctl: &mut EncloneControl, args: &[String]) -> Result<(), String
     // Define arguments that set something to a string that is an output file name or stdout.
 
     let set_string_writeable_or_stdout = [
+        ("AIRR", &mut ctl.gen_opt.airr),
         ("PEER_GROUP", &mut ctl.gen_opt.peer_group_filename),
         ("PHYLIP_AA", &mut ctl.gen_opt.phylip_aa),
         ("PHYLIP_DNA", &mut ctl.gen_opt.phylip_dna),
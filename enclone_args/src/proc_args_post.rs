@@ -9,7 +9,9 @@ use enclone_vars::encode_arith;
 use evalexpr::build_operator_tree;
 use expr_tools::vars_of_node;
 use io_utils::{open_for_read, open_userfile_for_read, path_exists};
+use std::collections::hash_map::RandomState;
 use std::collections::HashMap;
+use std::hash::{BuildHasher, Hasher};
 use std::io::BufRead;
 use std::time::Instant;
 use string_utils::{parse_csv, TextUtils};
@@ -336,6 +338,23 @@ pub fn proc_args_post(
         }
     }
 
+    // Check VDJ_FORMAT.
+
+    if !["", "json", "airr", "csv"].contains(&ctl.gen_opt.vdj_format.as_str()) {
+        return Err(
+            "\nThe only allowed forms for VDJ_FORMAT are VDJ_FORMAT=json, \
+            VDJ_FORMAT=airr and VDJ_FORMAT=csv.\n"
+                .to_string(),
+        );
+    }
+
+    // If SUBSAMPLE is given without SUBSAMPLE_SEED, choose a random seed, so that separate runs
+    // keep different barcodes.  The seed is recorded in the PROTO metadata.
+
+    if ctl.gen_opt.subsample >= 0.0 && !args.iter().any(|a| a.starts_with("SUBSAMPLE_SEED=")) {
+        ctl.gen_opt.subsample_seed = RandomState::new().build_hasher().finish() as usize;
+    }
+
     // Sanity check grouping arguments.
 
     if ctl.clono_group_opt.style == "asymmetric"
@@ -420,6 +439,23 @@ pub fn proc_args_post(
                 .to_string(),
         );
     }
+
+    // Plots are written only as SVG, to a file or to stdout.
+
+    for (what, path) in [
+        ("plot", &ctl.plot_opt.plot_file),
+        ("PLOTXY_EXACT plot", &ctl.plot_opt.plot_xy_filename),
+        ("SIM_MAT_PLOT plot", &ctl.plot_opt.sim_mat_plot_file),
+    ] {
+        if path.ends_with(".png") {
+            return Err(format!(
+                "\nThe {what} can only be written as SVG, so the file name should end with .svg.\n"
+            ));
+        }
+        if path == "gui" || path == "gui_stdout" {
+            return Err(format!("\nThe {what} cannot be displayed in a GUI.\n"));
+        }
+    }
     if ctl.clono_print_opt.amino.is_empty() && ctl.clono_print_opt.cvars.is_empty() {
         return Err(
             "\nSorry, use of both CVARS= and AMINO= (setting both to null) is not \
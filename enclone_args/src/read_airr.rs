@@ -0,0 +1,359 @@
+// Copyright (c) 2021 10X Genomics, Inc. All rights reserved.
+
+// Read contigs from an AIRR Rearrangement TSV file, as an alternative to contig annotations
+// json, for data from other platforms.  This is used if VDJ_FORMAT=airr is specified.
+//
+// Each row is converted to a contig annotation, as found in contig_annotations.json, and these
+// are then processed by read_json like any other source, with the annotations computed from the
+// sequence against the reference.  AIRR files do not carry quality scores, so every base is
+// given quality 40.  Every row is taken to be from a cell.
+//
+// Fields that are used:
+// • cell_id
+// • sequence_id
+// • sequence
+// • productive -- optional, if present and false the contig is not used
+// • v_call, d_call, j_call, c_call -- optional
+// • junction -- optional, if present and the computed CDR3 differs, the contig is not used
+// • umi_count -- optional, else duplicate_count, else 1
+// • consensus_count -- optional, used as read count, else umi_count.
+//
+// The gene calls are resolved against the reference by name.  Only the first call is used, and
+// any allele suffix (*01) is ignored.  A call that names no reference gene is an error, since it
+// implies that the file was made with a different reference.  If a call differs from the gene
+// found by annotation, the called gene is used, provided that its reference sequence has the
+// same length, so that the alignment coordinates remain valid.  The contig is not realigned to
+// it, so the alignment is that to the gene found by annotation.  Otherwise the gene found by
+// annotation is kept.  Both cases are reported as warnings.
+
+use crate::vdj_source::ContigAnnotation;
+use enclone_core::defs::TigData;
+use std::collections::HashMap;
+use std::fs::read_to_string;
+use vdj_ann::refx::RefData;
+use vector_utils::erase_if;
+
+/// The gene calls for one contig, without allele suffixes.
+#[derive(Default)]
+pub struct AirrCalls {
+    pub v: Option<String>,
+    pub d: Option<String>,
+    pub j: Option<String>,
+    pub c: Option<String>,
+    pub junction: Option<String>,
+}
+
+/// The contigs in an AIRR file, sorted by barcode, and their gene calls, keyed by contig name.
+pub struct AirrContigs {
+    pub contigs: Vec<ContigAnnotation>,
+    pub calls: HashMap<String, AirrCalls>,
+}
+
+pub fn read_airr(path: &str) -> Result<AirrContigs, String> {
+    let text = read_to_string(path)
+        .map_err(|e| format!("\nUnable to read the AIRR file {path}: {e}.\n"))?;
+    let mut lines = text.lines();
+    let header: Vec<&str> = lines.next().unwrap_or_default().split('\t').collect();
+    let col = |name: &str| header.iter().position(|&h| h == name);
+    let mut required = [0; 3];
+    for (r, name) in required
+        .iter_mut()
+        .zip(["cell_id", "sequence_id", "sequence"])
+    {
+        *r = col(name).ok_or_else(|| {
+            format!("\nThe AIRR file {path} does not have the required column {name}.\n")
+        })?;
+    }
+    let [cell_id, sequence_id, sequence] = required;
+    let (productive, junction) = (col("productive"), col("junction"));
+    let (v_call, d_call, j_call, c_call) =
+        (col("v_call"), col("d_call"), col("j_call"), col("c_call"));
+    let umi_count = col("umi_count").or_else(|| col("duplicate_count"));
+    let read_count = col("consensus_count");
+
+    let mut contigs = Vec::<ContigAnnotation>::new();
+    let mut calls = HashMap::<String, AirrCalls>::new();
+    for (i, line) in lines.enumerate() {
+        if line.is_empty() {
+            continue;
+        }
+        let fields: Vec<&str> = line.split('\t').collect();
+        let get = |c: Option<usize>| {
+            c.and_then(|c| fields.get(c).copied())
+                .filter(|x| !x.is_empty())
+        };
+        let count = |c: Option<usize>, name: &str| -> Result<Option<usize>, String> {
+            get(c)
+                .map(|x| {
+                    x.parse::<usize>().map_err(|_| {
+                        format!(
+                            "\nIn the AIRR file {path}, line {}, {name} has the value {x}, \
+                             which is not a nonnegative integer.\n",
+                            i + 2
+                        )
+                    })
+                })
+                .transpose()
+        };
+        let (Some(barcode), Some(name), Some(seq)) = (
+            get(Some(cell_id)),
+            get(Some(sequence_id)),
+            get(Some(sequence)),
+        ) else {
+            return Err(format!(
+                "\nIn the AIRR file {path}, line {} is missing cell_id, sequence_id or \
+                 sequence.\n",
+                i + 2
+            ));
+        };
+        let umis = count(umi_count, "umi_count")?.unwrap_or(1);
+        let reads = count(read_count, "consensus_count")?.unwrap_or(umis);
+        let productive = get(productive).map_or(true, |x| {
+            matches!(x, "T" | "t" | "TRUE" | "True" | "true" | "1")
+        });
+        let gene = |c: Option<usize>| {
+            get(c)
+                .and_then(|x| x.split(',').next())
+                .map(|x| x.split('*').next().unwrap().to_string())
+        };
+        calls.insert(
+            name.to_string(),
+            AirrCalls {
+                v: gene(v_call),
+                d: gene(d_call),
+                j: gene(j_call),
+                c: gene(c_call),
+                junction: get(junction).map(str::to_ascii_uppercase),
+            },
+        );
+        let seq = seq.to_ascii_uppercase();
+        contigs.push(ContigAnnotation {
+            barcode: barcode.to_string(),
+            contig_name: name.to_string(),
+            quals: "I".repeat(seq.len()),
+            sequence: seq,
+            umi_count: umis,
+            read_count: reads,
+            productive: Some(productive),
+            high_confidence: Some(true),
+            is_cell: Some(true),
+            ..ContigAnnotation::default()
+        });
+    }
+
+    // Contigs for the same barcode must be adjacent.
+
+    contigs.sort_by(|a, b| a.barcode.cmp(&b.barcode));
+    Ok(AirrContigs { contigs, calls })
+}
+
+/// Apply the gene calls to contigs that have been annotated, and remove those whose junction
+/// disagrees with the computed CDR3.  Return warnings for calls that were used without
+/// realignment, and for calls that could not be used, see above.
+pub fn apply_airr_calls(
+    path: &str,
+    tigs: &mut Vec<TigData>,
+    calls: &HashMap<String, AirrCalls>,
+    refdata: &RefData,
+) -> Result<Vec<String>, String> {
+    let mut by_name = HashMap::<(&str, &str), Vec<usize>>::new();
+    for i in 0..refdata.refs.len() {
+        by_name
+            .entry((refdata.name[i].as_str(), refdata.segtype[i]))
+            .or_default()
+            .push(i);
+    }
+
+    // Resolve a call for a gene of the given segment type.  Return the called gene, if it
+    // should replace the current one.  Differing calls are recorded as (contig, annotated gene,
+    // called gene), in swapped if the called gene is used, and otherwise in unused.
+
+    let mut swapped = Vec::<(String, String, String)>::new();
+    let mut unused = Vec::<(String, String, String)>::new();
+    let mut resolve = |tig: &str,
+                       call: &Option<String>,
+                       segtype: &str,
+                       current: usize|
+     -> Result<Option<usize>, String> {
+        let Some(call) = call else {
+            return Ok(None);
+        };
+        let Some(ids) = by_name.get(&(call.as_str(), segtype)) else {
+            return Err(format!(
+                "\nThe AIRR input calls the gene {call}, which is not in the reference.  \
+                 Please supply the reference that was used to make the file, using REF.\n"
+            ));
+        };
+        if refdata.name[current] == *call {
+            return Ok(None);
+        }
+        let id = ids
+            .iter()
+            .copied()
+            .find(|&id| refdata.refs[id].len() == refdata.refs[current].len());
+        let change = (tig.to_string(), refdata.name[current].clone(), call.clone());
+        if id.is_some() {
+            swapped.push(change);
+        } else {
+            unused.push(change);
+        }
+        Ok(id)
+    };
+    let mut keep = vec![true; tigs.len()];
+    for (t, keep) in tigs.iter_mut().zip(keep.iter_mut()) {
+        let Some(c) = calls.get(&t.tigname) else {
+            continue;
+        };
+        if c.junction.as_ref().is_some_and(|j| *j != t.cdr3_dna) {
+            *keep = false;
+            continue;
+        }
+        let tig = &t.tigname;
+        if let Some(v) = resolve(tig, &c.v, "V", t.v_ref_id)? {
+            t.v_ref_id = v;
+            for a in &mut t.annv {
+                a.2 = v as i32;
+            }
+        }
+        if let Some(d) = t.d_ref_id {
+            t.d_ref_id = Some(resolve(tig, &c.d, "D", d)?.unwrap_or(d));
+        }
+        t.j_ref_id = resolve(tig, &c.j, "J", t.j_ref_id)?.unwrap_or(t.j_ref_id);
+        if let Some(cr) = t.c_ref_id {
+            t.c_ref_id = Some(resolve(tig, &c.c, "C", cr)?.unwrap_or(cr));
+        }
+    }
+    let to_delete: Vec<bool> = keep.iter().map(|&k| !k).collect();
+    erase_if(tigs, &to_delete);
+
+    // Report the differing calls.
+
+    let mut warnings = Vec::<String>::new();
+    if let Some((tig, found, call)) = swapped.first() {
+        warnings.push(format!(
+            "\nWarning: for {} gene calls in the AIRR file {path}, the called gene differs from \
+             the gene found\nby annotation, and was used in its place, without realigning the \
+             contig.  For example,\ncontig {tig} was annotated as {found}, but is called {call}.\n",
+            swapped.len()
+        ));
+    }
+    if let Some((tig, found, call)) = unused.first() {
+        warnings.push(format!(
+            "\nWarning: for {} gene calls in the AIRR file {path}, the called gene differs from \
+             the gene found\nby annotation, and was not used, because its reference sequence \
+             has a different length.\nFor example, contig {tig} was annotated as {found}, but \
+             is called {call}.\n",
+            unused.len()
+        ));
+    }
+    Ok(warnings)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{apply_airr_calls, read_airr, AirrCalls};
+    use enclone_core::defs::TigData;
+    use std::collections::HashMap;
+    use std::fs::{read_to_string, write};
+    use vdj_ann::refx::{make_vdj_ref_data_core, RefData};
+
+    #[test]
+    fn test_read_airr() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("airr.tsv");
+        let path = path.to_str().unwrap();
+        write(
+            path,
+            "cell_id\tsequence_id\tsequence\tproductive\tv_call\tjunction\tumi_count\n\
+             BBB-1\tBBB-1_contig_1\tacgt\tT\tIGHV3-23*01,IGHV3-30*01\ttgtgcg\t7\n\
+             AAA-1\tAAA-1_contig_1\tCCGG\tF\t\t\t\n",
+        )
+        .unwrap();
+        let airr = read_airr(path).unwrap();
+        let bcs: Vec<&str> = airr.contigs.iter().map(|c| c.barcode.as_str()).collect();
+        assert_eq!(bcs, ["AAA-1", "BBB-1"]);
+        let (a, b) = (&airr.contigs[0], &airr.contigs[1]);
+        assert_eq!(
+            (a.productive, a.umi_count, a.read_count),
+            (Some(false), 1, 1)
+        );
+        assert_eq!((b.sequence.as_str(), b.quals.as_str()), ("ACGT", "IIII"));
+        assert_eq!((b.umi_count, b.read_count), (7, 7));
+        let calls = &airr.calls["BBB-1_contig_1"];
+        assert_eq!(calls.v.as_deref(), Some("IGHV3-23"));
+        assert_eq!(calls.j, None);
+        assert_eq!(calls.junction.as_deref(), Some("TGTGCG"));
+
+        // Malformed files.
+
+        write(path, "cell_id\tsequence\nAAA-1\tACGT\n").unwrap();
+        assert!(read_airr(path).is_err_and(|e| e.contains("column sequence_id")));
+        write(
+            path,
+            "cell_id\tsequence_id\tsequence\tumi_count\nAAA-1\tx\tACGT\tmany\n",
+        )
+        .unwrap();
+        assert!(read_airr(path).is_err_and(|e| e.contains("line 2, umi_count")));
+    }
+
+    #[test]
+    fn test_apply_airr_calls() {
+        // The test reference, with a second V gene of the same length as IGHV3-23, and a third
+        // of a different length.
+
+        let mut refx =
+            read_to_string(concat!(env!("CARGO_MANIFEST_DIR"), "/tests/data/ref.fa")).unwrap();
+        let v = refx.lines().nth(1).unwrap().to_string();
+        for (id, name, seq) in [(8, "IGHV3-30", &v[..]), (9, "IGHV1-2", &v[3..])] {
+            refx += &format!(
+                ">{id}|{name}*01 synthetic|{name}|L-REGION+V-REGION|IG|IGH|None|00\n{seq}\n"
+            );
+        }
+        let mut refdata = RefData::new();
+        make_vdj_ref_data_core(&mut refdata, &refx, "", false, true, None);
+        let index = |name: &str| refdata.name.iter().position(|n| n == name).unwrap();
+        let tig = |name: &str| TigData {
+            tigname: name.to_string(),
+            cdr3_dna: "TGTGCG".to_string(),
+            v_ref_id: index("IGHV3-23"),
+            j_ref_id: index("IGHJ4"),
+            annv: vec![(0, 351, index("IGHV3-23") as i32, 0, 0)],
+            ..TigData::default()
+        };
+        let call = |v: &str, junction: &str| AirrCalls {
+            v: Some(v.to_string()),
+            j: Some("IGHJ4".to_string()),
+            junction: Some(junction.to_string()),
+            ..AirrCalls::default()
+        };
+        let calls = HashMap::from([
+            ("same".to_string(), call("IGHV3-23", "TGTGCG")),
+            ("swapped".to_string(), call("IGHV3-30", "TGTGCG")),
+            ("unused".to_string(), call("IGHV1-2", "TGTGCG")),
+            ("junction".to_string(), call("IGHV3-23", "TGTGCA")),
+        ]);
+        let mut tigs: Vec<TigData> = ["same", "swapped", "unused", "junction"]
+            .into_iter()
+            .map(tig)
+            .collect();
+        let warnings = apply_airr_calls("airr.tsv", &mut tigs, &calls, &refdata).unwrap();
+        let names: Vec<&str> = tigs.iter().map(|t| t.tigname.as_str()).collect();
+        assert_eq!(names, ["same", "swapped", "unused"]);
+        assert_eq!(tigs[0].v_ref_id, index("IGHV3-23"));
+        assert_eq!(tigs[1].v_ref_id, index("IGHV3-30"));
+        assert_eq!(tigs[1].annv[0].2, index("IGHV3-30") as i32);
+        assert_eq!(tigs[2].v_ref_id, index("IGHV3-23"));
+        assert_eq!(warnings.len(), 2);
+        assert!(warnings[0]
+            .contains("contig swapped was annotated as IGHV3-23, but is called IGHV3-30"));
+        assert!(warnings[1].contains(
+            "contig unused was annotated as IGHV3-23, but \
+             is called IGHV1-2"
+        ));
+
+        // A call naming a gene that is not in the reference is an error.
+
+        let calls = HashMap::from([("same".to_string(), call("IGHV9-99", "TGTGCG"))]);
+        assert!(apply_airr_calls("airr.tsv", &mut tigs, &calls, &refdata).is_err());
+    }
+}
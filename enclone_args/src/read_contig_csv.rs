@@ -0,0 +1,176 @@
+// Copyright (c) 2021 10X Genomics, Inc. All rights reserved.
+
+// Read contigs from filtered_contig_annotations.csv and all_contig.fasta, for cellranger runs
+// for which the contig annotations json file was not kept.  This is used if VDJ_FORMAT=csv is
+// specified.
+//
+// Each row of the csv file is converted to a contig annotation, as found in
+// contig_annotations.json, with the sequence taken from the fasta file, and these are then
+// processed by read_json like any other source.  The csv file does not contain the alignments,
+// so the contigs are always reannotated against the reference.  Quality scores are not
+// available, so every base is given quality 40.
+//
+// Fields that are used:
+// • barcode
+// • contig_id
+// • is_cell -- optional, default true, as the file is filtered
+// • high_confidence -- optional, default true
+// • productive -- optional, default true
+// • umis
+// • reads.
+
+use crate::vdj_source::ContigAnnotation;
+use std::collections::HashMap;
+use std::fs::read_to_string;
+use string_utils::parse_csv;
+
+// Read a fasta file, returning a map from record name to sequence.
+
+fn read_fasta(path: &str) -> Result<HashMap<String, String>, String> {
+    let text = read_to_string(path)
+        .map_err(|e| format!("\nUnable to read the fasta file {path}: {e}.\n"))?;
+    let mut seqs = HashMap::<String, String>::new();
+    let mut name = None;
+    for line in text.lines() {
+        if let Some(header) = line.strip_prefix('>') {
+            let n = header.split_whitespace().next().unwrap_or_default();
+            seqs.insert(n.to_string(), String::new());
+            name = Some(n.to_string());
+        } else if let Some(n) = &name {
+            seqs.get_mut(n).unwrap().push_str(line.trim());
+        } else if !line.trim().is_empty() {
+            return Err(format!(
+                "\nThe fasta file {path} has a sequence line before the first header line.\n"
+            ));
+        }
+    }
+    Ok(seqs)
+}
+
+/// Read the contigs, sorted by barcode.
+pub fn read_contig_csv(csv: &str, fasta: &str) -> Result<Vec<ContigAnnotation>, String> {
+    let seqs = read_fasta(fasta)?;
+    let text =
+        read_to_string(csv).map_err(|e| format!("\nUnable to read the file {csv}: {e}.\n"))?;
+    let mut lines = text.lines();
+    let header = parse_csv(lines.next().unwrap_or_default());
+    let col = |name: &str| header.iter().position(|h| h == name);
+    let mut required = [0; 4];
+    for (r, name) in required
+        .iter_mut()
+        .zip(["barcode", "contig_id", "umis", "reads"])
+    {
+        *r = col(name).ok_or_else(|| {
+            format!("\nThe file {csv} does not have the required column {name}.\n")
+        })?;
+    }
+    let [barcode, contig_id, umis, reads] = required;
+    let (is_cell, high_confidence, productive) =
+        (col("is_cell"), col("high_confidence"), col("productive"));
+    let mut contigs = Vec::<ContigAnnotation>::new();
+    for (i, line) in lines.enumerate() {
+        if line.is_empty() {
+            continue;
+        }
+        let fields = parse_csv(line);
+        let line_err = |what: &str| format!("\nIn the file {csv}, line {}, {what}.\n", i + 2);
+        let get = |c: usize| {
+            fields
+                .get(c)
+                .map(String::as_str)
+                .ok_or_else(|| line_err("there are too few fields"))
+        };
+        let flag = |c: Option<usize>| -> Result<bool, String> {
+            Ok(match c {
+                Some(c) => get(c)?.eq_ignore_ascii_case("true"),
+                None => true,
+            })
+        };
+        let count = |c: usize, name: &str| -> Result<usize, String> {
+            let x = get(c)?;
+            x.parse::<usize>()
+                .map_err(|_| line_err(&format!("{name} has the value {x}, which is not a count")))
+        };
+        let (bc, tig) = (get(barcode)?, get(contig_id)?);
+        let Some(seq) = seqs.get(tig) else {
+            return Err(format!(
+                "\nThe contig {tig}, which is in {csv}, is not in the fasta file {fasta}.\n"
+            ));
+        };
+        contigs.push(ContigAnnotation {
+            barcode: bc.to_string(),
+            contig_name: tig.to_string(),
+            sequence: seq.clone(),
+            quals: "I".repeat(seq.len()),
+            umi_count: count(umis, "umis")?,
+            read_count: count(reads, "reads")?,
+            is_cell: Some(flag(is_cell)?),
+            high_confidence: Some(flag(high_confidence)?),
+            productive: Some(flag(productive)?),
+            ..ContigAnnotation::default()
+        });
+    }
+
+    // Contigs for the same barcode must be adjacent.
+
+    contigs.sort_by(|a, b| a.barcode.cmp(&b.barcode));
+    Ok(contigs)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{read_contig_csv, read_fasta};
+    use std::fs::write;
+
+    #[test]
+    fn test_read_contig_csv() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = |name: &str| dir.path().join(name).to_str().unwrap().to_string();
+        let (csv, fasta) = (path("contigs.csv"), path("contigs.fasta"));
+        write(
+            &fasta,
+            ">BBB-1_contig_1 extra\nACGT\nTT\n>AAA-1_contig_1\nGGCC\n>AAA-1_contig_2\nCA\n",
+        )
+        .unwrap();
+        let seqs = read_fasta(&fasta).unwrap();
+        assert_eq!(seqs.len(), 3);
+        assert_eq!(seqs["BBB-1_contig_1"], "ACGTTT");
+        write(path("bad.fasta"), "ACGT\n>x\nACGT\n").unwrap();
+        assert!(read_fasta(&path("bad.fasta")).is_err());
+
+        // The rows are sorted by barcode, and the flags default to true.
+
+        write(
+            &csv,
+            "barcode,contig_id,productive,umis,reads\n\
+             BBB-1,BBB-1_contig_1,True,4,400\n\
+             AAA-1,AAA-1_contig_1,False,2,200\n",
+        )
+        .unwrap();
+        let contigs = read_contig_csv(&csv, &fasta).unwrap();
+        let names: Vec<&str> = contigs.iter().map(|c| c.contig_name.as_str()).collect();
+        assert_eq!(names, ["AAA-1_contig_1", "BBB-1_contig_1"]);
+        let (a, b) = (&contigs[0], &contigs[1]);
+        assert_eq!(
+            (a.umi_count, a.read_count, a.productive),
+            (2, 200, Some(false))
+        );
+        assert_eq!(
+            (b.sequence.as_str(), b.quals.as_str()),
+            ("ACGTTT", "IIIIII")
+        );
+        assert_eq!((b.is_cell, b.high_confidence), (Some(true), Some(true)));
+
+        // A contig that is not in the fasta file is an error.
+
+        write(
+            &csv,
+            "barcode,contig_id,umis,reads\nCCC-1,CCC-1_contig_1,1,10\n",
+        )
+        .unwrap();
+        assert!(read_contig_csv(&csv, &fasta)
+            .is_err_and(|e| e.contains("The contig CCC-1_contig_1, which is in")));
+        write(&csv, "barcode,contig_id,umis\n").unwrap();
+        assert!(read_contig_csv(&csv, &fasta).is_err_and(|e| e.contains("column reads")));
+    }
+}
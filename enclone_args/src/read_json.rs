@@ -1,6 +1,6 @@
 // Copyright (c) 2021 10X Genomics, Inc. All rights reserved.
 
-// Fields that are used in all_contig_annotations.json:
+// Fields that are used in all_contig_annotations.json, see also ContigAnnotation:
 // • barcode
 // • is_cell and is_asm_cell -- both are optional, but at least one needs to be present and
 //   true for a cell called by the VDJ pipeline
@@ -21,80 +21,97 @@
 // • cdr3_seq, unless in reannotate mode
 // • cdr3_start, unless in reannotate mode
 // • annotations, unless in reannotate mode.
+//
+// Contigs may instead be read from an AIRR file, see read_airr.rs, or from a contig csv file and
+// fasta file, see read_contig_csv.rs.
 
 use self::annotate::{annotate_seq, get_cdr3_using_ann, print_some_annotations};
 use self::refx::RefData;
 use self::transcript::is_valid;
+use crate::contig_parse_error::{
+    contig_parse_report, ContigParseError, ParseAdvice, MAX_CONTIG_PARSE_ERRORS,
+};
+use crate::read_airr::{apply_airr_calls, read_airr};
+use crate::read_contig_csv::read_contig_csv;
+use crate::vdj_source::{ContigAnnotation, VdjSource};
 use debruijn::dna_string::DnaString;
-use enclone_core::barcode_fate::BarcodeFate;
+use enclone_core::barcode_fate::{BarcodeFate, FateRecord};
 use enclone_core::defs::{EncloneControl, OriginInfo, TigData};
 use io_utils::{open_maybe_compressed, path_exists, read_vector_entry_from_json};
-use rand::Rng;
 use rayon::prelude::*;
-use serde_json::Value;
-use std::fmt::Write;
-use std::sync::atomic::{AtomicBool, Ordering};
 use std::{collections::HashMap, io::BufReader};
 use string_utils::{stringme, strme, TextUtils};
 use vdj_ann::{annotate, refx, transcript};
-use vector_utils::{bin_position, erase_if, unique_sort};
+use vector_utils::{bin_member, bin_position, erase_if, unique_sort};
 
 // ▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓
 
-fn json_error(
-    json: Option<&str>,
-    ctl: &EncloneControl,
-    exiting: &AtomicBool,
-    msg: &str,
-) -> Result<(), String> {
-    // The following line prevents error messages from this function from being
-    // printed multiple times.
-    let mut msgx = String::new();
-    if !exiting.swap(true, Ordering::Relaxed) {
-        msgx = "\nThere is something wrong with the contig annotations in the cellranger output \
-             file"
-            .to_string();
-        if json.is_some() {
-            write!(msgx, "\n{}.", json.unwrap()).unwrap();
-        } else {
-            msgx += ".";
-        }
-        if ctl.gen_opt.internal_run {
-            writeln!(msgx, "\n\npossibly relevant internal data: {msg}").unwrap();
-        }
-        if ctl.gen_opt.internal_run {
-            msgx += "\n\nATTENTION INTERNAL 10X USERS!\n\
-                Quite possibly you are using data from a cellranger run carried out using a \
-                version\n\
-                between 3.1 and 4.0.  For certain of these versions, it is necessary to add the\n\
-                argument CURRENT_REF to your command line.  If that doesn't work, \
-                please see below.\n";
-        }
-        msgx += "\n\nHere is what you should do:\n\n\
-             1. If you used cellranger version ≥ 4.0, the problem is very likely\n\
-                that the directory outs/vdj_reference was not retained, so enclone\n\
-                didn't see it, and had to guess what the reference sequence was.\n\
-                Fix this and everything should be fine.\n\n\
-             2. If you used cellranger version 3.1, then you need to add a command-line\n\
-                argument REF=<vdj_reference_fasta_file_name>, or if you already did that,\n\
-                make sure it is the *same* as that which you gave cellranger.\n\n\
-             3. If you used cellranger version < 3.1 (the only other possibility), then\n\
-                you have options:\n\
-                • rerun cellranger using the current version\n\
-                • or provide an argument REF= as above and RE to force reannotation\n\
-                • or provide the argument BUILT_IN to use the current reference and force\n  \
-                  reannotation (and MOUSE if you used mouse); only works with human and mouse.\n\n\
-             Note that one way to get the error is to specify TCR when you meant BCR, or the\n\
-             other way.\n\n\
-             If you're stuck, please write to us at enclone@10xgenomics.com.\n";
-    }
-    Err(msgx)
+// Advice for a kind of contig parse error, given once in the error report.
+
+fn advice_text(advice: ParseAdvice, e: &ContigParseError, ctl: &EncloneControl) -> String {
+    match advice {
+        ParseAdvice::CheckReference => {
+            let mut msg = format!(
+                "\nThere is something wrong with the contig annotations in the cellranger output \
+                 file\n{}.",
+                e.file
+            );
+            if ctl.gen_opt.internal_run {
+                msg += "\n\nATTENTION INTERNAL 10X USERS!\n\
+                    Quite possibly you are using data from a cellranger run carried out using a \
+                    version\n\
+                    between 3.1 and 4.0.  For certain of these versions, it is necessary to add the\n\
+                    argument CURRENT_REF to your command line.  If that doesn't work, \
+                    please see below.\n";
+            }
+            msg += "\n\nHere is what you should do:\n\n\
+                 1. If you used cellranger version ≥ 4.0, the problem is very likely\n\
+                    that the directory outs/vdj_reference was not retained, so enclone\n\
+                    didn't see it, and had to guess what the reference sequence was.\n\
+                    Fix this and everything should be fine.\n\n\
+                 2. If you used cellranger version 3.1, then you need to add a command-line\n\
+                    argument REF=<vdj_reference_fasta_file_name>, or if you already did that,\n\
+                    make sure it is the *same* as that which you gave cellranger.\n\n\
+                 3. If you used cellranger version < 3.1 (the only other possibility), then\n\
+                    you have options:\n\
+                    • rerun cellranger using the current version\n\
+                    • or provide an argument REF= as above and RE to force reannotation\n\
+                    • or provide the argument BUILT_IN to use the current reference and force\n  \
+                      reannotation (and MOUSE if you used mouse); only works with human and mouse.\n\n\
+                 Note that one way to get the error is to specify TCR when you meant BCR, or the\n\
+                 other way.\n\n\
+                 If you're stuck, please write to us at enclone@10xgenomics.com.\n";
+            msg
+        }
+        ParseAdvice::InconsistentReference => format!(
+            "\nThere is an inconsistency between the reference \
+             file used to create the Cell Ranger output files in\n{}\nand the \
+             reference that enclone is using.\n\n\
+             As far as we know, this type of error can only occur with Cell Ranger \
+             versions before 4.0.\n\n\
+             If this is mouse data, please use the argument MOUSE, and that may \
+             solve the problem.\n\n\
+             If this is human or mouse data, and you are OK with using the current \
+             built-in reference that\nenclone has, \
+             you can instead add the argument BUILT_IN to the command line.  This \
+             forces\nrecomputation of annotations and may be somewhat slower.\n\n\
+             A solution that should always work is to supply\n\
+             REF=vdj_reference_fasta_filename as an argument to enclone.\n",
+            e.file.rsplit_once('/').map_or(e.file.as_str(), |x| x.0),
+        ),
+        ParseAdvice::Reannotate => format!(
+            "\nThe file\n{}\ndoes not contain annotations.  To use enclone with it, \
+             please specify the argument BUILT_IN\nto force use of the internal \
+             reference and recompute annotations.\n",
+            e.file
+        ),
+    }
 }
 
 // ▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓
 
 fn parse_vector_entry_from_json(
-    x: &[u8],
+    v: &ContigAnnotation,
     json: &str,
     accept_inconsistent: bool,
     origin_info: &OriginInfo,
@@ -108,31 +125,22 @@ fn parse_vector_entry_from_json(
     gex_cells_specified: &mut bool,
     cr_version: &mut String,
     tigs: &mut Vec<TigData>,
-    exiting: &AtomicBool,
-) -> Result<(), String> {
-    let v: Value = match serde_json::from_slice(x) {
-        Err(_) => {
-            return Err(format!(
-                "\nInternal error, failed to parse a value from a string.  The string is:\n{}\n",
-                strme(x)
-            ));
-        }
-        Ok(v) => v,
-    };
-    let barcode = v["barcode"].to_string().between("\"", "\"").to_string();
+    record: usize,
+) -> Result<(), ContigParseError> {
+    let barcode = v.barcode.clone();
 
     // Get cell status.  Sometime after CR 4.0 was released, and before 4.1 was released,
     // we added new fields is_asm_cell and is_gex_cell to the json file.  The value of
     // is_asm_cell is the original determination of "cell" in the VDJ pipeline, whereas the
     // value of is_gex_cell is that for the GEX pipeline.
 
-    let mut is_cell = v["is_cell"].as_bool().unwrap_or(false);
-    let is_asm_cell = v["is_asm_cell"].as_bool().unwrap_or(false);
+    let mut is_cell = v.is_cell.unwrap_or(false);
+    let is_asm_cell = v.is_asm_cell.unwrap_or(false);
     if is_asm_cell {
         is_cell = true;
     }
 
-    let is_gex_cell = v["is_gex_cell"].as_bool();
+    let is_gex_cell = v.is_gex_cell;
     if is_gex_cell.is_some() {
         *gex_cells_specified = true;
     }
@@ -149,15 +157,23 @@ fn parse_vector_entry_from_json(
 
     // Proceed.
 
-    if !ctl.gen_opt.reprod && !v["productive"].as_bool().unwrap_or(false) {
+    if !ctl.gen_opt.reprod && !v.productive.unwrap_or(false) {
         return Ok(());
     }
-    if !ctl.gen_opt.reprod && !ctl.gen_opt.ncell && !v["high_confidence"].as_bool().unwrap_or(false)
-    {
+    if !ctl.gen_opt.reprod && !ctl.gen_opt.ncell && !v.high_confidence.unwrap_or(false) {
         return Ok(());
     }
-    let tigname = v["contig_name"].to_string().between("\"", "\"").to_string();
-    let full_seq = &v["sequence"].to_string().between("\"", "\"").to_string();
+    let tigname = v.contig_name.clone();
+
+    // Errors identify the contig, and the field if there is one.
+
+    let err = |problem: &str| {
+        ContigParseError::new(json, record, problem)
+            .barcode(&barcode)
+            .contig(&tigname)
+    };
+    let missing = |field: &str| err("the field is missing").field(field, "null");
+    let full_seq = &v.sequence;
     let mut left = false;
     let (mut v_ref_id, mut j_ref_id) = (1000000, 0);
     let mut d_ref_id: Option<usize> = None;
@@ -175,47 +191,15 @@ fn parse_vector_entry_from_json(
     let mut cdr3_aa: String;
     let mut cdr3_dna: String;
     let mut cdr3_start: usize;
-    if v.get("version").is_some() {
-        *cr_version = v["version"].to_string().between("\"", "\"").to_string();
-    }
-
-    // Read validated and non-validated UMIs.
-
-    let mut validated_umis = Vec::<String>::new();
-    let mut validated_umis_present = false;
-    let val = v["validated_umis"].as_array();
-    if let Some(val) = val {
-        validated_umis_present = true;
-        for vi in val {
-            validated_umis.push(vi.to_string().between("\"", "\"").to_string());
-        }
-    }
-    let mut non_validated_umis = Vec::<String>::new();
-    let mut non_validated_umis_present = false;
-    let non_val = v["non_validated_umis"].as_array();
-    if let Some(non_val) = non_val {
-        non_validated_umis_present = true;
-        for nv in non_val {
-            non_validated_umis.push(nv.to_string().between("\"", "\"").to_string());
-        }
-    }
-    let mut invalidated_umis = Vec::<String>::new();
-    let mut invalidated_umis_present = false;
-    let inval = v["invalidated_umis"].as_array();
-    if let Some(inval) = inval {
-        invalidated_umis_present = true;
-        for inv in inval {
-            invalidated_umis.push(inv.to_string().between("\"", "\"").to_string());
-        }
+    if let Some(version) = &v.version {
+        cr_version.clone_from(version);
     }
 
     // Read fraction_of_reads_for_this_barcode_provided_as_input_to_assembly.
 
-    let mut frac_reads_used = None;
-    let f = v["fraction_of_reads_for_this_barcode_provided_as_input_to_assembly"].as_f64();
-    if let Some(f) = f {
-        frac_reads_used = Some((f * 1_000_000.0).round() as u32);
-    }
+    let frac_reads_used = v
+        .fraction_of_reads_for_this_barcode_provided_as_input_to_assembly
+        .map(|f| (f * 1_000_000.0).round() as u32);
 
     // Reannotate.
 
@@ -311,10 +295,10 @@ fn parse_vector_entry_from_json(
                 if anni.3 == 0 {
                     tig_start = anni.0 as isize;
                     if tig_start > cdr3_start as isize {
-                        panic!(
-                            "Something is wrong with the CDR3 start for this contig:\n\n{}.",
-                            &full_seq
-                        );
+                        return Err(err(&format!(
+                            "the V segment starts at {tig_start}, after the CDR3, which starts \
+                             at {cdr3_start}"
+                        )));
                     }
                     cdr3_start -= tig_start as usize;
                 }
@@ -340,97 +324,71 @@ fn parse_vector_entry_from_json(
     } else {
         // Use annotations from json file.
 
-        cdr3_aa = v["cdr3"].to_string().between("\"", "\"").to_string();
-        cdr3_dna = v["cdr3_seq"].to_string().between("\"", "\"").to_string();
-        cdr3_start = v["cdr3_start"].as_u64().unwrap() as usize;
-        let ann = v["annotations"].as_array();
-        if ann.is_none() {
-            return Err(format!(
-                "\nThe file\n{json}\ndoes not contain annotations.  To use enclone with it, \
-                    please specify the argument BUILT_IN\nto force use of the internal \
-                    reference and recompute annotations.\n"
-            ));
-        }
-        let ann = ann.unwrap();
+        cdr3_aa = v.cdr3.clone().ok_or_else(|| missing("cdr3"))?;
+        cdr3_dna = v.cdr3_seq.clone().ok_or_else(|| missing("cdr3_seq"))?;
+        cdr3_start = v.cdr3_start.ok_or_else(|| missing("cdr3_start"))?;
+        let Some(ann) = &v.annotations else {
+            return Err(missing("annotations").advice(ParseAdvice::Reannotate));
+        };
         let mut cigarv = String::new(); // cigar for V segment
         for a in ann {
-            let region_type = &a["feature"]["region_type"];
-            let feature_id = a["feature"]["feature_id"].as_u64().unwrap() as usize;
+            let region_type = a.feature.region_type.as_str();
+            let feature_id = a.feature.feature_id;
             if !to_ref_index.contains_key(&feature_id) {
                 continue;
             }
             let feature_idx = to_ref_index[&feature_id];
-            let ref_start = a["annotation_match_start"].as_u64().unwrap() as usize;
+            let ref_start = a.annotation_match_start;
             if region_type == "L-REGION+V-REGION" {
-                v_stop = a["contig_match_end"].as_i64().unwrap() as usize;
-                v_stop_ref = a["annotation_match_end"].as_i64().unwrap() as usize;
+                v_stop = a.contig_match_end;
+                v_stop_ref = a.annotation_match_end;
             }
-            let gene_name = a["feature"]["gene_name"]
-                .to_string()
-                .between("\"", "\"")
-                .to_string();
-            if refdata.name[feature_idx] != gene_name
-                && !accept_inconsistent
-                && !exiting.swap(true, Ordering::Relaxed)
-            {
-                return Err(format!(
-                    "\nThere is an inconsistency between the reference \
-                     file used to create the Cell Ranger output files in\n{}\nand the \
-                     reference that enclone is using.\n\nFor example, the feature \
-                     numbered {} is\nthe gene {} in one and the gene {} in the other.\n\n\
-                     As far as we know, this type of error can only occur with Cell Ranger \
-                     versions before 4.0.\n\n\
-                     If this is mouse data, please use the argument MOUSE, and that may \
-                     solve the problem.\n\n\
-                     If this is human or mouse data, and you are OK with using the current \
-                     built-in reference that\nenclone has, \
-                     you can instead add the argument BUILT_IN to the command line.  This \
-                     forces\nrecomputation of annotations and may be somewhat slower.\n\n\
-                     A solution that should always work is to supply\n\
-                     REF=vdj_reference_fasta_filename as an argument to enclone.\n",
-                    json.rev_before("/"),
-                    feature_id,
-                    gene_name,
+            let gene_name = &a.feature.gene_name;
+            if refdata.name[feature_idx] != *gene_name && !accept_inconsistent {
+                return Err(err(&format!(
+                    "feature {feature_id} is {} in the reference",
                     refdata.name[feature_idx]
-                ));
+                ))
+                .field("feature.gene_name", gene_name)
+                .advice(ParseAdvice::InconsistentReference));
             }
             if region_type == "L-REGION+V-REGION" && ref_start == 0 {
-                let chain = a["feature"]["chain"]
-                    .to_string()
-                    .between("\"", "\"")
-                    .to_string();
+                let chain = &a.feature.chain;
                 // if !chain.starts_with("IG") { continue; } // *******************
-                tig_start = a["contig_match_start"].as_i64().unwrap() as isize;
-                cdr3_start -= tig_start as usize;
+                let start = a.contig_match_start;
+                if start > cdr3_start {
+                    return Err(
+                        err(&format!("the CDR3 starts at {cdr3_start}, before the V"))
+                            .field("contig_match_start", start)
+                            .advice(ParseAdvice::CheckReference),
+                    );
+                }
+                tig_start = start as isize;
+                cdr3_start -= start;
                 chain_type = chain.clone();
-                if chain == *"IGH"
-                    || chain == *"TRB"
-                    || (chain == *"TRD" && ctl.gen_opt.gamma_delta)
-                {
+                if chain == "IGH" || chain == "TRB" || (chain == "TRD" && ctl.gen_opt.gamma_delta) {
                     left = true;
                 }
                 v_ref_id = feature_idx;
-                cigarv = a["cigar"].to_string().between("\"", "\"").to_string();
+                cigarv = a.cigar.clone();
             } else {
                 // also check for IG chain?????????????????????????????????????????
-                let ref_stop = a["annotation_match_end"].as_u64().unwrap() as usize;
-                let ref_len = a["annotation_length"].as_u64().unwrap() as usize;
-                if region_type == "J-REGION" && ref_stop == ref_len {
-                    tig_stop = a["contig_match_end"].as_i64().unwrap() as isize;
+                if region_type == "J-REGION" && a.annotation_match_end == a.annotation_length {
+                    tig_stop = a.contig_match_end as isize;
                     j_ref_id = feature_idx;
-                    j_start = a["contig_match_start"].as_i64().unwrap() as usize;
-                    j_start_ref = a["annotation_match_start"].as_i64().unwrap() as usize;
+                    j_start = a.contig_match_start;
+                    j_start_ref = ref_start;
                 }
                 if region_type == "5'UTR" {
                     u_ref_id = Some(feature_idx);
                 }
                 if region_type == "D-REGION" {
-                    d_start = Some(a["contig_match_start"].as_i64().unwrap() as usize);
+                    d_start = Some(a.contig_match_start);
                     d_ref_id = Some(feature_idx);
                 }
                 if region_type == "C-REGION" {
                     c_ref_id = Some(feature_idx);
-                    c_start = Some(a["contig_match_start"].as_i64().unwrap() as usize);
+                    c_start = Some(a.contig_match_start);
                 }
             }
         }
@@ -482,8 +440,13 @@ fn parse_vector_entry_from_json(
         }
         let rt = &refdata.refs[v_ref_id];
         if annv.len() == 2 && annv[0].1 as usize > rt.len() {
-            let msg = format!("annv[0].1 = {}, rt.len() = {}", annv[0].1, rt.len());
-            json_error(None, ctl, exiting, &msg)?;
+            return Err(err(&format!(
+                "the V alignment has length {}, longer than the reference V, of length {}",
+                annv[0].1,
+                rt.len()
+            ))
+            .field("cigar", cigarv)
+            .advice(ParseAdvice::CheckReference));
         }
 
         // Check to see if the CDR3 sequence has changed.  This could happen if the cellranger
@@ -534,33 +497,35 @@ fn parse_vector_entry_from_json(
     // Keep going.
 
     if tig_start < 0 || tig_stop < 0 {
-        let msg = format!("tig_start = {tig_start}, tig_stop = {tig_stop}");
-        json_error(Some(json), ctl, exiting, &msg)?;
+        return Err(err(&format!(
+            "no full V and J were found, V start = {tig_start}, J stop = {tig_stop}"
+        ))
+        .advice(ParseAdvice::CheckReference));
     }
     let (tig_start, tig_stop) = (tig_start as usize, tig_stop as usize);
-    let quals0 = v["quals"].to_string();
-    let quals0 = quals0.after("\"").as_bytes();
-    let mut quals = Vec::<u8>::new();
-    let mut slashed = false;
-    for &qual in quals0.iter().take(quals0.len() - 1) {
-        if !slashed && qual == b'\\'
-        /* && ( i == 0 || quals0[i-1] != b'\\' ) */
-        {
-            slashed = true;
-            continue;
-        }
-        slashed = false;
-        quals.push(qual);
+    if tig_stop > full_seq.len() {
+        return Err(err(&format!(
+            "the J ends at {tig_stop}, past the end of the contig, of length {}",
+            full_seq.len()
+        ))
+        .advice(ParseAdvice::CheckReference));
+    }
+    let quals = &v.quals;
+    if quals.len() != full_seq.len() || quals.bytes().any(|q| q < 33) {
+        return Err(err(&format!(
+            "expected {} quality scores, each at least 33",
+            full_seq.len()
+        ))
+        .field("quals", quals));
     }
-    assert_eq!(full_seq.len(), quals.len());
+    let mut quals = quals.clone().into_bytes();
     let seq = &full_seq[tig_start..tig_stop].to_string();
     for qual in quals.iter_mut() {
         *qual -= 33_u8;
     }
     let full_quals = quals;
     let quals = full_quals[tig_start..tig_stop].to_vec();
-    let umi_count = v["umi_count"].as_i64().unwrap() as usize;
-    let read_count = v["read_count"].as_i64().unwrap() as usize;
+    let (umi_count, read_count) = (v.umi_count, v.read_count);
     let origin = origin_info.origin_for_bc[li].get(&barcode).or_else(|| {
         // the way we use s1 here is flaky
         if !origin_info.origin_id[li].is_empty()
@@ -594,18 +559,6 @@ fn parse_vector_entry_from_json(
     if let Some(tag) = tag {
         tag_index = Some(bin_position(&origin_info.tag_list, tag) as usize);
     }
-    let mut valu = None;
-    if validated_umis_present {
-        valu = Some(validated_umis);
-    }
-    let mut non_valu = None;
-    if non_validated_umis_present {
-        non_valu = Some(non_validated_umis);
-    }
-    let mut invalu = None;
-    if invalidated_umis_present {
-        invalu = Some(invalidated_umis);
-    }
     tigs.push(TigData {
         cdr3_dna,
         len: seq.len(),
@@ -643,9 +596,9 @@ fn parse_vector_entry_from_json(
         read_count,
         chain_type,
         annv,
-        validated_umis: valu,
-        non_validated_umis: non_valu,
-        invalidated_umis: invalu,
+        validated_umis: v.validated_umis.clone(),
+        non_validated_umis: v.non_validated_umis.clone(),
+        invalidated_umis: v.invalidated_umis.clone(),
         frac_reads_used,
     });
     Ok(())
@@ -653,6 +606,91 @@ fn parse_vector_entry_from_json(
 
 // ▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓
 
+// Read the entries in a contig annotations json file, which may be lz4-compressed.
+
+fn read_json_entries(json: &str) -> Result<Vec<Vec<u8>>, String> {
+    let mut jsonx = json.to_string();
+    if !path_exists(json) {
+        jsonx = format!("{json}.lz4");
+    }
+    if jsonx.contains('/') {
+        let p = jsonx.rev_before("/");
+        if !path_exists(p) {
+            return Err(format!(
+                "\nThere should be a directory\n\
+                 \"{p}\"\n\
+                 but it does not exist.  Please check how you have specified the\n\
+                 input files to enclone, including the PRE argument.\n"
+            ));
+        }
+    }
+    if !path_exists(&jsonx) {
+        return Err(format!(
+            "\nThe path\n\
+             \"{jsonx}\"\n\
+             does not exist.  Please check how you have specified the\n\
+             input files to enclone, including the PRE argument.\n"
+        ));
+    }
+    let mut f = BufReader::new(open_maybe_compressed(&jsonx));
+    // ◼ This loop could be speeded up, see comments below.
+    let mut xs = Vec::<Vec<u8>>::new();
+    loop {
+        let x = read_vector_entry_from_json(&mut f)
+            .map_err(|e| format!("\nProblem reading {jsonx}, record {}: {e}\n", xs.len() + 1))?;
+        match x {
+            None => break,
+            Some(x) => {
+                xs.push(x);
+            }
+        }
+    }
+    Ok(xs)
+}
+
+// ▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓
+
+// The error for a record that is not a valid contig annotation.  The serde error does not name
+// the field, so if the record is a JSON object, the field is found by removing each field in
+// turn: removing the bad field leaves either a valid record or one that is only missing that
+// field.
+
+fn contig_annotation_error(
+    json: &str,
+    record: usize,
+    x: &[u8],
+    e: &serde_json::Error,
+) -> ContigParseError {
+    let mut err = ContigParseError::new(
+        json,
+        record,
+        format!("the record is not a valid contig annotation: {e}"),
+    );
+    let Ok(serde_json::Value::Object(fields)) = serde_json::from_slice(x) else {
+        return err;
+    };
+    if let Some(barcode) = fields.get("barcode").and_then(|v| v.as_str()) {
+        err = err.barcode(barcode);
+    }
+    if let Some(contig_name) = fields.get("contig_name").and_then(|v| v.as_str()) {
+        err = err.contig(contig_name);
+    }
+    if e.to_string().starts_with("missing field") {
+        return err;
+    }
+    for (key, value) in fields.iter() {
+        let mut rest = fields.clone();
+        rest.remove(key);
+        match serde_json::from_value::<ContigAnnotation>(serde_json::Value::Object(rest)) {
+            Err(e) if !e.to_string().starts_with("missing field") => {}
+            _ => return err.field(key, value),
+        }
+    }
+    err
+}
+
+// ▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓
+
 // Parse the JSON annotations file.
 //
 // In the future could be converted to LazyWrite:
@@ -680,7 +718,7 @@ pub fn read_json(
     accept_inconsistent: bool,
     origin_info: &OriginInfo,
     li: usize,
-    json: &String,
+    source: &VdjSource,
     refdata: &RefData,
     to_ref_index: &HashMap<usize, usize>,
     reannotate: bool,
@@ -689,48 +727,30 @@ pub fn read_json(
     vdj_cells: &mut Vec<String>,
     gex_cells: &mut Vec<String>,
     gex_cells_specified: &mut bool,
+    too_many_contigs: &mut Vec<String>,
 ) -> Result<Vec<Vec<TigData>>, String> {
     *gex_cells_specified = false;
     let mut tigs = Vec::<TigData>::new();
-    let mut jsonx = json.clone();
-    if !path_exists(json) {
-        jsonx = format!("{json}.lz4");
-    }
-    if jsonx.contains('/') {
-        let p = jsonx.rev_before("/");
-        if !path_exists(p) {
-            return Err(format!(
-                "\nThere should be a directory\n\
-                 \"{p}\"\n\
-                 but it does not exist.  Please check how you have specified the\n\
-                 input files to enclone, including the PRE argument.\n"
-            ));
-        }
-    }
-    if !path_exists(&jsonx) {
-        return Err(format!(
-            "\nThe path\n\
-             \"{jsonx}\"\n\
-             does not exist.  Please check how you have specified the\n\
-             input files to enclone, including the PRE argument.\n"
-        ));
-    }
-    let mut f = BufReader::new(open_maybe_compressed(&jsonx));
-    // ◼ This loop could be speeded up, see comments above.
-    let mut xs = Vec::<Vec<u8>>::new();
-    loop {
-        let x = read_vector_entry_from_json(&mut f);
-        if x.is_err() {
-            eprintln!("\nProblem reading {jsonx}.\n");
-            return Err(x.err().unwrap());
-        }
-        match x.unwrap() {
-            None => break,
-            Some(x) => {
-                xs.push(x);
-            }
-        }
-    }
+    let json = source.name();
+    let xs = match source {
+        VdjSource::File(json) => read_json_entries(json)?,
+        _ => Vec::new(),
+    };
+    let airr = match source {
+        VdjSource::Airr(path) => Some(read_airr(path)?),
+        _ => None,
+    };
+    let csv_contigs = match source {
+        VdjSource::Csv { csv, fasta } => read_contig_csv(csv, fasta)?,
+        _ => Vec::new(),
+    };
+    let reannotate = reannotate || matches!(source, VdjSource::Airr(_) | VdjSource::Csv { .. });
+    let n = match source {
+        VdjSource::File(_) => xs.len(),
+        VdjSource::Memory { contigs, .. } => contigs.len(),
+        VdjSource::Airr(_) => airr.as_ref().unwrap().contigs.len(),
+        VdjSource::Csv { .. } => csv_contigs.len(),
+    };
     let mut results = Vec::<(
         usize,
         Vec<String>,
@@ -738,9 +758,9 @@ pub fn read_json(
         bool,
         String,
         Vec<TigData>,
-        String,
+        Option<ContigParseError>,
     )>::new();
-    for i in 0..xs.len() {
+    for i in 0..n {
         results.push((
             i,
             Vec::<String>::new(),
@@ -748,14 +768,33 @@ pub fn read_json(
             false,
             String::new(),
             Vec::<TigData>::new(),
-            String::new(),
+            None,
         ));
     }
-    let exiting = AtomicBool::new(false);
+
+    // Parse the records, collecting errors.  All records are parsed, so that the errors reported,
+    // which are the first in record order, do not depend on thread scheduling.
+
     results.par_iter_mut().for_each(|res| {
         let i = res.0;
+        let parsed: ContigAnnotation;
+        let v = match source {
+            VdjSource::File(_) => match serde_json::from_slice(&xs[i]) {
+                Err(e) => {
+                    res.6 = Some(contig_annotation_error(json, i, &xs[i], &e));
+                    return;
+                }
+                Ok(v) => {
+                    parsed = v;
+                    &parsed
+                }
+            },
+            VdjSource::Memory { contigs, .. } => &contigs[i],
+            VdjSource::Airr(_) => &airr.as_ref().unwrap().contigs[i],
+            VdjSource::Csv { .. } => &csv_contigs[i],
+        };
         let resx = parse_vector_entry_from_json(
-            &xs[i],
+            v,
             json,
             accept_inconsistent,
             origin_info,
@@ -769,18 +808,21 @@ pub fn read_json(
             &mut res.3,
             &mut res.4,
             &mut res.5,
-            &exiting,
+            i,
         );
         if let Err(resx) = resx {
-            res.6 = resx;
+            res.6 = Some(resx);
         }
     });
-    for result in &results {
-        if !result.6.is_empty() {
-            return Err(result.6.clone());
-        }
+    let mut errors: Vec<ContigParseError> = results.iter_mut().filter_map(|r| r.6.take()).collect();
+    if !errors.is_empty() {
+        let total = errors.len();
+        errors.truncate(MAX_CONTIG_PARSE_ERRORS);
+        return Err(contig_parse_report(&errors, total, |a, e| {
+            advice_text(a, e, ctl)
+        }));
     }
-    for result in results.iter_mut().take(xs.len()) {
+    for result in results.iter_mut().take(n) {
         vdj_cells.append(&mut result.1);
         gex_cells.append(&mut result.2);
         if result.3 {
@@ -791,6 +833,13 @@ pub fn read_json(
         }
         tigs.append(&mut result.5);
     }
+    if let Some(airr) = &airr {
+        for warning in apply_airr_calls(json, &mut tigs, &airr.calls, refdata)? {
+            if !ctl.gen_opt.nwarn {
+                eprint!("{warning}");
+            }
+        }
+    }
     unique_sort(gex_cells);
     let mut tig_bc = Vec::<Vec<TigData>>::new();
     let mut r = 0;
@@ -803,29 +852,32 @@ pub fn read_json(
             s += 1;
         }
 
-        // For now we require at most four contigs (but we don't yet merge foursies).
+        // For now we require at most four contigs (but we don't yet merge foursies).  Barcodes
+        // with more are reported, so that their fate can be recorded.
 
         if s - r <= 4 || ctl.clono_filt_opt_def.nmax {
             let mut bc_tigs = tigs[r..s].to_vec();
             bc_tigs.sort();
             tig_bc.push(bc_tigs);
+        } else {
+            too_many_contigs.push(tigs[r].barcode.clone());
         }
         r = s;
     }
     unique_sort(vdj_cells);
+    unique_sort(too_many_contigs);
 
-    // Subsample.
+    // Subsample.  Whether a barcode is kept depends only on the seed and the barcode, so that
+    // runs with the same seed keep the same barcodes.
 
     if ctl.gen_opt.subsample >= 0.0 {
-        let mut rng = rand::thread_rng();
         let mut to_delete1 = vec![false; tig_bc.len()];
         let mut to_delete2 = vec![false; vdj_cells.len()];
         let mut to_delete3 = vec![false; gex_cells.len()];
         for (bc, del) in tig_bc.iter().zip(to_delete1.iter_mut()) {
-            let y: f64 = rng.gen();
-            if y < 1.0 - ctl.gen_opt.subsample {
+            let bc = &bc[0].barcode;
+            if subsample_draw(ctl.gen_opt.subsample_seed as u64, bc) >= ctl.gen_opt.subsample {
                 *del = true;
-                let bc = &bc[0].barcode;
                 let p = bin_position(vdj_cells, bc);
                 if p >= 0 {
                     to_delete2[p as usize] = true;
@@ -848,17 +900,36 @@ pub fn read_json(
 
 // ▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓
 
+// Map a seed and barcode to a number in [0,1), for SUBSAMPLE.  This uses FNV-1a followed by the
+// splitmix64 finalizer, rather than the standard library hasher, whose output is not guaranteed
+// to be stable across Rust versions.
+
+fn subsample_draw(seed: u64, barcode: &str) -> f64 {
+    let mut h: u64 = 0xcbf29ce484222325;
+    for &b in seed.to_le_bytes().iter().chain(barcode.as_bytes()) {
+        h ^= u64::from(b);
+        h = h.wrapping_mul(0x100000001b3);
+    }
+    h = (h ^ (h >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+    h = (h ^ (h >> 27)).wrapping_mul(0x94d049bb133111eb);
+    h ^= h >> 31;
+    (h >> 11) as f64 / (1u64 << 53) as f64
+}
+
+// ▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓
+
 // Parse the JSON annotations file(s).
 
 pub fn parse_json_annotations_files(
     ctl: &EncloneControl,
+    sources: &[VdjSource],
     tig_bc: &mut Vec<Vec<TigData>>,
     refdata: &RefData,
     to_ref_index: &HashMap<usize, usize>,
     vdj_cells: &mut Vec<Vec<String>>,
     gex_cells: &mut Vec<Vec<String>>,
     gex_cells_specified: &mut Vec<bool>,
-    fate: &mut [HashMap<String, BarcodeFate>],
+    fate: &mut [HashMap<String, FateRecord>],
 ) -> Result<(), String> {
     // (origin index, contig name, V..J length): (?)
     let mut results = Vec::<(
@@ -871,6 +942,7 @@ pub fn parse_json_annotations_files(
         Vec<String>,
         bool,
         String,
+        Vec<String>,
     )>::new();
     for i in 0..ctl.origin_info.dataset_path.len() {
         results.push((
@@ -883,27 +955,31 @@ pub fn parse_json_annotations_files(
             Vec::<String>::new(),
             false,
             String::new(),
+            Vec::<String>::new(),
+        ));
+    }
+    if sources.len() != results.len() {
+        return Err(format!(
+            "\nThere are {} datasets but contig annotations were provided for {}.\n",
+            results.len(),
+            sources.len()
         ));
     }
     // Note: only tracking truncated seq and quals initially
-    let ann = if !ctl.gen_opt.cellranger {
-        "all_contig_annotations.json"
-    } else {
-        "contig_annotations.json"
-    };
     results.par_iter_mut().for_each(|res| {
         let li = res.0;
-        let json = format!("{}/{ann}", ctl.origin_info.dataset_path[li]);
-        let json_lz4 = format!("{}/{ann}.lz4", ctl.origin_info.dataset_path[li]);
-        if !path_exists(&json) && !path_exists(&json_lz4) {
-            res.8 = format!("\ncan't find {json} or {json_lz4}\n");
-            return;
+        if let VdjSource::File(json) = &sources[li] {
+            let json_lz4 = format!("{json}.lz4");
+            if !path_exists(json) && !path_exists(&json_lz4) {
+                res.8 = format!("\ncan't find {json} or {json_lz4}\n");
+                return;
+            }
         }
         let resx = read_json(
             ctl.gen_opt.accept_inconsistent,
             &ctl.origin_info,
             li,
-            &json,
+            &sources[li],
             refdata,
             to_ref_index,
             ctl.gen_opt.reannotate,
@@ -912,6 +988,7 @@ pub fn parse_json_annotations_files(
             &mut res.5,
             &mut res.6,
             &mut res.7,
+            &mut res.9,
         );
         if let Ok(resx) = resx {
             let tig_bc: Vec<Vec<TigData>> = resx;
@@ -950,7 +1027,12 @@ pub fn parse_json_annotations_files(
         }
         for j in 0..found.len() {
             if !found[j] {
-                fate[i].insert(cells[j].clone(), BarcodeFate::NonProductive);
+                let f = if bin_member(&results[i].9, &cells[j]) {
+                    BarcodeFate::TooManyContigs
+                } else {
+                    BarcodeFate::NonProductive
+                };
+                fate[i].insert(cells[j].clone(), f.into());
             }
         }
     }
@@ -972,3 +1054,151 @@ pub fn parse_json_annotations_files(
     */
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::{read_json, subsample_draw};
+    use crate::vdj_source::{ContigAnnotation, VdjSource};
+    use enclone_core::defs::{EncloneControl, TigData};
+    use std::collections::HashMap;
+    use std::fs::read_to_string;
+    use vdj_ann::refx::{make_vdj_ref_data_core, RefData};
+
+    const DATA: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/data");
+
+    // Read the test contigs from the given source.
+
+    fn read(ctl: &EncloneControl, source: &VdjSource) -> Vec<Vec<TigData>> {
+        try_read(ctl, source).unwrap()
+    }
+
+    fn try_read(ctl: &EncloneControl, source: &VdjSource) -> Result<Vec<Vec<TigData>>, String> {
+        let mut refdata = RefData::new();
+        let refx = read_to_string(format!("{DATA}/ref.fa")).unwrap();
+        make_vdj_ref_data_core(&mut refdata, &refx, "", false, true, None);
+        let to_ref_index: HashMap<usize, usize> = refdata
+            .id
+            .iter()
+            .take(refdata.refs.len())
+            .enumerate()
+            .map(|(i, &id)| (id as usize, i))
+            .collect();
+        read_json(
+            false,
+            &ctl.origin_info,
+            0,
+            source,
+            &refdata,
+            &to_ref_index,
+            false,
+            &mut String::new(),
+            ctl,
+            &mut Vec::new(),
+            &mut Vec::new(),
+            &mut false,
+            &mut Vec::new(),
+        )
+    }
+
+    fn control() -> EncloneControl {
+        let mut ctl = EncloneControl::default();
+        ctl.gen_opt.cellranger = true;
+        ctl.gen_opt.subsample = -1.0;
+        ctl.origin_info.origin_id = vec!["s1".to_string()];
+        ctl.origin_info.donor_id = vec!["d1".to_string()];
+        ctl.origin_info.origin_list = ctl.origin_info.origin_id.clone();
+        ctl.origin_info.donor_list = ctl.origin_info.donor_id.clone();
+        ctl.origin_info.origin_for_bc = vec![HashMap::new()];
+        ctl.origin_info.donor_for_bc = vec![HashMap::new()];
+        ctl.origin_info.tag = vec![HashMap::new()];
+        ctl
+    }
+
+    fn contigs() -> Vec<ContigAnnotation> {
+        let json = read_to_string(format!("{DATA}/contig_annotations.json")).unwrap();
+        serde_json::from_str(&json).unwrap()
+    }
+
+    #[test]
+    fn test_memory_source() {
+        let ctl = control();
+        let from_file = read(
+            &ctl,
+            &VdjSource::File(format!("{DATA}/contig_annotations.json")),
+        );
+        let from_memory = read(
+            &ctl,
+            &VdjSource::Memory {
+                name: "memory".to_string(),
+                contigs: contigs(),
+            },
+        );
+        assert_eq!(from_file.len(), 4);
+        assert!(from_file.iter().all(|tigs| tigs.len() == 2));
+        assert!(from_file == from_memory);
+    }
+
+    #[test]
+    fn test_subsample() {
+        // The draws do not change from run to run, or with the Rust version.
+
+        let bc = "AAACCTGAGCGATAGC-1";
+        let scale = (1u64 << 53) as f64;
+        assert_eq!(subsample_draw(0, bc), 8771983984030712.0 / scale);
+        assert_eq!(subsample_draw(1, bc), 4824396459431721.0 / scale);
+
+        // The barcodes kept do not depend on the order of the contigs.
+
+        let mut ctl = control();
+        ctl.gen_opt.subsample = 0.5;
+        ctl.gen_opt.subsample_seed = 0;
+        let barcodes = |contigs: Vec<ContigAnnotation>| -> Vec<String> {
+            let source = VdjSource::Memory {
+                name: "memory".to_string(),
+                contigs,
+            };
+            let mut bcs: Vec<String> = read(&ctl, &source)
+                .iter()
+                .map(|tigs| tigs[0].barcode.clone())
+                .collect();
+            bcs.sort();
+            bcs
+        };
+        let forward = barcodes(contigs());
+        let mut reversed = contigs();
+        reversed.reverse();
+        assert_eq!(barcodes(reversed), forward);
+        assert_eq!(forward, ["ACGCCAGTCTCGCATC-1", "TTGCCGTCATGACGGA-1"]);
+    }
+
+    #[test]
+    fn test_bad_record() {
+        // Records whose umi_count is not a number, or whose barcode is not a string, are reported
+        // by field.
+
+        let json = read_to_string(format!("{DATA}/contig_annotations.json")).unwrap();
+        let mut records: Vec<serde_json::Value> = serde_json::from_str(&json).unwrap();
+        records[1]["umi_count"] = serde_json::json!("many");
+        records[4]["barcode"] = serde_json::json!(7);
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("contig_annotations.json");
+        std::fs::write(&path, serde_json::to_string_pretty(&records).unwrap()).unwrap();
+        let path = path.to_str().unwrap().to_string();
+        let report = try_read(&control(), &VdjSource::File(path.clone())).unwrap_err();
+        let lines: Vec<&str> = report.lines().filter(|l| l.starts_with("• ")).collect();
+        assert!(report.starts_with("\nFound 2 bad contig record(s):\n\n"));
+        assert_eq!(lines.len(), 2);
+        let (barcode, contig) = (&records[1]["barcode"], &records[1]["contig_name"]);
+        assert!(lines[0].starts_with(&format!(
+            "• {path}, record 2, barcode {}, contig {}, field umi_count = \"many\": \
+             the record is not a valid contig annotation: invalid type: string \"many\"",
+            barcode.as_str().unwrap(),
+            contig.as_str().unwrap(),
+        )));
+        assert!(lines[1].starts_with(&format!(
+            "• {path}, record 5, contig {}, field barcode = 7: \
+             the record is not a valid contig annotation: invalid type: integer `7`",
+            records[4]["contig_name"].as_str().unwrap(),
+        )));
+    }
+}
@@ -0,0 +1,116 @@
+// Copyright (c) 2021 10X Genomics, Inc. All rights reserved.
+
+// Sources of contig annotations.  Normally these are read from the json file in each dataset
+// directory, but a library caller that already has the annotations in memory may pass them
+// directly, avoiding a round trip through the filesystem.
+
+use enclone_core::defs::EncloneControl;
+use serde::{Deserialize, Serialize};
+
+/// One entry of a contig annotations json file, having the fields that enclone uses, see
+/// read_json.rs.  Other fields are ignored.  The flags are optional, and a flag that is absent
+/// counts as false.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct ContigAnnotation {
+    pub barcode: String,
+    pub contig_name: String,
+    pub sequence: String,
+    /// Quality scores, as in a fastq file.
+    pub quals: String,
+    pub umi_count: usize,
+    pub read_count: usize,
+    pub is_cell: Option<bool>,
+    pub is_asm_cell: Option<bool>,
+    pub is_gex_cell: Option<bool>,
+    pub productive: Option<bool>,
+    pub high_confidence: Option<bool>,
+    /// The cellranger version.
+    pub version: Option<String>,
+    pub validated_umis: Option<Vec<String>>,
+    pub non_validated_umis: Option<Vec<String>>,
+    pub invalidated_umis: Option<Vec<String>>,
+    pub fraction_of_reads_for_this_barcode_provided_as_input_to_assembly: Option<f64>,
+    /// The CDR3 and the annotations are not needed if the contigs are reannotated.
+    pub cdr3: Option<String>,
+    pub cdr3_seq: Option<String>,
+    pub cdr3_start: Option<usize>,
+    pub annotations: Option<Vec<AnnotationRegion>>,
+}
+
+/// The alignment of a reference segment to a contig.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AnnotationRegion {
+    pub feature: AnnotationFeature,
+    pub contig_match_start: usize,
+    pub contig_match_end: usize,
+    pub annotation_match_start: usize,
+    pub annotation_match_end: usize,
+    pub annotation_length: usize,
+    pub cigar: String,
+}
+
+/// A reference segment.  The feature id and gene name are those in the reference fasta file.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AnnotationFeature {
+    pub feature_id: usize,
+    pub gene_name: String,
+    /// For example L-REGION+V-REGION or J-REGION.
+    pub region_type: String,
+    /// For example IGH.
+    pub chain: String,
+}
+
+/// Where the contig annotations for one dataset come from.
+pub enum VdjSource {
+    /// A contig annotations json file.  If it does not exist, the same path with .lz4 appended
+    /// is used.
+    File(String),
+    /// Contig annotations held in memory, with the contigs for each barcode adjacent, as in a
+    /// json file.  The name is used in error messages.
+    Memory {
+        name: String,
+        contigs: Vec<ContigAnnotation>,
+    },
+    /// An AIRR Rearrangement TSV file, see read_airr.  Annotations are always recomputed.
+    Airr(String),
+    /// A filtered_contig_annotations.csv file and the fasta file of contig sequences, see
+    /// read_contig_csv.  Annotations are always recomputed.
+    Csv { csv: String, fasta: String },
+}
+
+impl VdjSource {
+    /// The file sources implied by the dataset paths in ctl.  These are the
+    /// all_contig_annotations.json files, or in cellranger mode, contig_annotations.json.
+    /// With VDJ_FORMAT=airr, a dataset path is instead an AIRR file, or a directory containing
+    /// airr_rearrangement.tsv.  With VDJ_FORMAT=csv, filtered_contig_annotations.csv and
+    /// all_contig.fasta are used.
+    pub fn files(ctl: &EncloneControl) -> Vec<VdjSource> {
+        let ann = if !ctl.gen_opt.cellranger {
+            "all_contig_annotations.json"
+        } else {
+            "contig_annotations.json"
+        };
+        ctl.origin_info
+            .dataset_path
+            .iter()
+            .map(|p| match ctl.gen_opt.vdj_format.as_str() {
+                "airr" if p.ends_with(".tsv") => VdjSource::Airr(p.clone()),
+                "airr" => VdjSource::Airr(format!("{p}/airr_rearrangement.tsv")),
+                "csv" => VdjSource::Csv {
+                    csv: format!("{p}/filtered_contig_annotations.csv"),
+                    fasta: format!("{p}/all_contig.fasta"),
+                },
+                _ => VdjSource::File(format!("{p}/{ann}")),
+            })
+            .collect()
+    }
+
+    /// The file path, or for an in-memory source, its name.
+    pub fn name(&self) -> &str {
+        match self {
+            VdjSource::File(path) | VdjSource::Airr(path) => path,
+            VdjSource::Csv { csv, .. } => csv,
+            VdjSource::Memory { name, .. } => name,
+        }
+    }
+}
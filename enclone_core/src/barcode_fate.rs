@@ -1,4 +1,11 @@
+use crate::defs::TigData1;
 use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::io::{self, Write};
+
+/// The version of the FATE_FILE format.  Version 1 was a bare list, per dataset, of maps from
+/// barcode to fate.
+pub const FATE_SCHEMA_VERSION: u32 = 2;
 
 /// Different reasons why a barcode which have productive contig(s)
 /// are not called as cells by enclone
@@ -7,6 +14,10 @@ use serde::{Deserialize, Serialize};
 /// and <https://10xgenomics.github.io/enclone/pages/auto/help.special.html>
 #[derive(Serialize, Deserialize, Clone)]
 pub enum BarcodeFate {
+    /// The barcode is in a pure subclonotype that shares CDR3s with two other, much larger pure
+    /// subclonotypes that share nothing with each other, and so appears to be a doublet of
+    /// cells from them.  The CDR3 amino acid sequences of these are recorded as the evidence
+    /// pair1 and pair2.
     Doublet,
     WeakChains,
     /// The barcode was not called as cell by the assembler
@@ -63,6 +74,11 @@ pub enum BarcodeFate {
     /// No productive contigs for this barcode. This will only happen
     /// when certain default filters are turned off
     NonProductive,
+    /// The barcode has more than four contigs.  Turned off by NMAX.
+    TooManyContigs,
+    /// The barcode is in an exact subclonotype having more than one heavy or TRB chain, and
+    /// MAX_HEAVIES=1 was specified.
+    TooManyHeavies,
 }
 
 impl BarcodeFate {
@@ -83,6 +99,165 @@ impl BarcodeFate {
             BarcodeFate::Improper => "IMPROPER",
             BarcodeFate::GraphFilter => "GRAPH_FILTER",
             BarcodeFate::NonProductive => "PRODUCTIVE",
+            BarcodeFate::TooManyContigs => "MAX",
+            BarcodeFate::TooManyHeavies => "MAX_HEAVIES",
+        }
+    }
+
+    /// The position in the pipeline of the stage at which this fate is decided.
+    pub fn stage(&self) -> usize {
+        match self {
+            BarcodeFate::NonProductive | BarcodeFate::TooManyContigs => 0,
+            BarcodeFate::NotAsmCell => 1,
+            BarcodeFate::GraphFilter => 2,
+            BarcodeFate::Cross => 3,
+            BarcodeFate::DuplicatedBarcode => 4,
+            BarcodeFate::GelBeadContamination => 5,
+            BarcodeFate::FoursieKill => 6,
+            BarcodeFate::TooManyHeavies => 7,
+            BarcodeFate::Improper => 8,
+            BarcodeFate::Umi => 9,
+            BarcodeFate::UmiRatio => 10,
+            BarcodeFate::NotGexCell => 11,
+            BarcodeFate::Doublet => 12,
+            BarcodeFate::Signature => 13,
+            BarcodeFate::WeakChains => 14,
+            BarcodeFate::Qual => 15,
+        }
+    }
+}
+
+/// The fate of a barcode, with where and why it was decided.
+#[derive(Serialize, Deserialize, Clone)]
+pub struct FateRecord {
+    pub fate: BarcodeFate,
+    /// See BarcodeFate::stage.
+    pub stage: usize,
+    /// The CDR3 amino acid sequences of the exact subclonotype that the barcode was in, if it
+    /// had been placed in one.
+    pub exact_subclonotype: Option<Vec<String>>,
+    /// The values that caused the barcode to be filtered, by name.
+    pub evidence: BTreeMap<String, String>,
+}
+
+impl From<BarcodeFate> for FateRecord {
+    fn from(fate: BarcodeFate) -> Self {
+        FateRecord {
+            stage: fate.stage(),
+            fate,
+            exact_subclonotype: None,
+            evidence: BTreeMap::new(),
+        }
+    }
+}
+
+impl FateRecord {
+    pub fn label(&self) -> &'static str {
+        self.fate.label()
+    }
+
+    /// Record the exact subclonotype that the barcode was in, given its shared chain data.
+    pub fn exact(mut self, share: &[TigData1]) -> Self {
+        self.exact_subclonotype = Some(share.iter().map(|t| t.cdr3_aa.clone()).collect());
+        self
+    }
+
+    /// Record a value that caused the barcode to be filtered.
+    pub fn evidence(mut self, name: &str, value: impl ToString) -> Self {
+        self.evidence.insert(name.to_string(), value.to_string());
+        self
+    }
+}
+
+/// The contents of FATE_FILE, when written as JSON.
+#[derive(Serialize)]
+pub struct FateFile<'a> {
+    pub schema_version: u32,
+    pub datasets: Vec<DatasetFates<'a>>,
+}
+
+#[derive(Serialize)]
+pub struct DatasetFates<'a> {
+    pub dataset_id: &'a str,
+    pub fates: &'a HashMap<String, FateRecord>,
+}
+
+impl<'a> FateFile<'a> {
+    pub fn new(dataset_ids: &'a [String], fate: &'a [HashMap<String, FateRecord>]) -> Self {
+        FateFile {
+            schema_version: FATE_SCHEMA_VERSION,
+            datasets: dataset_ids
+                .iter()
+                .zip(fate)
+                .map(|(dataset_id, fates)| DatasetFates { dataset_id, fates })
+                .collect(),
+        }
+    }
+}
+
+/// Write the fates as TSV, with one line per barcode, sorted by dataset and barcode.  The
+/// first line gives the schema version, and evidence is written as name=value pairs separated
+/// by semicolons.
+pub fn write_fate_tsv(
+    mut w: impl Write,
+    dataset_ids: &[String],
+    fate: &[HashMap<String, FateRecord>],
+) -> io::Result<()> {
+    writeln!(w, "# fate schema version {FATE_SCHEMA_VERSION}")?;
+    writeln!(
+        w,
+        "dataset\tbarcode\tfate\tstage\texact_subclonotype\tevidence"
+    )?;
+    for (dataset_id, fates) in dataset_ids.iter().zip(fate) {
+        let mut barcodes: Vec<&String> = fates.keys().collect();
+        barcodes.sort();
+        for bc in barcodes {
+            let f = &fates[bc];
+            let exact = f
+                .exact_subclonotype
+                .as_ref()
+                .map(|cdr3s| cdr3s.join(","))
+                .unwrap_or_default();
+            let evidence: Vec<String> = f
+                .evidence
+                .iter()
+                .map(|(name, value)| format!("{name}={value}"))
+                .collect();
+            writeln!(
+                w,
+                "{dataset_id}\t{bc}\t{}\t{}\t{exact}\t{}",
+                f.label(),
+                f.stage,
+                evidence.join(";")
+            )?;
         }
     }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{write_fate_tsv, BarcodeFate, FateRecord};
+    use std::collections::HashMap;
+
+    #[test]
+    fn test_write_fate_tsv() {
+        let mut fate = vec![HashMap::new()];
+        fate[0].insert(
+            "TTTC-1".to_string(),
+            FateRecord::from(BarcodeFate::Umi)
+                .evidence("umis", 3)
+                .evidence("min_umis", "7.50"),
+        );
+        fate[0].insert("AAAC-1".to_string(), BarcodeFate::NotAsmCell.into());
+        let mut out = Vec::new();
+        write_fate_tsv(&mut out, &["s1".to_string()], &fate).unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "# fate schema version 2\n\
+             dataset\tbarcode\tfate\tstage\texact_subclonotype\tevidence\n\
+             s1\tAAAC-1\tCELL\t1\t\t\n\
+             s1\tTTTC-1\tUMI\t9\t\tmin_umis=7.50;umis=3\n"
+        );
+    }
 }
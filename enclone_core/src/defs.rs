@@ -133,6 +133,9 @@ pub struct GeneralOpt {
     pub exact: Option<usize>,
     pub binary: String,
     pub proto: String,
+    // Write a sidecar index of clonotype offsets next to the proto file
+    pub proto_index: bool,
+    pub airr: String,
     pub fate_file: String,
     // Optional path to a json file containing metadata
     pub proto_metadata: Option<String>,
@@ -158,6 +161,8 @@ pub struct GeneralOpt {
     pub required_four_chain_clonotypes: Option<usize>,
     pub required_datasets: Option<usize>,
     pub cellranger: bool,
+    // Format of the VDJ input: empty or json for contig annotations json, or airr or csv
+    pub vdj_format: String,
     pub summary: bool,
     pub summary_clean: bool,
     pub summary_csv: bool,
@@ -245,6 +250,7 @@ pub struct GeneralOpt {
     pub var_def: Vec<(String, String, Node, String)>, // {(variable, value, compiled value, expr)}
     pub nospaces: bool,
     pub subsample: f64,
+    pub subsample_seed: usize,
     pub all_bc_filename: String,
     pub all_bc_human: bool,
     pub all_bc_fields: Vec<String>,
@@ -669,7 +675,7 @@ impl TigData {
 // TigData0: data for each cell
 // TigData1: shared data
 
-#[derive(Clone)]
+#[derive(Clone, Default)]
 pub struct TigData0 {
     pub quals: Vec<u8>,                          // quality scores, truncated to V..J
     pub v_start: usize,                          // start of V on full contig sequence
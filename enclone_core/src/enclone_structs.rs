@@ -2,8 +2,9 @@
 
 use self::refx::RefData;
 use crate::{
-    barcode_fate::BarcodeFate,
+    barcode_fate::FateRecord,
     defs::{AlleleData, CloneInfo, EncloneControl, ExactClonotype, GexInfo},
+    gex_access::GexMatrixAccess,
 };
 use enclone_proto::types::DonorReferenceItem;
 use qd::Double;
@@ -37,6 +38,7 @@ pub struct EncloneSetup {
     pub ctl: EncloneControl,
     pub ann: String,
     pub gex_info: GexInfo,
+    pub gex_access: GexMatrixAccess, // shared access to h5 matrix data in gex_info
     pub tall: Option<Instant>,
     pub refdata: RefData,
     pub is_bcr: bool,
@@ -60,7 +62,7 @@ pub struct EncloneExacts {
     pub join_info: Vec<(usize, usize, bool, Vec<u8>)>,
     pub drefs: Vec<DonorReferenceItem>,
     pub sr: Vec<Vec<Double>>,
-    pub fate: Vec<HashMap<String, BarcodeFate>>, // GETS MODIFIED SUBSEQUENTLY
+    pub fate: Vec<HashMap<String, FateRecord>>, // GETS MODIFIED SUBSEQUENTLY
     pub is_bcr: bool,
     pub allele_data: AlleleData,
 }
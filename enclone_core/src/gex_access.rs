@@ -0,0 +1,143 @@
+// Copyright (c) 2021 10X Genomics, Inc. All rights reserved.
+
+// Access to the GEX and FB matrix data in raw_feature_bc_matrix.h5, for datasets whose matrix
+// has not been loaded into gex_matrices.  This is shared by all code that needs it, and is
+// created once per run, so that when H5_PRE is set, the data and indices for each dataset are
+// read at most once, and only if they are needed.
+
+use crate::defs::{EncloneControl, GexInfo};
+use io_utils::path_exists;
+use ndarray::s;
+use std::sync::OnceLock;
+
+type H5Entries = Result<(Vec<u32>, Vec<u32>), String>;
+
+#[derive(Default)]
+pub struct GexMatrixAccess {
+    h5_pre: bool,
+    gex_paths: Vec<String>,
+    loaded: Vec<OnceLock<H5Entries>>, // for each dataset, (data, indices) if H5_PRE
+}
+
+impl GexMatrixAccess {
+    pub fn new(ctl: &EncloneControl) -> GexMatrixAccess {
+        let n = ctl.origin_info.n();
+        GexMatrixAccess {
+            h5_pre: ctl.gen_opt.h5_pre,
+            gex_paths: ctl.origin_info.gex_path.clone(),
+            loaded: (0..n).map(|_| OnceLock::new()).collect(),
+        }
+    }
+
+    // Explain why the h5 datasets for dataset li are not available.
+
+    fn missing(&self, li: usize) -> String {
+        let path = &self.gex_paths[li];
+        let h5_path = format!("{path}/raw_feature_bc_matrix.h5");
+        let mut msg = format!("\nUnable to access the matrix data in {h5_path}.\n");
+        if !path_exists(path) {
+            msg += "The directory that is supposed to contain it does not exist.\n";
+        } else if !path_exists(&h5_path) {
+            msg += "The file does not exist.\n";
+        } else {
+            msg += "The file exists but was not opened.\n";
+        }
+        msg
+    }
+
+    // Read the full data and indices arrays for dataset li.
+
+    fn load(&self, gex_info: &GexInfo, li: usize) -> H5Entries {
+        let (Some(d), Some(ind)) = (&gex_info.h5_data[li], &gex_info.h5_indices[li]) else {
+            return Err(self.missing(li));
+        };
+        let err = |e: hdf5::Error| format!("\nFailed to read {}: {e}.\n", self.gex_paths[li]);
+        let d: Vec<u32> = d.as_reader().read_raw().map_err(err)?;
+        let ind: Vec<u32> = ind.as_reader().read_raw().map_err(err)?;
+        Ok((d, ind))
+    }
+
+    /// Return the (data, indices) entries for the barcode at position p in dataset li.  This is
+    /// only for datasets for which gex_matrices[li] has not been initialized.
+    pub fn h5_row(
+        &self,
+        gex_info: &GexInfo,
+        li: usize,
+        p: usize,
+    ) -> Result<(Vec<u32>, Vec<u32>), String> {
+        let z1 = gex_info.h5_indptr[li][p] as usize;
+        let z2 = gex_info.h5_indptr[li][p + 1] as usize;
+        if self.h5_pre {
+            let (d, ind) = self.loaded[li]
+                .get_or_init(|| self.load(gex_info, li))
+                .as_ref()
+                .map_err(Clone::clone)?;
+            return Ok((d[z1..z2].to_vec(), ind[z1..z2].to_vec()));
+        }
+        let (Some(d), Some(ind)) = (&gex_info.h5_data[li], &gex_info.h5_indices[li]) else {
+            return Err(self.missing(li));
+        };
+        let err = |e: hdf5::Error| format!("\nFailed to read {}: {e}.\n", self.gex_paths[li]);
+        let d: Vec<u32> = d.as_reader().read_slice(s![z1..z2]).map_err(err)?.to_vec();
+        let ind: Vec<u32> = ind
+            .as_reader()
+            .read_slice(s![z1..z2])
+            .map_err(err)?
+            .to_vec();
+        Ok((d, ind))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::GexMatrixAccess;
+    use crate::defs::GexInfo;
+    use std::sync::OnceLock;
+
+    // A dataset whose h5 file was never opened.
+
+    fn unopened() -> GexInfo {
+        GexInfo {
+            h5_data: vec![None],
+            h5_indices: vec![None],
+            h5_indptr: vec![vec![0, 0]],
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_h5_row_missing_data() {
+        let gex_info = unopened();
+        for h5_pre in [false, true] {
+            let access = GexMatrixAccess {
+                h5_pre,
+                gex_paths: vec!["/nonexistent/outs".to_string()],
+                loaded: vec![OnceLock::new()],
+            };
+            let e = access.h5_row(&gex_info, 0, 0).unwrap_err();
+            assert!(e.contains("/nonexistent/outs/raw_feature_bc_matrix.h5"));
+            assert!(e.contains("does not exist"));
+            assert_eq!(access.loaded[0].get().is_some(), h5_pre);
+        }
+    }
+
+    #[test]
+    fn test_h5_row_loads_once() {
+        let gex_info = unopened();
+        let mut access = GexMatrixAccess {
+            h5_pre: true,
+            gex_paths: vec!["/nonexistent/outs".to_string()],
+            loaded: vec![OnceLock::new()],
+        };
+        let e1 = access.h5_row(&gex_info, 0, 0).unwrap_err();
+        assert!(e1.contains("directory that is supposed to contain it does not exist"));
+
+        // Point the dataset at a directory that exists.  Were the load repeated, the explanation
+        // would change; instead the second call gets the result of the first load.
+
+        access.gex_paths[0] = env!("CARGO_MANIFEST_DIR").to_string();
+        let e2 = access.h5_row(&gex_info, 0, 0).unwrap_err();
+        assert_eq!(e1, e2);
+        assert!(access.missing(0).contains("The file does not exist"));
+    }
+}
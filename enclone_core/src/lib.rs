@@ -7,6 +7,7 @@ pub mod cell_color;
 pub mod combine_group_pics;
 pub mod defs;
 pub mod enclone_structs;
+pub mod gex_access;
 pub mod hcomp;
 pub mod join_one;
 pub mod linear_condition;
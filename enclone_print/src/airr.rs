@@ -0,0 +1,421 @@
+// Copyright (c) 2021 10X Genomics, Inc. All rights reserved.
+
+// Write an AIRR Rearrangement TSV file (AIRR=...), as read by tools such as Immcantation and
+// scirpy.  There is one row per contig per cell, for the cells in the final clonotypes.  The
+// clone_id of a row is the clonotype_id of its clonotype in parseable output.
+//
+// Positions follow the AIRR conventions: they are one-based and closed, and refer to the
+// sequence field, which is the full contig.  The sequence_alignment is the V..J part of the
+// contig, aligned to the germline V..J, made from the donor reference V allele if there is one,
+// and otherwise the universal reference V, followed by the D and J, if any.  Gaps are shown as
+// dashes.
+
+use bio_edit::alignment::pairwise::Aligner;
+use enclone_core::defs::{EncloneControl, ExactClonotype, TigData1};
+use enclone_proto::types::DonorReferenceItem;
+use std::fmt::Write as _;
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use vdj_ann::refx::RefData;
+
+const AIRR_FIELDS: [&str; 33] = [
+    "cell_id",
+    "clone_id",
+    "sequence_id",
+    "sequence",
+    "rev_comp",
+    "productive",
+    "locus",
+    "v_call",
+    "d_call",
+    "j_call",
+    "c_call",
+    "sequence_alignment",
+    "germline_alignment",
+    "junction",
+    "junction_aa",
+    "cdr3",
+    "cdr3_aa",
+    "v_cigar",
+    "d_cigar",
+    "j_cigar",
+    "v_sequence_start",
+    "v_sequence_end",
+    "d_sequence_start",
+    "j_sequence_start",
+    "j_sequence_end",
+    "c_sequence_start",
+    "fwr1_start",
+    "cdr1_start",
+    "fwr2_start",
+    "cdr2_start",
+    "fwr3_start",
+    "umi_count",
+    "consensus_count",
+];
+
+// Convert a cigar string for the alignment of x to y, starting at position ystart on y, into
+// the gapped aligned strings for x and y.  Clipped parts of x are omitted.
+
+fn gapped_alignment(x: &[u8], y: &[u8], ystart: usize, cigar: &str) -> (String, String) {
+    let (mut gx, mut gy) = (String::new(), String::new());
+    let (mut i, mut j) = (0, ystart);
+    let mut n = 0;
+    for c in cigar.chars() {
+        if let Some(d) = c.to_digit(10) {
+            n = 10 * n + d as usize;
+            continue;
+        }
+        match c {
+            '=' | 'X' | 'M' => {
+                gx.push_str(&String::from_utf8_lossy(&x[i..i + n]));
+                gy.push_str(&String::from_utf8_lossy(&y[j..j + n]));
+                i += n;
+                j += n;
+            }
+            'I' => {
+                gx.push_str(&String::from_utf8_lossy(&x[i..i + n]));
+                gy.push_str(&"-".repeat(n));
+                i += n;
+            }
+            'D' => {
+                gx.push_str(&"-".repeat(n));
+                gy.push_str(&String::from_utf8_lossy(&y[j..j + n]));
+                j += n;
+            }
+            'S' | 'H' => i += n,
+            _ => {}
+        }
+        n = 0;
+    }
+    (gx, gy)
+}
+
+// Align the V..J sequence of a chain to its germline sequence.
+
+fn germline_alignment(
+    t: &TigData1,
+    refdata: &RefData,
+    dref: &[DonorReferenceItem],
+) -> (String, String) {
+    let mut germline = match t.v_ref_id_donor {
+        Some(d) if t.v_ref_id_donor_alt_id.is_some() => dref[d].nt_sequence.clone(),
+        _ => refdata.refs[t.v_ref_id].to_ascii_vec(),
+    };
+    if let Some(d) = t.d_ref_id {
+        germline.append(&mut refdata.refs[d].to_ascii_vec());
+    }
+    germline.append(&mut refdata.refs[t.j_ref_id].to_ascii_vec());
+    let score = |a: u8, b: u8| if a == b { 1i32 } else { -1i32 };
+    let mut aligner = Aligner::new(-6, -1, &score);
+    let al = aligner.semiglobal(&t.seq, &germline);
+    gapped_alignment(&t.seq, &germline, al.ystart, &al.cigar(false))
+}
+
+// The V cigar, from the V annotation of the V..J sequence, which starts at position v_start on
+// a contig of length len.  If the annotation has two entries, they are separated by an
+// insertion or deletion.
+
+fn v_cigar(annv: &[(i32, i32, i32, i32, i32)], v_start: usize, len: usize) -> String {
+    let mut cigar = String::new();
+    let Some(first) = annv.first() else {
+        return cigar;
+    };
+    write!(cigar, "{}S{}N", v_start + first.0 as usize, first.3).unwrap();
+    let (mut qend, mut rend) = (first.0, first.3);
+    for a in annv {
+        if a.0 > qend {
+            write!(cigar, "{}I", a.0 - qend).unwrap();
+        }
+        if a.3 > rend {
+            write!(cigar, "{}D", a.3 - rend).unwrap();
+        }
+        write!(cigar, "{}M", a.1).unwrap();
+        qend = a.0 + a.1;
+        rend = a.3 + a.1;
+    }
+    write!(cigar, "{}S", len - (v_start + qend as usize)).unwrap();
+    cigar
+}
+
+// The J cigar, for J aligned from j_start to j_stop on a contig of length len, starting at
+// j_start_ref on the reference J.
+
+fn j_cigar(j_start: usize, j_start_ref: usize, j_stop: usize, len: usize) -> String {
+    format!(
+        "{j_start}S{j_start_ref}N{}M{}S",
+        j_stop - j_start,
+        len - j_stop
+    )
+}
+
+/// Write AIRR output for the given clonotypes, which are in output order, so that the
+/// clonotype with index i is given clone_id i + 1.
+pub fn write_airr(
+    ctl: &EncloneControl,
+    exacts: &[Vec<usize>],
+    exact_clonotypes: &[ExactClonotype],
+    refdata: &RefData,
+    dref: &[DonorReferenceItem],
+) -> Result<(), String> {
+    let path = &ctl.gen_opt.airr;
+    let mut out: Box<dyn Write> = if path == "stdout" {
+        Box::new(BufWriter::new(io::stdout()))
+    } else {
+        let f = File::create(path)
+            .map_err(|e| format!("\nUnable to open AIRR file {path} for writing: {e}.\n"))?;
+        Box::new(BufWriter::new(f))
+    };
+    let err = |e: io::Error| format!("\nFailed to write AIRR file {path}: {e}.\n");
+    writeln!(out, "{}", AIRR_FIELDS.join("\t")).map_err(err)?;
+    let name = |id: usize| refdata.name[id].clone();
+    let pos = |p: Option<usize>| p.map(|p| format!("{}", p + 1)).unwrap_or_default();
+    for (i, exacts) in exacts.iter().enumerate() {
+        let clone_id = format!("{}", i + 1);
+        for &e in exacts {
+            let ex = &exact_clonotypes[e];
+
+            // The V..J sequences, and so their alignments, are the same for every cell.
+
+            let alignments: Vec<(String, String)> = ex
+                .share
+                .iter()
+                .map(|t| germline_alignment(t, refdata, dref))
+                .collect();
+            for clone in &ex.clones {
+                for ((t, t0), (seq_aln, germ_aln)) in
+                    ex.share.iter().zip(clone.iter()).zip(alignments.iter())
+                {
+                    // Convert a position on the V..J sequence to one on this contig.
+
+                    let vj = |p: usize| t0.v_start + p;
+                    let junction = &t.cdr3_dna;
+                    let cdr3 = junction.get(3..junction.len().saturating_sub(3));
+                    let cdr3_aa = t.cdr3_aa.get(1..t.cdr3_aa.len().saturating_sub(1));
+                    let len = t0.full_seq.len();
+                    let j_start = vj(t.j_start - t.v_start);
+                    let fields = [
+                        t0.barcode.clone(),
+                        clone_id.clone(),
+                        t0.tigname.clone(),
+                        String::from_utf8_lossy(&t0.full_seq).into_owned(),
+                        "F".to_string(),
+                        "T".to_string(),
+                        t.chain_type.clone(),
+                        name(t.v_ref_id),
+                        t.d_ref_id.map(name).unwrap_or_default(),
+                        name(t.j_ref_id),
+                        t.c_ref_id.map(name).unwrap_or_default(),
+                        seq_aln.clone(),
+                        germ_aln.clone(),
+                        junction.clone(),
+                        t.cdr3_aa.clone(),
+                        cdr3.unwrap_or_default().to_string(),
+                        cdr3_aa.unwrap_or_default().to_string(),
+                        v_cigar(&t.annv, t0.v_start, len),
+                        String::new(),
+                        j_cigar(j_start, t.j_start_ref, t0.j_stop, len),
+                        pos(Some(t0.v_start)),
+                        format!("{}", vj(t.v_stop - t.v_start)),
+                        pos(t.d_start.map(|d| vj(d - t.v_start))),
+                        pos(Some(j_start)),
+                        format!("{}", t0.j_stop),
+                        pos(t0.c_start),
+                        pos(Some(vj(t.fr1_start))),
+                        pos(t.cdr1_start.map(vj)),
+                        pos(t.fr2_start.map(vj)),
+                        pos(t.cdr2_start.map(vj)),
+                        pos(t.fr3_start.map(vj)),
+                        format!("{}", t0.umi_count),
+                        format!("{}", t0.read_count),
+                    ];
+                    writeln!(out, "{}", fields.join("\t")).map_err(err)?;
+                }
+            }
+        }
+    }
+    out.flush().map_err(err)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{gapped_alignment, v_cigar, write_airr, AIRR_FIELDS};
+    use debruijn::dna_string::DnaString;
+    use enclone_core::defs::{EncloneControl, ExactClonotype, Junction, TigData0, TigData1};
+    use std::fs::read_to_string;
+    use vdj_ann::refx::RefData;
+
+    // A heavy chain that matches the reference exactly, with V..J at 3..19 on a contig of
+    // length 21.
+
+    const V: &str = "ACGTACGTAC";
+    const J: &str = "TTTGGG";
+
+    fn tig() -> TigData1 {
+        let seq = format!("{V}{J}").into_bytes();
+        TigData1 {
+            cdr3_dna: "ACGTACTTTGGG".to_string(),
+            seq: seq.clone(),
+            seq_del: seq.clone(),
+            seq_del_amino: seq.clone(),
+            aa_mod_indel: Vec::new(),
+            ins: Vec::new(),
+            full_seq: [b"GGG", seq.as_slice(), b"CC"].concat(),
+            v_start: 3,
+            v_stop: 13,
+            v_stop_ref: 10,
+            d_start: None,
+            j_start: 13,
+            j_start_ref: 0,
+            j_stop: 19,
+            u_ref_id: None,
+            v_ref_id: 0,
+            v_ref_id_donor: None,
+            v_ref_id_donor_donor: None,
+            v_ref_id_donor_alt_id: None,
+            d_ref_id: None,
+            j_ref_id: 1,
+            c_ref_id: None,
+            fr1_start: 0,
+            cdr1_start: None,
+            fr2_start: None,
+            cdr2_start: None,
+            fr3_start: None,
+            cdr3_aa: "TYFG".to_string(),
+            cdr3_start: 6,
+            left: true,
+            chain_type: "IGH".to_string(),
+            annv: vec![(0, 10, 0, 0, 0)],
+            vs: DnaString::from_dna_string(V),
+            vs_notesx: String::new(),
+            js: DnaString::from_dna_string(J),
+            inkt_alpha_chain_gene_match: false,
+            inkt_alpha_chain_junction_match: false,
+            inkt_beta_chain_gene_match: false,
+            inkt_beta_chain_junction_match: false,
+            mait_alpha_chain_gene_match: false,
+            mait_alpha_chain_junction_match: false,
+            mait_beta_chain_gene_match: false,
+            mait_beta_chain_junction_match: false,
+            jun: Junction::default(),
+        }
+    }
+
+    fn exact(cells: &[(&str, usize, usize)]) -> ExactClonotype {
+        let t = tig();
+        let clones = cells
+            .iter()
+            .map(|&(barcode, umi_count, read_count)| {
+                vec![TigData0 {
+                    v_start: t.v_start,
+                    j_stop: t.j_stop,
+                    full_seq: t.full_seq.clone(),
+                    barcode: barcode.to_string(),
+                    tigname: format!("{barcode}_contig_1"),
+                    umi_count,
+                    read_count,
+                    ..Default::default()
+                }]
+            })
+            .collect();
+        ExactClonotype {
+            share: vec![t],
+            clones,
+        }
+    }
+
+    #[test]
+    fn test_write_airr() {
+        let mut refdata = RefData::new();
+        refdata.refs = vec![DnaString::from_dna_string(V), DnaString::from_dna_string(J)];
+        refdata.name = ["IGHV1-2", "IGHJ4"].map(str::to_string).to_vec();
+        let exact_clonotypes = vec![
+            exact(&[("CCC-1", 4, 40)]),
+            exact(&[("AAA-1", 7, 70), ("GGG-1", 2, 20)]),
+        ];
+
+        // The second exact subclonotype is in the first clonotype.
+
+        let exacts = vec![vec![1], vec![0]];
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("airr.tsv");
+        let mut ctl = EncloneControl::default();
+        ctl.gen_opt.airr = path.to_str().unwrap().to_string();
+        write_airr(&ctl, &exacts, &exact_clonotypes, &refdata, &[]).unwrap();
+        let airr = read_to_string(&path).unwrap();
+        let lines: Vec<Vec<&str>> = airr.lines().map(|l| l.split('\t').collect()).collect();
+        assert_eq!(lines[0], AIRR_FIELDS);
+        assert_eq!(lines.len(), 4);
+        assert!(lines.iter().all(|l| l.len() == AIRR_FIELDS.len()));
+        let get = |row: usize, field: &str| -> &str {
+            lines[row][AIRR_FIELDS.iter().position(|f| *f == field).unwrap()]
+        };
+
+        // One row per contig per cell, with the clonotype order giving the clone_id.
+
+        let rows: Vec<[&str; 5]> = (1..4)
+            .map(|r| {
+                [
+                    "cell_id",
+                    "clone_id",
+                    "sequence_id",
+                    "umi_count",
+                    "consensus_count",
+                ]
+                .map(|f| get(r, f))
+            })
+            .collect();
+        assert_eq!(
+            rows,
+            [
+                ["AAA-1", "1", "AAA-1_contig_1", "7", "70"],
+                ["GGG-1", "1", "GGG-1_contig_1", "2", "20"],
+                ["CCC-1", "2", "CCC-1_contig_1", "4", "40"],
+            ]
+        );
+
+        // The annotation, with one-based positions on the contig.
+
+        let vj = format!("{V}{J}");
+        let seq = format!("GGG{vj}CC");
+        for (field, value) in [
+            ("sequence", seq.as_str()),
+            ("productive", "T"),
+            ("locus", "IGH"),
+            ("v_call", "IGHV1-2"),
+            ("d_call", ""),
+            ("j_call", "IGHJ4"),
+            ("sequence_alignment", vj.as_str()),
+            ("germline_alignment", vj.as_str()),
+            ("junction", "ACGTACTTTGGG"),
+            ("cdr3", "TACTTT"),
+            ("cdr3_aa", "YF"),
+            ("v_cigar", "3S0N10M8S"),
+            ("j_cigar", "13S0N6M2S"),
+            ("v_sequence_start", "4"),
+            ("v_sequence_end", "13"),
+            ("j_sequence_start", "14"),
+            ("j_sequence_end", "19"),
+            ("fwr1_start", "4"),
+            ("cdr1_start", ""),
+        ] {
+            assert_eq!(get(1, field), value, "{field}");
+        }
+    }
+
+    #[test]
+    fn test_airr_alignments() {
+        let (x, y) = gapped_alignment(b"ACGTTAC", b"GGACGAC", 2, "3=2I2=");
+        assert_eq!(x, "ACGTTAC");
+        assert_eq!(y, "ACG--AC");
+        let (x, y) = gapped_alignment(b"ACGAC", b"ACGTTAC", 0, "3=2D2=");
+        assert_eq!(x, "ACG--AC");
+        assert_eq!(y, "ACGTTAC");
+        assert_eq!(v_cigar(&[(0, 100, 7, 0, 1)], 20, 150), "20S0N100M30S");
+        assert_eq!(
+            v_cigar(&[(0, 50, 7, 0, 0), (53, 40, 7, 50, 0)], 20, 150),
+            "20S0N50M3I40M37S"
+        );
+    }
+}
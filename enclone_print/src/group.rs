@@ -0,0 +1,501 @@
+// Copyright (c) 2021 10X Genomics, Inc. All rights reserved.
+
+// Group clonotypes, as specified by GROUP=... and its short forms (symmetric grouping) or by
+// AGROUP (asymmetric grouping), and then apply the group filters MIN_GROUP, MIN_GROUP_DONORS,
+// GROUP_CDR3, GROUP_DONOR, GROUP_NAIVE, GROUP_NO_NAIVE and GROUP_CDR3H_LEN_VAR.
+//
+// Symmetric grouping joins two clonotypes if they satisfy every given condition, and the groups
+// are the classes of the resulting equivalence relation.  Each chain of a clonotype is
+// represented by the first exact subclonotype having the chain.  Conditions on the heavy (or
+// light) chain use the first heavy (or light) chain, and are never satisfied by a clonotype
+// lacking one.  Percent identity conditions require sequences of the same length.  For
+// cdr3_aa_heavy≥n%:h:@f, identity is generalized by scoring each pair of amino acids with the
+// matrix in f, so that the identity matrix gives cdr3_aa_heavy≥n%.
+//
+// Asymmetric grouping makes one group for each center, containing the center and the clonotypes
+// near it.  The centers are the clonotypes that pass the filters, and with
+// AG_CENTER=from_filters, the other clonotypes are those that were kept although they failed
+// them.  The distance between two clonotypes is the sum of the edit distances between the CDR3
+// amino acid sequences of their first heavy chains and of their first light chains, and is
+// undefined unless both have the same kinds of chains.  With AG_DIST_BOUND=top=n, the n nearest
+// noncenters join the group, and with AG_DIST_BOUND=max=d, those at distance at most d.  So a
+// noncenter may be in several groups, or in none.
+//
+// With neither, each clonotype is its own group.  Groups are ordered by decreasing number of
+// cells, and the clonotypes in a group by their order in the output.
+
+use crate::tree::germline;
+use enclone_core::defs::{ColInfo, EncloneControl, ExactClonotype, TigData1};
+use equiv::EquivRel;
+use std::cmp::Reverse;
+use std::collections::{BTreeSet, HashMap};
+use triple_accel::levenshtein;
+use vdj_ann::refx::RefData;
+
+const AMINO_ACIDS: &[u8; 20] = b"ACDEFGHIKLMNPQRSTVWY";
+
+// The chains that represent a clonotype.
+
+struct Chains<'a> {
+    all: Vec<&'a TigData1>,
+    heavy: Option<&'a TigData1>,
+    light: Option<&'a TigData1>,
+}
+
+impl<'a> Chains<'a> {
+    fn new(exacts: &[usize], exact_clonotypes: &'a [ExactClonotype], rsi: &ColInfo) -> Self {
+        let all: Vec<&TigData1> = rsi
+            .mat
+            .iter()
+            .filter_map(|col| {
+                col.iter()
+                    .zip(exacts.iter())
+                    .find_map(|(m, &e)| m.map(|m| &exact_clonotypes[e].share[m]))
+            })
+            .collect();
+        Chains {
+            heavy: all.iter().copied().find(|t| t.left),
+            light: all.iter().copied().find(|t| !t.left),
+            all,
+        }
+    }
+}
+
+fn percent_identity(a: &[u8], b: &[u8]) -> Option<f64> {
+    (a.len() == b.len() && !a.is_empty()).then(|| {
+        let same = a.iter().zip(b.iter()).filter(|(x, y)| x == y).count();
+        100.0 * same as f64 / a.len() as f64
+    })
+}
+
+// Percent identity of amino acid sequences, with each pair of amino acids scored by m.
+
+fn matrix_identity(a: &[u8], b: &[u8], m: &[Vec<f64>]) -> Option<f64> {
+    let index = |x: &u8| AMINO_ACIDS.iter().position(|y| y == x);
+    (a.len() == b.len() && !a.is_empty()).then(|| {
+        let score: f64 = a
+            .iter()
+            .zip(b.iter())
+            .filter_map(|(x, y)| Some(m[index(x)?][index(y)?]))
+            .sum();
+        100.0 * score / a.len() as f64
+    })
+}
+
+// Test a percent identity condition on a pair of chains.
+
+fn identity_at_least(
+    a: Option<&TigData1>,
+    b: Option<&TigData1>,
+    pc: Option<f64>,
+    seq: fn(&TigData1) -> &[u8],
+) -> bool {
+    let Some(pc) = pc else {
+        return true;
+    };
+    match (a, b) {
+        (Some(a), Some(b)) => percent_identity(seq(a), seq(b)).is_some_and(|x| x >= pc),
+        _ => false,
+    }
+}
+
+fn symmetric_groups(ctl: &EncloneControl, chains: &[Chains], refdata: &RefData) -> Vec<Vec<usize>> {
+    let g = &ctl.clono_group_opt;
+    let needs_heavy = g.v_heavy_refname
+        || g.vj_heavy_refname
+        || g.vdj_heavy_refname
+        || g.cdr3_heavy_len
+        || g.cdr3_heavy_pc.is_some()
+        || g.cdr3_aa_heavy_pc.is_some()
+        || g.heavy_pc.is_some()
+        || g.aa_heavy_pc.is_some()
+        || g.cdr3_heavy_pc_hf.is_some();
+    let needs_light = g.cdr3_light_len
+        || g.cdr3_light_pc.is_some()
+        || g.cdr3_aa_light_pc.is_some()
+        || g.light_pc.is_some()
+        || g.aa_light_pc.is_some();
+    let name = |id: usize| refdata.name[id].as_str();
+    let vj = |t: &TigData1| format!("{}:{}", name(t.v_ref_id), name(t.j_ref_id));
+    let vdj = |t: &TigData1| {
+        let d = t.d_ref_id.map_or("", name);
+        format!("{}:{d}:{}", name(t.v_ref_id), name(t.j_ref_id))
+    };
+    let sorted = |mut x: Vec<String>| {
+        x.sort();
+        x.join(",")
+    };
+
+    // Clonotypes can only be joined if they have the same key, which encodes the conditions
+    // that require equality, including equal lengths for percent identity.
+
+    let mut buckets = HashMap::<Vec<String>, Vec<usize>>::new();
+    for (i, c) in chains.iter().enumerate() {
+        if (needs_heavy && c.heavy.is_none()) || (needs_light && c.light.is_none()) {
+            continue;
+        }
+        let mut key = Vec::<String>::new();
+        if g.vj_refname {
+            key.push(sorted(c.all.iter().map(|t| vj(t)).collect()));
+        }
+        if g.vdj_refname {
+            key.push(sorted(c.all.iter().map(|t| vdj(t)).collect()));
+        }
+        if g.vj_len {
+            key.push(sorted(
+                c.all.iter().map(|t| t.seq.len().to_string()).collect(),
+            ));
+        }
+        if g.cdr3_len {
+            key.push(sorted(
+                c.all.iter().map(|t| t.cdr3_aa.len().to_string()).collect(),
+            ));
+        }
+        if let Some(h) = c.heavy {
+            if g.v_heavy_refname {
+                key.push(name(h.v_ref_id).to_string());
+            }
+            if g.vj_heavy_refname {
+                key.push(vj(h));
+            }
+            if g.vdj_heavy_refname {
+                key.push(vdj(h));
+            }
+            if g.heavy_pc.is_some() {
+                key.push(h.seq_del.len().to_string());
+            }
+            if g.aa_heavy_pc.is_some() {
+                key.push(h.aa_mod_indel.len().to_string());
+            }
+        }
+        for (t, len, pc) in [
+            (
+                c.heavy,
+                g.cdr3_heavy_len,
+                g.cdr3_heavy_pc.is_some()
+                    || g.cdr3_aa_heavy_pc.is_some()
+                    || g.cdr3_heavy_pc_hf.is_some(),
+            ),
+            (
+                c.light,
+                g.cdr3_light_len,
+                g.cdr3_light_pc.is_some() || g.cdr3_aa_light_pc.is_some(),
+            ),
+        ] {
+            if let Some(t) = t.filter(|_| len || pc) {
+                key.push(t.cdr3_aa.len().to_string());
+            }
+        }
+        if let Some(l) = c.light {
+            if g.light_pc.is_some() {
+                key.push(l.seq_del.len().to_string());
+            }
+            if g.aa_light_pc.is_some() {
+                key.push(l.aa_mod_indel.len().to_string());
+            }
+        }
+        buckets.entry(key).or_default().push(i);
+    }
+
+    // Join clonotypes in the same bucket that satisfy the percent identity conditions.
+
+    let mut eq = EquivRel::new(chains.len() as i32);
+    for bucket in buckets.values() {
+        for (k, &i1) in bucket.iter().enumerate() {
+            for &i2 in &bucket[k + 1..] {
+                if eq.class_id(i1 as i32) == eq.class_id(i2 as i32) {
+                    continue;
+                }
+                let (c1, c2) = (&chains[i1], &chains[i2]);
+                let (h1, h2, l1, l2) = (c1.heavy, c2.heavy, c1.light, c2.light);
+                let joined = identity_at_least(h1, h2, g.cdr3_heavy_pc, |t| t.cdr3_dna.as_bytes())
+                    && identity_at_least(l1, l2, g.cdr3_light_pc, |t| t.cdr3_dna.as_bytes())
+                    && identity_at_least(h1, h2, g.cdr3_aa_heavy_pc, |t| t.cdr3_aa.as_bytes())
+                    && identity_at_least(l1, l2, g.cdr3_aa_light_pc, |t| t.cdr3_aa.as_bytes())
+                    && identity_at_least(h1, h2, g.heavy_pc, |t| t.seq_del.as_slice())
+                    && identity_at_least(l1, l2, g.light_pc, |t| t.seq_del.as_slice())
+                    && identity_at_least(h1, h2, g.aa_heavy_pc, |t| t.aa_mod_indel.as_slice())
+                    && identity_at_least(l1, l2, g.aa_light_pc, |t| t.aa_mod_indel.as_slice())
+                    && g.cdr3_heavy_pc_hf.as_ref().map_or(true, |(pc, m)| {
+                        h1.zip(h2).is_some_and(|(h1, h2)| {
+                            matrix_identity(h1.cdr3_aa.as_bytes(), h2.cdr3_aa.as_bytes(), m)
+                                .is_some_and(|x| x >= *pc)
+                        })
+                    });
+                if joined {
+                    eq.join(i1 as i32, i2 as i32);
+                }
+            }
+        }
+    }
+    let mut reps = Vec::<i32>::new();
+    eq.orbit_reps(&mut reps);
+    reps.iter()
+        .map(|&r| {
+            let mut o = Vec::<i32>::new();
+            eq.orbit(r, &mut o);
+            let mut o: Vec<usize> = o.into_iter().map(|i| i as usize).collect();
+            o.sort_unstable();
+            o
+        })
+        .collect()
+}
+
+fn asymmetric_groups(
+    ctl: &EncloneControl,
+    chains: &[Chains],
+    in_center: &[bool],
+) -> Vec<Vec<usize>> {
+    let dist = |a: &Chains, b: &Chains| -> Option<usize> {
+        if a.heavy.is_some() != b.heavy.is_some() || a.light.is_some() != b.light.is_some() {
+            return None;
+        }
+        let cdr3 = |x: Option<&TigData1>, y: Option<&TigData1>| {
+            x.zip(y).map_or(0, |(x, y)| {
+                levenshtein(x.cdr3_aa.as_bytes(), y.cdr3_aa.as_bytes()) as usize
+            })
+        };
+        Some(cdr3(a.heavy, b.heavy) + cdr3(a.light, b.light))
+    };
+    let bound = &ctl.clono_group_opt.asymmetric_dist_bound;
+    let top = bound
+        .strip_prefix("top=")
+        .and_then(|x| x.parse::<usize>().ok());
+    let max = bound
+        .strip_prefix("max=")
+        .and_then(|x| x.parse::<f64>().ok());
+    let mut groups = Vec::<Vec<usize>>::new();
+    for (i, c) in chains.iter().enumerate() {
+        if !in_center[i] {
+            continue;
+        }
+        let mut near: Vec<(usize, usize)> = chains
+            .iter()
+            .enumerate()
+            .filter(|&(j, _)| !in_center[j])
+            .filter_map(|(j, d)| Some((dist(c, d)?, j)))
+            .collect();
+        near.sort_unstable();
+        if let Some(top) = top {
+            near.truncate(top);
+        } else if let Some(max) = max {
+            near.retain(|&(d, _)| d as f64 <= max);
+        }
+        let mut group = vec![i];
+        group.extend(near.iter().map(|&(_, j)| j));
+        group.sort_unstable();
+        groups.push(group);
+    }
+    groups
+}
+
+/// Group the clonotypes, which are given in output order, and return the groups that pass the
+/// group filters, each as a list of indices of clonotypes.  The vector in_center tells which
+/// clonotypes are centers for asymmetric grouping.
+pub fn group_clonotypes(
+    ctl: &EncloneControl,
+    exacts: &[Vec<usize>],
+    exact_clonotypes: &[ExactClonotype],
+    rsi: &[ColInfo],
+    in_center: &[bool],
+    refdata: &RefData,
+) -> Vec<Vec<usize>> {
+    let g = &ctl.clono_group_opt;
+    let chains: Vec<Chains> = exacts
+        .iter()
+        .zip(rsi.iter())
+        .map(|(exacts, rsi)| Chains::new(exacts, exact_clonotypes, rsi))
+        .collect();
+    let mut groups = match g.style.as_str() {
+        "symmetric" => symmetric_groups(ctl, &chains, refdata),
+        "asymmetric" => asymmetric_groups(ctl, &chains, in_center),
+        _ => (0..exacts.len()).map(|i| vec![i]).collect(),
+    };
+
+    // Apply the group filters.
+
+    let exacts_of = |group: &[usize]| -> Vec<&ExactClonotype> {
+        group
+            .iter()
+            .flat_map(|&i| exacts[i].iter().map(|&e| &exact_clonotypes[e]))
+            .collect()
+    };
+    groups.retain(|group| {
+        let exs = exacts_of(group);
+        let donors: BTreeSet<&str> = exs
+            .iter()
+            .flat_map(|ex| ex.clones.iter())
+            .filter_map(|clone| clone[0].donor_index)
+            .map(|d| ctl.origin_info.donor_list[d].as_str())
+            .collect();
+        let naive = || {
+            exs.iter()
+                .any(|ex| ex.share.iter().all(|t| germline(t) == t.seq))
+        };
+        let heavy_cdr3_lens: BTreeSet<usize> = exs
+            .iter()
+            .flat_map(|ex| ex.share.iter())
+            .filter(|t| t.left)
+            .map(|t| t.cdr3_aa.len())
+            .collect();
+        group.len() >= g.min_group
+            && donors.len() >= g.min_group_donors
+            && (g.cdr3.is_empty()
+                || exs
+                    .iter()
+                    .any(|ex| ex.share.iter().any(|t| t.cdr3_aa == g.cdr3)))
+            && g.donor.iter().all(|d| donors.contains(d.as_str()))
+            && (!g.naive || naive())
+            && (!g.no_naive || !naive())
+            && (!g.cdr3h_len_var || heavy_cdr3_lens.len() > 1)
+    });
+
+    // Order the groups by decreasing number of cells.
+
+    let ncells = |group: &[usize]| -> usize { exacts_of(group).iter().map(|ex| ex.ncells()).sum() };
+    groups.sort_by_cached_key(|group| (Reverse(ncells(group)), group[0]));
+    groups
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{asymmetric_groups, matrix_identity, percent_identity, symmetric_groups, Chains};
+    use debruijn::dna_string::DnaString;
+    use enclone_core::defs::{EncloneControl, Junction, TigData1};
+    use vdj_ann::refx::RefData;
+
+    fn tig(left: bool, cdr3_aa: &str, v_ref_id: usize) -> TigData1 {
+        TigData1 {
+            cdr3_dna: String::new(),
+            seq: Vec::new(),
+            seq_del: Vec::new(),
+            seq_del_amino: Vec::new(),
+            aa_mod_indel: Vec::new(),
+            ins: Vec::new(),
+            full_seq: Vec::new(),
+            v_start: 0,
+            v_stop: 0,
+            v_stop_ref: 0,
+            d_start: None,
+            j_start: 0,
+            j_start_ref: 0,
+            j_stop: 0,
+            u_ref_id: None,
+            v_ref_id,
+            v_ref_id_donor: None,
+            v_ref_id_donor_donor: None,
+            v_ref_id_donor_alt_id: None,
+            d_ref_id: None,
+            j_ref_id: 2,
+            c_ref_id: None,
+            fr1_start: 0,
+            cdr1_start: None,
+            fr2_start: None,
+            cdr2_start: None,
+            fr3_start: None,
+            cdr3_aa: cdr3_aa.to_string(),
+            cdr3_start: 0,
+            left,
+            chain_type: String::new(),
+            annv: Vec::new(),
+            vs: DnaString::new(),
+            vs_notesx: String::new(),
+            js: DnaString::new(),
+            inkt_alpha_chain_gene_match: false,
+            inkt_alpha_chain_junction_match: false,
+            inkt_beta_chain_gene_match: false,
+            inkt_beta_chain_junction_match: false,
+            mait_alpha_chain_gene_match: false,
+            mait_alpha_chain_junction_match: false,
+            mait_beta_chain_gene_match: false,
+            mait_beta_chain_junction_match: false,
+            jun: Junction::default(),
+        }
+    }
+
+    fn chains<'a>(heavy: Option<&'a TigData1>, light: Option<&'a TigData1>) -> Chains<'a> {
+        Chains {
+            all: heavy.into_iter().chain(light).collect(),
+            heavy,
+            light,
+        }
+    }
+
+    #[test]
+    fn test_symmetric_groups() {
+        let mut refdata = RefData::new();
+        refdata.name = ["IGHV1", "IGHV2", "IGHJ1"].map(str::to_string).to_vec();
+        let (h0, h1, h2, h4) = (
+            tig(true, "CARW", 0),
+            tig(true, "CAKW", 1),
+            tig(true, "CGGW", 0),
+            tig(true, "CARWW", 0),
+        );
+        let l3 = tig(false, "CQQW", 0);
+        let chains = [
+            chains(Some(&h0), None),
+            chains(Some(&h1), None),
+            chains(Some(&h2), None),
+            chains(None, Some(&l3)),
+            chains(Some(&h4), None),
+        ];
+        let mut ctl = EncloneControl::default();
+        let groups = |ctl: &EncloneControl| {
+            let mut groups = symmetric_groups(ctl, &chains, &refdata);
+            groups.sort();
+            groups
+        };
+
+        // Clonotypes 0 and 1 have heavy CDR3s that are 75% identical.  Clonotype 3, which has
+        // no heavy chain, and clonotype 4, whose heavy CDR3 has a different length, are alone.
+
+        ctl.clono_group_opt.cdr3_aa_heavy_pc = Some(75.0);
+        assert_eq!(groups(&ctl), [vec![0, 1], vec![2], vec![3], vec![4]]);
+
+        // Clonotypes 0 and 1 have different heavy V genes.
+
+        ctl.clono_group_opt.v_heavy_refname = true;
+        assert_eq!(groups(&ctl), [vec![0], vec![1], vec![2], vec![3], vec![4]]);
+        ctl.clono_group_opt.cdr3_aa_heavy_pc = None;
+        assert_eq!(groups(&ctl), [vec![0, 2, 4], vec![1], vec![3]]);
+    }
+
+    #[test]
+    fn test_asymmetric_groups() {
+        // Clonotypes 0 and 3 are the centers.  Clonotype 1 is at distance 1 from both, and
+        // clonotype 4 at distances 3 and 2.  Clonotype 2 lacks a light chain, so its distance to
+        // the centers is undefined.
+
+        let light = tig(false, "CQQW", 0);
+        let heavy = ["CARW", "CAKW", "CARW", "CAKF", "CGGF"].map(|x| tig(true, x, 0));
+        let chains: Vec<_> = heavy
+            .iter()
+            .enumerate()
+            .map(|(i, h)| chains(Some(h), (i != 2).then_some(&light)))
+            .collect();
+        let in_center = [true, false, false, true, false];
+        let mut ctl = EncloneControl::default();
+        ctl.clono_group_opt.asymmetric_dist_bound = "top=1".to_string();
+        assert_eq!(
+            asymmetric_groups(&ctl, &chains, &in_center),
+            [vec![0, 1], vec![1, 3]]
+        );
+        ctl.clono_group_opt.asymmetric_dist_bound = "max=2".to_string();
+        assert_eq!(
+            asymmetric_groups(&ctl, &chains, &in_center),
+            [vec![0, 1], vec![1, 3, 4]]
+        );
+    }
+
+    #[test]
+    fn test_group_identity() {
+        assert_eq!(percent_identity(b"CARW", b"CAKW"), Some(75.0));
+        assert_eq!(percent_identity(b"CARW", b"CAW"), None);
+        let mut m = vec![vec![0.0; 20]; 20];
+        for (i, row) in m.iter_mut().enumerate() {
+            row[i] = 1.0;
+        }
+        m[14][8] = 0.5; // R to K
+        assert_eq!(matrix_identity(b"CARW", b"CAKW", &m), Some(87.5));
+    }
+}
@@ -0,0 +1,526 @@
+// Copyright (c) 2021 10X Genomics, Inc. All rights reserved.
+
+// Draw the clonotypes as a honeycomb plot in SVG, for PLOT=, PLOT2=, HONEY=, PLOT_BY_ISOTYPE=
+// and PLOT_BY_MARK=.
+//
+// Each cell is a disk, and the cells of a clonotype are packed into a hexagonal cluster, filled
+// ring by ring from the center, so that the size of a cluster reflects its number of cells.
+// Within a cluster, cells are ordered by color, so that cells of the same color are adjacent.
+// The clusters are placed in rows, largest first.  With SPLIT_PLOT_BY_DATASET or
+// SPLIT_PLOT_BY_ORIGIN, there is one plot for each dataset or origin, showing only its cells,
+// and the plot file name has the number of the dataset or origin inserted before .svg.
+//
+// The color of a cell is given by the first of these that applies:
+// - PLOT_BY_ISOTYPE: the constant region of the heavy chain
+// - PLOT_BY_MARK: whether the cell was marked for possible deletion
+// - PLOT2: the color of the first condition that the cell satisfies
+// - PLOT with colors: the color of the origin of the cell
+// - HONEY: the color specification, see cell_color.rs.
+// Otherwise cells are black.  The legend is the one given by LEGEND, if any, and otherwise is
+// made from the colors that were used, unless legends are turned off.
+
+use crate::svg::{escape, legend, legend_size, svg_start, turbo, write_svg, PALETTE};
+use enclone_core::cell_color::CellColor;
+use enclone_core::defs::{EncloneControl, ExactClonotype, POUT_SEP};
+use std::cmp::Reverse;
+use std::collections::HashMap;
+use std::fmt::Write as _;
+use vdj_ann::refx::RefData;
+
+// Cell diameter and the gaps between clusters and around the plot, in pixels.
+
+const CELL: f64 = 10.0;
+const GAP: f64 = 10.0;
+const MARGIN: f64 = 20.0;
+
+// Color for cells whose category or value is unknown, or not among those shown.
+
+const UNASSIGNED: &str = "lightgray";
+
+// A cell, with its key, which determines its color.
+
+struct Cell {
+    key: String,
+    color: String,
+    dataset: usize,
+    origin: Option<usize>,
+}
+
+// How the key of a cell determines its color.
+
+enum Coloring {
+    // The key is a category.  Categories having a color in the map get it, and the others get
+    // palette colors, in order of decreasing number of cells, up to a total of maxcat colored
+    // categories.  The rest are UNASSIGNED.
+    Category {
+        colors: HashMap<String, String>,
+        maxcat: usize,
+    },
+    // The key is a number, which is scaled using the given or observed range, and colored by
+    // turbo.
+    Value {
+        display: String,
+        min: Option<f64>,
+        max: Option<f64>,
+    },
+    // The key is a color.
+    Direct,
+}
+
+// The value of a variable for cell b of an exact subclonotype having n cells.  A per-cell
+// variable has one value for each cell.
+
+fn cell_value<'a>(
+    x: &'a HashMap<String, String>,
+    var: &str,
+    b: usize,
+    n: usize,
+) -> Option<&'a str> {
+    let v = x.get(var)?;
+    let vals: Vec<&str> = v.split(POUT_SEP).collect();
+    let v = if vals.len() == n { vals[b] } else { v.as_str() };
+    (!v.is_empty()).then_some(v)
+}
+
+// The source of cell colors, see above.
+
+#[derive(Clone, Copy)]
+enum Scheme<'a> {
+    Isotype { colors: &'a [String], legend: bool },
+    Mark,
+    Conditions,
+    Origin,
+    Spec(&'a CellColor),
+}
+
+// Find the cells of each clonotype, with their keys, and how keys determine colors.  Also
+// return whether a legend is made by default.
+
+fn cell_keys(
+    ctl: &EncloneControl,
+    exacts: &[Vec<usize>],
+    exact_clonotypes: &[ExactClonotype],
+    out_datas: &[Vec<HashMap<String, String>>],
+    refdata: &RefData,
+) -> (Vec<Vec<Cell>>, Coloring, bool) {
+    let opt = &ctl.plot_opt;
+    let oi = &ctl.origin_info;
+    let scheme = if opt.plot_by_isotype {
+        Scheme::Isotype {
+            colors: &opt.plot_by_isotype_color,
+            legend: !opt.plot_by_isotype_nolegend,
+        }
+    } else if opt.plot_by_mark {
+        Scheme::Mark
+    } else if !opt.plot_conditions.is_empty() {
+        Scheme::Conditions
+    } else if !ctl.gen_opt.origin_color_map.is_empty() {
+        Scheme::Origin
+    } else if let CellColor::ByIsotype(x) = &opt.cell_color {
+        Scheme::Isotype {
+            colors: &x.color,
+            legend: x.show_legend,
+        }
+    } else {
+        Scheme::Spec(&opt.cell_color)
+    };
+    let category =
+        |colors: HashMap<String, String>, maxcat: usize| Coloring::Category { colors, maxcat };
+    let (coloring, default_legend) = match scheme {
+        Scheme::Isotype { colors, legend } => {
+            let mut isotypes: Vec<String> = exact_clonotypes
+                .iter()
+                .flat_map(|ex| ex.share.iter())
+                .filter(|t| t.left)
+                .filter_map(|t| t.c_ref_id.map(|c| refdata.name[c].clone()))
+                .collect();
+            isotypes.sort();
+            isotypes.dedup();
+            let colors = isotypes.into_iter().zip(colors.iter().cloned()).collect();
+            (category(colors, usize::MAX), legend)
+        }
+        Scheme::Mark => {
+            let colors = [("marked", "red"), ("unmarked", "black")]
+                .iter()
+                .map(|c| (c.0.to_string(), c.1.to_string()))
+                .collect();
+            (category(colors, 0), true)
+        }
+        Scheme::Conditions => (Coloring::Direct, false),
+        Scheme::Origin => (
+            category(ctl.gen_opt.origin_color_map.clone(), 0),
+            opt.use_legend,
+        ),
+        Scheme::Spec(CellColor::ByVariableValue(x)) => (
+            Coloring::Value {
+                display: x.display_var.clone(),
+                min: x.min,
+                max: x.max,
+            },
+            opt.use_legend,
+        ),
+        Scheme::Spec(CellColor::ByCategoricalVariableValue(x)) => {
+            (category(HashMap::new(), x.maxcat), opt.use_legend)
+        }
+        Scheme::Spec(CellColor::BySample(x)) if !x.by_meta => {
+            (category(x.specification.clone(), 0), opt.use_legend)
+        }
+        Scheme::Spec(CellColor::ByDataset(_)) => {
+            let colors = oi
+                .dataset_id
+                .iter()
+                .zip(oi.color.iter())
+                .filter(|c| !c.1.is_empty())
+                .map(|c| (c.0.clone(), c.1.clone()))
+                .collect();
+            (category(colors, usize::MAX), opt.use_legend)
+        }
+        Scheme::Spec(_) => (Coloring::Direct, false),
+    };
+
+    // Find the cells and their keys.
+
+    let mut cells = Vec::<Vec<Cell>>::new();
+    for (exacts, out_data) in exacts.iter().zip(out_datas.iter()) {
+        let mut clono_cells = Vec::new();
+        for (&e, x) in exacts.iter().zip(out_data.iter()) {
+            let ex = &exact_clonotypes[e];
+            let n = ex.ncells();
+            for (b, clone) in ex.clones.iter().enumerate() {
+                let c = &clone[0];
+                let d = c.dataset_index;
+                let origin = || c.origin_index.map(|i| oi.origin_list[i].clone());
+                let value = |var: &str| -> Option<String> {
+                    match var {
+                        "dataset" => Some(oi.dataset_id[d].clone()),
+                        "origin" => origin(),
+                        "donor" => c.donor_index.map(|i| oi.donor_list[i].clone()),
+                        "tag" => c.tag_index.map(|i| oi.tag_list[i].clone()),
+                        _ => cell_value(x, var, b, n).map(str::to_string),
+                    }
+                };
+                let key = match scheme {
+                    Scheme::Isotype { .. } => ex
+                        .share
+                        .iter()
+                        .find(|t| t.left)
+                        .and_then(|t| t.c_ref_id)
+                        .map(|id| refdata.name[id].clone()),
+                    Scheme::Mark => {
+                        let marked = clone.iter().any(|c| c.marked);
+                        Some((if marked { "marked" } else { "unmarked" }).to_string())
+                    }
+                    Scheme::Conditions => opt
+                        .plot_conditions
+                        .iter()
+                        .zip(opt.plot_colors.iter())
+                        .find(|(cond, _)| {
+                            let (var, val) = cond.split_once('=').unwrap();
+                            value(var).as_deref() == Some(val)
+                        })
+                        .map(|(_, color)| color.clone()),
+                    Scheme::Origin => origin(),
+                    Scheme::Spec(CellColor::ByVariableValue(v)) => value(&v.var),
+                    Scheme::Spec(CellColor::ByCategoricalVariableValue(v)) => v
+                        .vars
+                        .iter()
+                        .map(|var| value(var.as_str()))
+                        .collect::<Option<Vec<String>>>()
+                        .map(|vals| vals.join(",")),
+                    Scheme::Spec(CellColor::BySample(s)) if s.by_meta => Some(oi.color[d].clone()),
+                    Scheme::Spec(CellColor::BySample(_)) => origin(),
+                    Scheme::Spec(CellColor::ByBarcodeSpecification(_)) => {
+                        oi.barcode_color[d].get(&c.barcode).cloned()
+                    }
+                    Scheme::Spec(CellColor::ByDataset(_)) => Some(oi.dataset_id[d].clone()),
+                    Scheme::Spec(_) => None,
+                };
+                clono_cells.push(Cell {
+                    key: key.unwrap_or_default(),
+                    color: String::new(),
+                    dataset: d,
+                    origin: c.origin_index,
+                });
+            }
+        }
+        cells.push(clono_cells);
+    }
+    (cells, coloring, default_legend)
+}
+
+// Assign colors to cells, and make the legend that describes them, as (color, label) pairs.
+
+fn cell_colors(
+    ctl: &EncloneControl,
+    exacts: &[Vec<usize>],
+    exact_clonotypes: &[ExactClonotype],
+    out_datas: &[Vec<HashMap<String, String>>],
+    refdata: &RefData,
+) -> (Vec<Vec<Cell>>, Vec<(String, String)>) {
+    let (mut cells, coloring, default_legend) =
+        cell_keys(ctl, exacts, exact_clonotypes, out_datas, refdata);
+    let mut auto_legend = Vec::<(String, String)>::new();
+    let color: Box<dyn Fn(&str) -> String> = match coloring {
+        Coloring::Category { mut colors, maxcat } => {
+            let mut counts = HashMap::<&str, usize>::new();
+            for c in cells.iter().flatten() {
+                if !c.key.is_empty() {
+                    *counts.entry(c.key.as_str()).or_default() += 1;
+                }
+            }
+            let mut cats: Vec<(&str, usize)> = counts.into_iter().collect();
+            cats.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(b.0)));
+            let mut ncolored = colors.len();
+            let mut palette = PALETTE.iter().cycle();
+            for &(cat, _) in &cats {
+                if !colors.contains_key(cat) && ncolored < maxcat {
+                    colors.insert(cat.to_string(), palette.next().unwrap().to_string());
+                    ncolored += 1;
+                }
+            }
+            let mut other = false;
+            for &(cat, _) in &cats {
+                match colors.get(cat) {
+                    Some(c) => auto_legend.push((c.clone(), cat.to_string())),
+                    None => other = true,
+                }
+            }
+            if other || cells.iter().flatten().any(|c| c.key.is_empty()) {
+                auto_legend.push((UNASSIGNED.to_string(), "other".to_string()));
+            }
+            Box::new(move |key: &str| {
+                colors
+                    .get(key)
+                    .cloned()
+                    .unwrap_or_else(|| UNASSIGNED.to_string())
+            })
+        }
+        Coloring::Value { display, min, max } => {
+            let vals = cells
+                .iter()
+                .flatten()
+                .filter_map(|c| c.key.parse::<f64>().ok())
+                .filter(|v| v.is_finite());
+            let (lo, hi) = vals.fold((f64::INFINITY, f64::NEG_INFINITY), |(lo, hi), v| {
+                (lo.min(v), hi.max(v))
+            });
+            let (lo, hi) = (min.unwrap_or(lo), max.unwrap_or(hi));
+            if lo <= hi {
+                auto_legend.push((turbo(0.0), format!("{display} = {lo}")));
+                auto_legend.push((turbo(1.0), format!("{display} = {hi}")));
+            }
+            Box::new(move |key: &str| match key.parse::<f64>() {
+                Ok(v) if v.is_finite() && lo < hi => turbo((v - lo) / (hi - lo)),
+                Ok(v) if v.is_finite() => turbo(0.0),
+                _ => "black".to_string(),
+            })
+        }
+        Coloring::Direct => Box::new(|key: &str| {
+            if key.is_empty() {
+                "black".to_string()
+            } else {
+                key.to_string()
+            }
+        }),
+    };
+    for c in cells.iter_mut().flatten() {
+        c.color = color(&c.key);
+    }
+    let legend = if !ctl.plot_opt.legend.is_empty() {
+        ctl.plot_opt.legend.clone()
+    } else if default_legend {
+        auto_legend
+    } else {
+        Vec::new()
+    };
+    (cells, legend)
+}
+
+// The centers of n cells in a hexagonal cluster centered at the origin, ring by ring, in units
+// of the cell diameter.
+
+fn hex_positions(n: usize) -> Vec<(f64, f64)> {
+    const DIRS: [(i32, i32); 6] = [(1, 0), (1, -1), (0, -1), (-1, 0), (-1, 1), (0, 1)];
+    let mut hexes = vec![(0, 0)];
+    let mut r = 1;
+    while hexes.len() < n {
+        let mut h = (DIRS[4].0 * r, DIRS[4].1 * r);
+        for d in DIRS {
+            for _ in 0..r {
+                hexes.push(h);
+                h = (h.0 + d.0, h.1 + d.1);
+            }
+        }
+        r += 1;
+    }
+    hexes.truncate(n);
+    hexes
+        .iter()
+        .map(|&(q, r)| {
+            let (q, r) = (f64::from(q), f64::from(r));
+            (q + r / 2.0, r * 3.0_f64.sqrt() / 2.0)
+        })
+        .collect()
+}
+
+// The radius of a hexagonal cluster of n cells, in units of the cell diameter.
+
+fn hex_radius(n: usize) -> f64 {
+    let mut r = 0;
+    while 1 + 3 * r * (r + 1) < n {
+        r += 1;
+    }
+    r as f64 + 0.5
+}
+
+// Make one plot, of clusters of the given cells.
+
+fn honeycomb_svg(
+    clusters: &[Vec<&Cell>],
+    title: &str,
+    legend_entries: &[(String, String)],
+) -> String {
+    let mut clusters: Vec<&Vec<&Cell>> = clusters.iter().filter(|c| !c.is_empty()).collect();
+    clusters.sort_by_key(|c| Reverse(c.len()));
+
+    // Lay out the clusters in rows, of about the width that would make the plot square.
+
+    let diams: Vec<f64> = clusters
+        .iter()
+        .map(|c| 2.0 * CELL * hex_radius(c.len()))
+        .collect();
+    let area: f64 = diams.iter().map(|d| (d + GAP) * (d + GAP)).sum();
+    let row_width = area.sqrt().max(diams.first().copied().unwrap_or(0.0));
+    let mut centers = Vec::<(f64, f64)>::new();
+    let (mut x, mut y, mut row_height, mut width) = (0.0, 0.0, 0.0_f64, 0.0_f64);
+    for &d in &diams {
+        if x > 0.0 && x + d > row_width {
+            y += row_height + GAP;
+            x = 0.0;
+            row_height = 0.0;
+        }
+        centers.push((x + d / 2.0, y + d / 2.0));
+        x += d + GAP;
+        width = width.max(x - GAP);
+        row_height = row_height.max(d);
+    }
+    let height = y + row_height;
+
+    // Draw.
+
+    let top = if title.is_empty() {
+        MARGIN
+    } else {
+        MARGIN + 30.0
+    };
+    let (lw, lh) = if legend_entries.is_empty() {
+        (0.0, 0.0)
+    } else {
+        legend_size(legend_entries)
+    };
+    let mut s = svg_start(
+        width + 2.0 * MARGIN + if lw > 0.0 { lw + MARGIN } else { 0.0 },
+        top + height.max(lh) + MARGIN,
+    );
+    if !title.is_empty() {
+        writeln!(
+            s,
+            "<text x=\"{MARGIN}\" y=\"{}\" font-family=\"Arial\" font-size=\"16\">{}</text>",
+            MARGIN + 15.0,
+            escape(title)
+        )
+        .unwrap();
+    }
+    for (cells, &(cx, cy)) in clusters.iter().zip(centers.iter()) {
+        let mut colors: Vec<&str> = cells.iter().map(|c| c.color.as_str()).collect();
+        colors.sort_unstable();
+        for (color, (hx, hy)) in colors.iter().zip(hex_positions(cells.len())) {
+            writeln!(
+                s,
+                "<circle cx=\"{:.1}\" cy=\"{:.1}\" r=\"{}\" style=\"fill:{}\" />",
+                MARGIN + cx + CELL * hx,
+                top + cy + CELL * hy,
+                CELL / 2.0,
+                escape(color)
+            )
+            .unwrap();
+        }
+    }
+    if !legend_entries.is_empty() {
+        s += &legend(width + 2.0 * MARGIN, top, legend_entries);
+    }
+    s += "</svg>\n";
+    s
+}
+
+/// Write the honeycomb plot or plots for the given clonotypes, to ctl.plot_opt.plot_file.
+pub fn plot_honeycomb(
+    ctl: &EncloneControl,
+    exacts: &[Vec<usize>],
+    exact_clonotypes: &[ExactClonotype],
+    out_datas: &[Vec<HashMap<String, String>>],
+    refdata: &RefData,
+) -> Result<(), String> {
+    let opt = &ctl.plot_opt;
+    let (cells, legend_entries) = cell_colors(ctl, exacts, exact_clonotypes, out_datas, refdata);
+    let oi = &ctl.origin_info;
+
+    // Find the title of each plot, and the plot that each cell is in.
+
+    let whole = [String::new()];
+    let titles: &[String] = if opt.split_plot_by_dataset {
+        &oi.dataset_id
+    } else if opt.split_plot_by_origin {
+        &oi.origin_list
+    } else {
+        &whole
+    };
+    let split = |c: &Cell| -> Option<usize> {
+        if opt.split_plot_by_dataset {
+            Some(c.dataset)
+        } else if opt.split_plot_by_origin {
+            c.origin
+        } else {
+            Some(0)
+        }
+    };
+    let nsplits = titles.len();
+    for (k, title) in titles.iter().enumerate() {
+        let clusters: Vec<Vec<&Cell>> = cells
+            .iter()
+            .map(|c| c.iter().filter(|&c| split(c) == Some(k)).collect())
+            .collect();
+        let svg = honeycomb_svg(&clusters, title, &legend_entries);
+        let path = &opt.plot_file;
+        let path = if nsplits == 1 || path == "stdout" {
+            path.clone()
+        } else if let Some(stem) = path.strip_suffix(".svg") {
+            format!("{stem}.{}.svg", k + 1)
+        } else {
+            format!("{path}.{}", k + 1)
+        };
+        write_svg(&path, "plot", &svg)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{hex_positions, hex_radius};
+
+    #[test]
+    fn test_hex_cluster() {
+        assert_eq!(hex_radius(1), 0.5);
+        assert_eq!(hex_radius(7), 1.5);
+        assert_eq!(hex_radius(8), 2.5);
+        let p = hex_positions(19);
+        for (i, a) in p.iter().enumerate() {
+            let r = (a.0 * a.0 + a.1 * a.1).sqrt();
+            assert!(r <= hex_radius(19) - 0.5 + 1e-9);
+            for b in &p[..i] {
+                let d = ((a.0 - b.0).powi(2) + (a.1 - b.1).powi(2)).sqrt();
+                assert!(d > 1.0 - 1e-9);
+            }
+        }
+    }
+}
@@ -3,7 +3,9 @@
 // This set of functions writes a protobuf data structure that
 // Loupe uses to access clonotype data.
 
-use enclone_proto::proto_io::write_proto;
+use enclone_proto::proto_io::{
+    write_proto, write_proto_indexed, Error as ProtoError, ProtoStreamWriter,
+};
 use enclone_proto::PROTO_VERSION;
 use vdj_ann::refx;
 
@@ -424,12 +426,16 @@ pub fn make_loupe_clonotype(
     }
 }
 
+/// Write the loupe clonotypes, which are given in output order, to the BINARY and PROTO files.
+/// Unless BINARY is specified, the clonotypes are streamed to the proto file as they are
+/// produced by the iterator, rather than being collected first.
 pub fn loupe_out(
     ctl: &EncloneControl,
-    all_loupe_clonotypes: Vec<Clonotype>,
+    num_clonotypes: usize,
+    clonotypes: impl Iterator<Item = Clonotype>,
     refdata: &RefData,
     dref: &[DonorReferenceItem],
-) {
+) -> Result<(), String> {
     if !ctl.gen_opt.binary.is_empty() || !ctl.gen_opt.proto.is_empty() {
         let mut uref = Vec::new();
         for i in 0..refdata.refs.len() {
@@ -447,7 +453,7 @@ pub fn loupe_out(
                 nt_sequence: refdata.refs[i].to_ascii_vec(),
             });
         }
-        let metadata = match &ctl.gen_opt.proto_metadata {
+        let mut metadata = match &ctl.gen_opt.proto_metadata {
             Some(fname) => serde_json::from_reader(
                 std::fs::File::open(fname)
                     .unwrap_or_else(|_| panic!("Error while reading {fname}")),
@@ -455,21 +461,40 @@ pub fn loupe_out(
             .unwrap_or_else(|_| panic!("Unable to deserialize Metadata from {fname}")),
             None => Metadata::default(),
         };
-        let enclone_outputs = EncloneOutputs {
+        if ctl.gen_opt.subsample >= 0.0 {
+            metadata.subsample_seed = Some(ctl.gen_opt.subsample_seed as u64);
+            metadata.subsample_fraction = Some(ctl.gen_opt.subsample);
+        }
+        let mut enclone_outputs = EncloneOutputs {
             version: PROTO_VERSION.into(),
             metadata,
-            num_clonotypes: all_loupe_clonotypes.len() as u32,
-            clonotypes: all_loupe_clonotypes,
+            num_clonotypes: num_clonotypes as u32,
+            clonotypes: Vec::new(),
             universal_reference: UniversalReference { items: uref },
             donor_reference: DonorReference {
                 items: dref.to_vec(),
             },
         };
+        let proto = &ctl.gen_opt.proto;
+        let err = |e: ProtoError| format!("\nFailed to write PROTO file {proto}: {e}.\n");
         if !ctl.gen_opt.binary.is_empty() {
+            enclone_outputs.clonotypes = clonotypes.collect();
             write_obj(&enclone_outputs, &ctl.gen_opt.binary);
-        }
-        if !ctl.gen_opt.proto.is_empty() {
-            write_proto(enclone_outputs, &ctl.gen_opt.proto).unwrap();
+            if !proto.is_empty() && ctl.gen_opt.proto_index {
+                write_proto_indexed(enclone_outputs, proto).map_err(err)?;
+            } else if !proto.is_empty() {
+                write_proto(enclone_outputs, proto).map_err(err)?;
+            }
+        } else {
+            let mut writer = ProtoStreamWriter::create(proto, enclone_outputs).map_err(err)?;
+            if ctl.gen_opt.proto_index {
+                writer = writer.with_index();
+            }
+            for cl in clonotypes {
+                writer.write_clonotype(cl).map_err(err)?;
+            }
+            writer.finish().map_err(err)?;
         }
     }
+    Ok(())
 }
@@ -0,0 +1,255 @@
+// Copyright (c) 2021 10X Genomics, Inc. All rights reserved.
+
+// Write parseable output (POUT), from the out_datas produced by print_clonotypes.
+//
+// There are two layouts.  By default there is one line per exact subclonotype, and the values
+// of per-cell variables are joined with semicolons.  If PCELL was specified, there is one line
+// per barcode, and each line gets its own value of each per-cell variable.  The per-cell
+// variables are barcode, those ending in _barcode, those ending in _cell (followed by the chain
+// number for chain variables), and those defined by VAR_DEF.  All others have one value per exact
+// subclonotype, which is repeated on each line.
+//
+// The output is CSV, unless the file name ends in .tsv, or POUT=stdouth, in which case it is
+// TSV.
+
+use enclone_core::defs::{ColInfo, EncloneControl, ExactClonotype, POUT_SEP};
+use enclone_core::set_speakers::set_speakers;
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use string_utils::TextUtils;
+
+// Format one field.  For CSV, fields containing the separator or a quote are quoted.
+
+fn field(x: &str, sep: char) -> String {
+    if sep == ',' && (x.contains(',') || x.contains('"')) {
+        format!("\"{}\"", x.replace('"', "\"\""))
+    } else {
+        x.to_string()
+    }
+}
+
+// Determine if a variable has one value per cell, see above.
+
+fn is_cell_var(ctl: &EncloneControl, var: &str) -> bool {
+    var == "barcode"
+        || var.ends_with("_barcode")
+        || var
+            .trim_end_matches(|c: char| c.is_ascii_digit())
+            .ends_with("_cell")
+        || ctl.gen_opt.var_def.iter().any(|v| v.0 == var)
+}
+
+/// Determine the columns of parseable output.  These are PCOLS if given, and otherwise all the
+/// fields that could be generated, for the chains that are filled in: PCHAINS of them, or with
+/// PCHAINS=max, as many as the clonotype having the most chains.
+pub fn parseable_fields(ctl: &EncloneControl, rsi: &[ColInfo]) -> Vec<String> {
+    if !ctl.parseable_opt.pcols.is_empty() {
+        return ctl.parseable_opt.pcols.clone();
+    }
+    let nchains = if ctl.parseable_opt.pchains == "max" {
+        rsi.iter().map(|r| r.mat.len()).max().unwrap_or(0)
+    } else {
+        ctl.parseable_opt.pchains.force_usize()
+    };
+    let mut fields = Vec::<String>::new();
+    set_speakers(ctl, &mut fields, nchains);
+    fields
+}
+
+/// Write parseable output for the given clonotypes, which are in output order, so that the
+/// clonotype with index i is given clonotype_id i + 1.  The clonotypes are written group by
+/// group, as given by group_clonotypes, with group k given group_id k + 1, so that the output
+/// has the groups that would be printed.  Thus a clonotype is written once for each group that
+/// contains it.  Without grouping, that is once.  With symmetric grouping, it is at most once,
+/// and not at all if its group is removed by a group filter such as MIN_GROUP.  With AGROUP, a
+/// clonotype that is near several centers is written once for each of their groups, and one
+/// that is near no center is not written.
+pub fn write_parseable(
+    ctl: &EncloneControl,
+    exacts: &[Vec<usize>],
+    exact_clonotypes: &[ExactClonotype],
+    rsi: &[ColInfo],
+    out_datas: &[Vec<HashMap<String, String>>],
+    groups: &[Vec<usize>],
+) -> Result<(), String> {
+    let pout = &ctl.parseable_opt.pout;
+    let stdout = pout == "stdout" || pout == "stdouth";
+    let sep = if pout == "stdouth" || pout.ends_with(".tsv") {
+        '\t'
+    } else {
+        ','
+    };
+    let mut out: Box<dyn Write> = if stdout {
+        Box::new(BufWriter::new(io::stdout()))
+    } else {
+        let f = File::create(pout)
+            .map_err(|e| format!("\nUnable to open POUT file {pout} for writing: {e}.\n"))?;
+        Box::new(BufWriter::new(f))
+    };
+    let err = |e: io::Error| format!("\nFailed to write POUT file {pout}: {e}.\n");
+    let fields = parseable_fields(ctl, rsi);
+    let cell_vars: Vec<bool> = fields.iter().map(|c| is_cell_var(ctl, c)).collect();
+    let sep_str = sep.to_string();
+
+    // Write the header line, substituting PCOLS_SHOW names if given.
+
+    if !ctl.parseable_opt.pno_header {
+        let header = if ctl.parseable_opt.pcols_show.is_empty() {
+            &fields
+        } else {
+            &ctl.parseable_opt.pcols_show
+        };
+        let header: Vec<String> = header.iter().map(|x| field(x, sep)).collect();
+        writeln!(out, "{}", header.join(&sep_str)).map_err(err)?;
+    }
+
+    // Write the data lines.
+
+    let ncells = |i: usize| -> usize {
+        exacts[i]
+            .iter()
+            .map(|&e| exact_clonotypes[e].ncells())
+            .sum()
+    };
+    for (k, group) in groups.iter().enumerate() {
+        let group_ncells: usize = group.iter().map(|&i| ncells(i)).sum();
+        for &i in group {
+            let ids = [
+                ("clonotype_id", format!("{}", i + 1)),
+                ("group_id", format!("{}", k + 1)),
+                ("group_ncells", format!("{group_ncells}")),
+            ];
+            for (&e, x) in exacts[i].iter().zip(out_datas[i].iter()) {
+                let value = |c: &str| -> Option<&str> {
+                    match x.get(c) {
+                        Some(v) => Some(v.as_str()),
+                        None => ids.iter().find(|id| id.0 == c).map(|id| id.1.as_str()),
+                    }
+                };
+                if !ctl.parseable_opt.pbarcode {
+                    let line: Vec<String> = fields
+                        .iter()
+                        .map(|c| field(&value(c).unwrap_or("").replace(POUT_SEP, ";"), sep))
+                        .collect();
+                    writeln!(out, "{}", line.join(&sep_str)).map_err(err)?;
+                } else {
+                    let n = exact_clonotypes[e].ncells();
+                    for b in 0..n {
+                        let line: Vec<String> = fields
+                            .iter()
+                            .zip(cell_vars.iter())
+                            .map(|(c, &cell_var)| {
+                                let v = value(c).unwrap_or("");
+                                if cell_var {
+                                    field(v.split(POUT_SEP).nth(b).unwrap_or(""), sep)
+                                } else {
+                                    field(&v.replace(POUT_SEP, ";"), sep)
+                                }
+                            })
+                            .collect();
+                        writeln!(out, "{}", line.join(&sep_str)).map_err(err)?;
+                    }
+                }
+            }
+        }
+    }
+    out.flush().map_err(err)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{parseable_fields, write_parseable};
+    use enclone_core::defs::{ColInfo, EncloneControl, ExactClonotype, TigData0, POUT_SEP};
+    use std::collections::HashMap;
+    use std::fs::read_to_string;
+
+    #[test]
+    fn test_write_parseable() {
+        let dir = tempfile::tempdir().unwrap();
+        let exact = |ncells: usize| ExactClonotype {
+            share: Vec::new(),
+            clones: vec![vec![TigData0::default()]; ncells],
+        };
+        let exact_clonotypes = vec![exact(2), exact(1)];
+        let exacts = vec![vec![0], vec![1]];
+        let out_data = |barcode: &str, cdr3: &str, umis: &str| {
+            vec![HashMap::from([
+                ("barcode".to_string(), barcode.replace(';', POUT_SEP)),
+                ("cdr3_aa1".to_string(), cdr3.to_string()),
+                ("u_cell1".to_string(), umis.replace(';', POUT_SEP)),
+            ])]
+        };
+        let out_datas = vec![
+            out_data("AAA-1;CCC-1", "CAR,W", "3;5"),
+            out_data("GGG-1", "CASW", "7"),
+        ];
+        let mut ctl = EncloneControl::default();
+        ctl.parseable_opt.pcols = [
+            "clonotype_id",
+            "group_ncells",
+            "barcode",
+            "cdr3_aa1",
+            "u_cell1",
+        ]
+        .map(str::to_string)
+        .to_vec();
+        let mut write = |name: &str, pcell: bool| -> String {
+            let path = dir.path().join(name);
+            ctl.parseable_opt.pout = path.to_str().unwrap().to_string();
+            ctl.parseable_opt.pbarcode = pcell;
+            write_parseable(
+                &ctl,
+                &exacts,
+                &exact_clonotypes,
+                &[],
+                &out_datas,
+                &[vec![0, 1]],
+            )
+            .unwrap();
+            read_to_string(path).unwrap()
+        };
+
+        // One line per exact subclonotype, as CSV.
+
+        assert_eq!(
+            write("clones.csv", false),
+            "clonotype_id,group_ncells,barcode,cdr3_aa1,u_cell1\n\
+             1,3,AAA-1;CCC-1,\"CAR,W\",3;5\n\
+             2,3,GGG-1,CASW,7\n"
+        );
+
+        // One line per barcode, as TSV.  The per-exact subclonotype value is repeated.
+
+        assert_eq!(
+            write("cells.tsv", true),
+            "clonotype_id\tgroup_ncells\tbarcode\tcdr3_aa1\tu_cell1\n\
+             1\t3\tAAA-1\tCAR,W\t3\n\
+             1\t3\tCCC-1\tCAR,W\t5\n\
+             2\t3\tGGG-1\tCASW\t7\n"
+        );
+    }
+
+    #[test]
+    fn test_parseable_fields() {
+        // The chain fields are those of the chains that are filled in.
+
+        let clonotype = |nchains: usize| ColInfo {
+            mat: vec![Vec::new(); nchains],
+            ..Default::default()
+        };
+        let rsi = vec![clonotype(1), clonotype(5), clonotype(3)];
+        let mut ctl = EncloneControl::default();
+        let mut has = |pchains: &str, rsi: &[ColInfo], field: &str| -> bool {
+            ctl.parseable_opt.pchains = pchains.to_string();
+            parseable_fields(&ctl, rsi).iter().any(|f| f == field)
+        };
+        assert!(has("2", &rsi, "cdr3_aa2"));
+        assert!(!has("2", &rsi, "cdr3_aa3"));
+        assert!(has("max", &rsi, "cdr3_aa5"));
+        assert!(!has("max", &rsi, "cdr3_aa6"));
+        assert!(has("max", &rsi[..1], "cdr3_aa1"));
+        assert!(!has("max", &rsi[..1], "cdr3_aa2"));
+    }
+}
@@ -0,0 +1,214 @@
+// Copyright (c) 2021 10X Genomics, Inc. All rights reserved.
+
+// Scatter plot of two variables across exact subclonotypes, for PLOTXY_EXACT.
+//
+// There is one point per exact subclonotype, at the values of the two variables, as computed
+// for the clonotype table.  For a per-cell variable, the mean over the cells is used.  Exact
+// subclonotypes lacking a numeric value for either variable are omitted, as are those having a
+// nonpositive value for a variable that is plotted on a log10 scale.  The area of a point is
+// proportional to the number of cells.  With sym, both axes have the same range.
+
+use crate::svg::{escape, svg_start, write_svg};
+use enclone_core::defs::{EncloneControl, ExactClonotype, POUT_SEP};
+use std::cmp::Reverse;
+use std::collections::HashMap;
+use std::fmt::Write as _;
+
+// Size of the plotting area, and the margins around it, in pixels.
+
+const SIZE: f64 = 400.0;
+const LEFT: f64 = 80.0;
+const RIGHT: f64 = 30.0;
+const TOP: f64 = 30.0;
+const BOTTOM: f64 = 60.0;
+
+/// The numeric value of a variable for an exact subclonotype, or for a per-cell variable, the
+/// mean of its values over the cells.
+pub(crate) fn exact_value(x: &HashMap<String, String>, var: &str) -> Option<f64> {
+    let vals = x
+        .get(var)?
+        .split(POUT_SEP)
+        .map(str::parse::<f64>)
+        .collect::<Result<Vec<f64>, _>>()
+        .ok()?;
+    let mean = vals.iter().sum::<f64>() / vals.len() as f64;
+    mean.is_finite().then_some(mean)
+}
+
+// Tick positions for an axis covering [lo, hi], at multiples of 1, 2 or 5 times a power of ten,
+// about five in all.
+
+fn ticks(lo: f64, hi: f64) -> Vec<f64> {
+    let range = hi - lo;
+    if range <= 0.0 {
+        return vec![lo];
+    }
+    let pow = 10.0_f64.powf((range / 5.0).log10().floor());
+    let step = [1.0, 2.0, 5.0, 10.0]
+        .iter()
+        .map(|m| m * pow)
+        .find(|s| range / s <= 6.0)
+        .unwrap();
+    let first = (lo / step).ceil() as i64;
+    let last = (hi / step).floor() as i64;
+    (first..=last).map(|k| k as f64 * step).collect()
+}
+
+// Format a tick label, without trailing zeros.
+
+fn tick_label(x: f64) -> String {
+    let s = format!("{x:.6}");
+    let s = s.trim_end_matches('0').trim_end_matches('.');
+    if s == "-0" {
+        "0".to_string()
+    } else {
+        s.to_string()
+    }
+}
+
+// Expand a range slightly, so that no point is on the boundary, and so that it is not empty.
+
+fn pad(lo: f64, hi: f64) -> (f64, f64) {
+    let d = if hi > lo { (hi - lo) / 20.0 } else { 1.0 };
+    (lo - d, hi + d)
+}
+
+/// Write the scatter plot requested by PLOTXY_EXACT, for the given clonotypes.
+pub fn plot_xy(
+    ctl: &EncloneControl,
+    exacts: &[Vec<usize>],
+    exact_clonotypes: &[ExactClonotype],
+    out_datas: &[Vec<HashMap<String, String>>],
+) -> Result<(), String> {
+    let opt = &ctl.plot_opt;
+    let scale = |v: f64, log10: bool| -> Option<f64> {
+        if !log10 {
+            Some(v)
+        } else if v > 0.0 {
+            Some(v.log10())
+        } else {
+            None
+        }
+    };
+
+    // Find the points.
+
+    let mut points = Vec::<(f64, f64, usize)>::new();
+    for (exacts, out_data) in exacts.iter().zip(out_datas.iter()) {
+        for (&e, x) in exacts.iter().zip(out_data.iter()) {
+            let px = exact_value(x, &opt.plot_xy_xvar).and_then(|v| scale(v, opt.plot_xy_x_log10));
+            let py = exact_value(x, &opt.plot_xy_yvar).and_then(|v| scale(v, opt.plot_xy_y_log10));
+            if let (Some(px), Some(py)) = (px, py) {
+                points.push((px, py, exact_clonotypes[e].ncells()));
+            }
+        }
+    }
+
+    // Find the ranges.
+
+    let range = |f: fn(&(f64, f64, usize)) -> f64| {
+        let lo = points.iter().map(f).fold(f64::INFINITY, f64::min);
+        let hi = points.iter().map(f).fold(f64::NEG_INFINITY, f64::max);
+        if points.is_empty() {
+            (0.0, 1.0)
+        } else {
+            pad(lo, hi)
+        }
+    };
+    let (mut xr, mut yr) = (range(|p| p.0), range(|p| p.1));
+    if opt.plot_xy_sym {
+        xr = (xr.0.min(yr.0), xr.1.max(yr.1));
+        yr = xr;
+    }
+    let sx = |x: f64| LEFT + SIZE * (x - xr.0) / (xr.1 - xr.0);
+    let sy = |y: f64| TOP + SIZE * (1.0 - (y - yr.0) / (yr.1 - yr.0));
+
+    // Draw the axes.
+
+    let mut s = svg_start(LEFT + SIZE + RIGHT, TOP + SIZE + BOTTOM);
+    writeln!(
+        s,
+        "<rect x=\"{LEFT}\" y=\"{TOP}\" width=\"{SIZE}\" height=\"{SIZE}\" \
+         style=\"fill:none;stroke:black;stroke-width:1\" />"
+    )
+    .unwrap();
+    for t in ticks(xr.0, xr.1) {
+        let x = sx(t);
+        writeln!(
+            s,
+            "<line x1=\"{x:.1}\" y1=\"{}\" x2=\"{x:.1}\" y2=\"{}\" stroke=\"black\" />\n\
+             <text x=\"{x:.1}\" y=\"{}\" font-family=\"Arial\" font-size=\"12\" \
+             text-anchor=\"middle\">{}</text>",
+            TOP + SIZE,
+            TOP + SIZE + 5.0,
+            TOP + SIZE + 20.0,
+            tick_label(t)
+        )
+        .unwrap();
+    }
+    for t in ticks(yr.0, yr.1) {
+        let y = sy(t);
+        writeln!(
+            s,
+            "<line x1=\"{}\" y1=\"{y:.1}\" x2=\"{LEFT}\" y2=\"{y:.1}\" stroke=\"black\" />\n\
+             <text x=\"{}\" y=\"{:.1}\" font-family=\"Arial\" font-size=\"12\" \
+             text-anchor=\"end\">{}</text>",
+            LEFT - 5.0,
+            LEFT - 8.0,
+            y + 4.0,
+            tick_label(t)
+        )
+        .unwrap();
+    }
+    let label = |var: &str, log10: bool| {
+        if log10 {
+            escape(&format!("log10({var})"))
+        } else {
+            escape(var)
+        }
+    };
+    writeln!(
+        s,
+        "<text x=\"{}\" y=\"{}\" font-family=\"Arial\" font-size=\"14\" \
+         text-anchor=\"middle\">{}</text>\n\
+         <text x=\"20\" y=\"{}\" font-family=\"Arial\" font-size=\"14\" text-anchor=\"middle\" \
+         transform=\"rotate(-90 20 {})\">{}</text>",
+        LEFT + SIZE / 2.0,
+        TOP + SIZE + 45.0,
+        label(&opt.plot_xy_xvar, opt.plot_xy_x_log10),
+        TOP + SIZE / 2.0,
+        TOP + SIZE / 2.0,
+        label(&opt.plot_xy_yvar, opt.plot_xy_y_log10)
+    )
+    .unwrap();
+
+    // Draw the points, largest first, so that small points are not hidden.
+
+    points.sort_by_key(|p| Reverse(p.2));
+    for (x, y, n) in points {
+        writeln!(
+            s,
+            "<circle cx=\"{:.1}\" cy=\"{:.1}\" r=\"{:.1}\" \
+             style=\"fill:#1f77b4;fill-opacity:0.6;stroke:black;stroke-width:0.5\" />",
+            sx(x),
+            sy(y),
+            2.0 * (n as f64).sqrt()
+        )
+        .unwrap();
+    }
+    s += "</svg>\n";
+    write_svg(&opt.plot_xy_filename, "PLOTXY_EXACT plot", &s)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{tick_label, ticks};
+
+    #[test]
+    fn test_ticks() {
+        assert_eq!(ticks(0.0, 10.0), vec![0.0, 2.0, 4.0, 6.0, 8.0, 10.0]);
+        assert_eq!(ticks(-0.3, 0.35), vec![-0.2, 0.0, 0.2]);
+        let labels: Vec<String> = ticks(95.0, 240.0).into_iter().map(tick_label).collect();
+        assert_eq!(labels, ["100", "150", "200"]);
+    }
+}
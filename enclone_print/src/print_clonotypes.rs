@@ -17,15 +17,16 @@ use crate::print_utils3::{
 };
 use crate::print_utils4::{build_show_aa, compute_bu, compute_some_stats};
 use crate::print_utils5::{delete_weaks, vars_and_shares};
+use crate::tree::CloneTree;
 use enclone_args::proc_args_check::involves_gex_fb;
 use enclone_core::allowed_vars::{CVARS_ALLOWED, CVARS_ALLOWED_PCELL, LVARS_ALLOWED};
-use enclone_core::barcode_fate::BarcodeFate;
+use enclone_core::barcode_fate::{write_fate_tsv, FateFile, FateRecord};
 use enclone_core::defs::{AlleleData, CloneInfo, ColInfo, EncloneControl, ExactClonotype, GexInfo};
+use enclone_core::gex_access::GexMatrixAccess;
 use enclone_core::mammalian_fixed_len::mammalian_fixed_len_peer_groups;
 use enclone_core::set_speakers::set_speakers;
-use enclone_proto::types::{Clonotype, DonorReferenceItem};
+use enclone_proto::types::DonorReferenceItem;
 use equiv::EquivRel;
-use hdf5::Reader;
 use itertools::izip;
 use qd::Double;
 use rayon::prelude::*;
@@ -63,9 +64,7 @@ pub fn print_clonotypes(
     raw_joins: &[Vec<usize>],
     gex_info: &GexInfo,
     vdj_cells: &[Vec<String>],
-    d_readers: &[Option<Reader>],
-    ind_readers: &[Option<Reader>],
-    h5_data: &[(usize, Vec<u32>, Vec<u32>)],
+    gex_access: &GexMatrixAccess,
     pics: &mut Vec<String>,
     exacts: &mut Vec<Vec<usize>>,
     in_center: &mut Vec<bool>,
@@ -73,7 +72,7 @@ pub fn print_clonotypes(
     out_datas: &mut Vec<Vec<HashMap<String, String>>>,
     tests: &mut Vec<usize>,
     controls: &mut Vec<usize>,
-    fate: &mut [HashMap<String, BarcodeFate>],
+    fate: &mut [HashMap<String, FateRecord>],
     allele_data: &AlleleData,
 ) -> Result<(), String> {
     let lvars = &ctl.clono_print_opt.lvars;
@@ -186,6 +185,7 @@ pub fn print_clonotypes(
     // 1: vector of clonotype pictures
     // 2: vector of some clonotype info
     //    [parallel to 1]
+    // 6: the exacts and column info of each clonotype to be written to loupe output
     // next to last three entries = whitelist contam, denominator for that, low gex count
     // added out_datas (used to be next to last three, now one more)
     let mut results = Vec::<(
@@ -195,12 +195,12 @@ pub fn print_clonotypes(
         usize,
         usize,
         usize,
-        Vec<Clonotype>,
+        Vec<(Vec<usize>, ColInfo)>,
         Vec<Vec<HashMap<String, String>>>,
         isize,
         Vec<bool>,
         Vec<bool>,
-        Vec<(usize, String, BarcodeFate)>,
+        Vec<(usize, String, FateRecord)>,
         Vec<bool>,
         String,
     )>::new();
@@ -212,7 +212,7 @@ pub fn print_clonotypes(
             0,
             0,
             0,
-            Vec::<Clonotype>::new(),
+            Vec::<(Vec<usize>, ColInfo)>::new(),
             Vec::<Vec<HashMap<String, String>>>::new(),
             0,
             Vec::<bool>::new(),
@@ -356,17 +356,11 @@ pub fn print_clonotypes(
                 }
             }
 
-            // Generate Loupe data.
+            // Save what is needed to generate Loupe data.  The loupe clonotypes are built
+            // later, one at a time, as they are written.
 
             if (!ctl.gen_opt.binary.is_empty() || !ctl.gen_opt.proto.is_empty()) && pass == 2 {
-                loupe_clonotypes.push(make_loupe_clonotype(
-                    exact_clonotypes,
-                    &exacts,
-                    &rsi,
-                    refdata,
-                    dref,
-                    ctl,
-                ));
+                loupe_clonotypes.push((exacts.clone(), rsi.clone()));
             }
 
             // Set up for parseable output.
@@ -652,9 +646,7 @@ pub fn print_clonotypes(
                         &rsi,
                         dref,
                         &groups,
-                        d_readers,
-                        ind_readers,
-                        h5_data,
+                        gex_access,
                         &mut these_stats,
                         &stats_pass1,
                         vdj_cells,
@@ -892,6 +884,32 @@ pub fn print_clonotypes(
                     &cdr3_con,
                 );
 
+                // Add the tree for TREE and NEWICK.  The lines for exact subclonotypes are
+                // annotated with the values of the variables given by TREE=.
+
+                if ctl.gen_opt.tree_on || ctl.gen_opt.newick {
+                    let tree = CloneTree::new(&exacts, exact_clonotypes, &rsi);
+                    if ctl.gen_opt.tree_on {
+                        let annotations: Vec<String> = (0..exacts.len())
+                            .map(|u| {
+                                ctl.gen_opt
+                                    .tree
+                                    .iter()
+                                    .map(|var| {
+                                        let val = out_data.get(u).and_then(|d| d.get(var));
+                                        format!("{var}={}", val.map_or("", String::as_str))
+                                    })
+                                    .collect::<Vec<_>>()
+                                    .join(" ")
+                            })
+                            .collect();
+                        logz += &format!("\n{}", tree.ascii(&annotations));
+                    }
+                    if ctl.gen_opt.newick {
+                        logz += &format!("\n{}\n", tree.newick());
+                    }
+                }
+
                 // Save.
 
                 res.1.push(logz);
@@ -923,20 +941,37 @@ pub fn print_clonotypes(
 
     results.sort_by_key(|x| -x.8);
 
-    // Write loupe output.
-
-    let mut all_loupe_clonotypes = Vec::<Clonotype>::new();
-    for r in results.iter_mut() {
-        all_loupe_clonotypes.append(&mut r.6);
-    }
-    loupe_out(ctl, all_loupe_clonotypes, refdata, dref);
+    // Write loupe output.  The loupe clonotypes are built in parallel, a chunk at a time, and
+    // each chunk is written in output order before the next is built, so that they are never
+    // all in memory at once.
+
+    const LOUPE_CHUNK: usize = 1000;
+    let loupe_inputs: Vec<&(Vec<usize>, ColInfo)> =
+        results.iter().flat_map(|r| r.6.iter()).collect();
+    let num_loupe_clonotypes = loupe_inputs.len();
+    let loupe_clonotypes = loupe_inputs.chunks(LOUPE_CHUNK).flat_map(|chunk| {
+        chunk
+            .par_iter()
+            .map(|(exacts, rsi)| {
+                make_loupe_clonotype(exact_clonotypes, exacts, rsi, refdata, dref, ctl)
+            })
+            .collect::<Vec<_>>()
+    });
+    loupe_out(ctl, num_loupe_clonotypes, loupe_clonotypes, refdata, dref)?;
 
-    // Write out the fate of each filtered barcode.
+    // Write out the fate of each filtered barcode, as TSV if the file name ends in .tsv, and
+    // otherwise as JSON.
     if !ctl.gen_opt.fate_file.is_empty() {
         let mut wtr = BufWriter::new(
             File::create(&ctl.gen_opt.fate_file).expect("Unable to open FATE_FILE for writing"),
         );
-        serde_json::to_writer_pretty(&mut wtr, fate).map_err(|e| e.to_string())?;
+        let dataset_ids = &ctl.origin_info.dataset_id;
+        if ctl.gen_opt.fate_file.ends_with(".tsv") {
+            write_fate_tsv(&mut wtr, dataset_ids, fate).map_err(|e| e.to_string())?;
+        } else {
+            serde_json::to_writer_pretty(&mut wtr, &FateFile::new(dataset_ids, fate))
+                .map_err(|e| e.to_string())?;
+        }
     }
 
     // Set up to group and print clonotypes.
@@ -890,6 +890,13 @@ pub fn extra_args(ctl: &EncloneControl) -> Vec<String> {
         extra_args.push(ctl.plot_opt.plot_xy_xvar.clone());
         extra_args.push(ctl.plot_opt.plot_xy_yvar.clone());
     }
+    for cond in &ctl.plot_opt.plot_conditions {
+        if let Some((var, _)) = cond.split_once('=') {
+            if !["dataset", "origin", "donor", "tag"].contains(&var) {
+                extra_args.push(var.to_string());
+            }
+        }
+    }
     match ctl.plot_opt.cell_color {
         CellColor::ByVariableValue(ref x) => {
             extra_args.push(x.var.clone());
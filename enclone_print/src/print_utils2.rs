@@ -9,15 +9,14 @@ use crate::proc_lvar2::proc_lvar2;
 use crate::proc_lvar_auto::proc_lvar_auto;
 use amino::{aa_seq, codon_to_aa};
 use enclone_core::allowed_vars::LVARS_ALLOWED;
-use enclone_core::barcode_fate::BarcodeFate;
+use enclone_core::barcode_fate::FateRecord;
 use enclone_core::defs::{AlleleData, ColInfo, EncloneControl, ExactClonotype, GexInfo, POUT_SEP};
+use enclone_core::gex_access::GexMatrixAccess;
 use enclone_core::median::median_f64;
 use enclone_proto::types::DonorReferenceItem;
 use enclone_vars::decode_arith;
 use expr_tools::{define_evalexpr_context, vars_of_node};
-use hdf5::Reader;
 use itertools::Itertools;
-use ndarray::s;
 use stats_utils::percent_ratio;
 use std::collections::{HashMap, HashSet};
 use std::fmt::Write;
@@ -57,9 +56,7 @@ pub fn row_fill(
     rsi: &ColInfo,
     dref: &[DonorReferenceItem],
     groups: &HashMap<usize, Vec<usize>>,
-    d_readers: &[Option<Reader>],
-    ind_readers: &[Option<Reader>],
-    h5_data: &[(usize, Vec<u32>, Vec<u32>)],
+    gex_access: &GexMatrixAccess,
     stats: &mut Vec<(String, Vec<String>)>,
     stats_pass1: &[Vec<(String, Vec<String>)>],
     vdj_cells: &[Vec<String>],
@@ -71,7 +68,7 @@ pub fn row_fill(
     extra_args: &[String],
     all_vars: &[&str],
     need_gex: bool,
-    fate: &[HashMap<String, BarcodeFate>],
+    fate: &[HashMap<String, FateRecord>],
     cdr3_con: &[Vec<u8>],
     allele_data: &AlleleData,
 ) -> Result<(), String> {
@@ -226,27 +223,7 @@ pub fn row_fill(
                             }
                         }
                     } else {
-                        let z1 = gex_info.h5_indptr[li][p as usize] as usize;
-                        let z2 = gex_info.h5_indptr[li][p as usize + 1] as usize; // is p+1 OK??
-                        let d: Vec<u32>;
-                        let ind: Vec<u32>;
-                        if ctl.gen_opt.h5_pre {
-                            d = h5_data[li].1[z1..z2].to_vec();
-                            ind = h5_data[li].2[z1..z2].to_vec();
-                        } else {
-                            d = d_readers[li]
-                                .as_ref()
-                                .unwrap()
-                                .read_slice(s![z1..z2])
-                                .unwrap()
-                                .to_vec();
-                            ind = ind_readers[li]
-                                .as_ref()
-                                .unwrap()
-                                .read_slice(s![z1..z2])
-                                .unwrap()
-                                .to_vec();
-                        }
+                        let (d, ind) = gex_access.h5_row(gex_info, li, p as usize)?;
                         for j in 0..d.len() {
                             if gex_info.is_gex[li][ind[j] as usize] {
                                 let n = d[j] as usize;
@@ -442,9 +419,7 @@ pub fn row_fill(
             &gex_counts_unsorted,
             &gex_fcounts_unsorted,
             &n_gexs,
-            d_readers,
-            ind_readers,
-            h5_data,
+            gex_access,
             &alt_bcs,
         )? {
             let _ = proc_lvar2(
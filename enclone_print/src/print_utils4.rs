@@ -2,7 +2,7 @@
 
 use amino::codon_to_aa;
 use enclone_core::{
-    barcode_fate::BarcodeFate,
+    barcode_fate::FateRecord,
     defs::{ColInfo, EncloneControl, ExactClonotype, GexInfo},
 };
 use enclone_proto::types::DonorReferenceItem;
@@ -503,7 +503,7 @@ pub fn compute_bu(
     gex_info: &GexInfo,
     rsi: &ColInfo,
     sr: &mut Vec<(Vec<String>, Vec<Vec<String>>, Vec<Vec<u8>>, usize)>,
-    fate: &[HashMap<String, BarcodeFate>],
+    fate: &[HashMap<String, FateRecord>],
     nd_fields: &[String],
     alt_bcs: &[String],
     cred: &[Vec<String>],
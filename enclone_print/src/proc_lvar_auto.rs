@@ -2,13 +2,12 @@
 // This file is auto-generated by the crate enclone_vars, please do not edit.
 
 use amino::{aa_seq, codon_to_aa};
-use enclone_core::barcode_fate::BarcodeFate;
+use enclone_core::barcode_fate::FateRecord;
 use enclone_core::defs::{ColInfo, EncloneControl, ExactClonotype, GexInfo, POUT_SEP};
+use enclone_core::gex_access::GexMatrixAccess;
 use enclone_core::median::{median_f64, rounded_median};
 use enclone_proto::types::DonorReferenceItem;
-use hdf5::Reader;
 use itertools::Itertools;
-use ndarray::s;
 use regex::Regex;
 use std::cmp::{max, min};
 use std::collections::HashMap;
@@ -31,7 +30,7 @@ pub fn proc_lvar_auto(
     stats: &mut Vec<(String, Vec<String>)>,
     lvars: &[String],
     row: &mut Vec<String>,
-    fate: &[HashMap<String, BarcodeFate>],
+    fate: &[HashMap<String, FateRecord>],
     dref: &[DonorReferenceItem],
     varmat: &[Vec<Vec<u8>>],
     fp: &[Vec<usize>],
@@ -44,9 +43,7 @@ pub fn proc_lvar_auto(
     gex_counts_unsorted: &[usize],
     gex_fcounts_unsorted: &[f64],
     n_gexs: &[usize],
-    d_readers: &[Option<Reader>],
-    ind_readers: &[Option<Reader>],
-    h5_data: &[(usize, Vec<u32>, Vec<u32>)],
+    gex_access: &GexMatrixAccess,
     alt_bcs: &[&str],
 ) -> Result<bool, String> {
     let clonotype_id = exacts[u];
@@ -795,27 +792,7 @@ pub fn proc_lvar_auto(
                             }
                         }
                     } else {
-                        let z1 = gex_info.h5_indptr[li][p as usize] as usize;
-                        let z2 = gex_info.h5_indptr[li][p as usize + 1] as usize; // is p+1 OK??
-                        let d: Vec<u32>;
-                        let ind: Vec<u32>;
-                        if ctl.gen_opt.h5_pre {
-                            d = h5_data[li].1[z1..z2].to_vec();
-                            ind = h5_data[li].2[z1..z2].to_vec();
-                        } else {
-                            d = d_readers[li]
-                                .as_ref()
-                                .unwrap()
-                                .read_slice(s![z1..z2])
-                                .unwrap()
-                                .to_vec();
-                            ind = ind_readers[li]
-                                .as_ref()
-                                .unwrap()
-                                .read_slice(s![z1..z2])
-                                .unwrap()
-                                .to_vec();
-                        }
+                        let (d, ind) = gex_access.h5_row(gex_info, li, p as usize)?;
                         for j in 0..d.len() {
                             if gex_info.is_gex[li][ind[j] as usize] {
                                 raw_count += d[j] as usize;
@@ -843,27 +820,7 @@ pub fn proc_lvar_auto(
                             }
                         }
                     } else {
-                        let z1 = gex_info.h5_indptr[li][p as usize] as usize;
-                        let z2 = gex_info.h5_indptr[li][p as usize + 1] as usize; // is p+1 OK??
-                        let d: Vec<u32>;
-                        let ind: Vec<u32>;
-                        if ctl.gen_opt.h5_pre {
-                            d = h5_data[li].1[z1..z2].to_vec();
-                            ind = h5_data[li].2[z1..z2].to_vec();
-                        } else {
-                            d = d_readers[li]
-                                .as_ref()
-                                .unwrap()
-                                .read_slice(s![z1..z2])
-                                .unwrap()
-                                .to_vec();
-                            ind = ind_readers[li]
-                                .as_ref()
-                                .unwrap()
-                                .read_slice(s![z1..z2])
-                                .unwrap()
-                                .to_vec();
-                        }
+                        let (d, ind) = gex_access.h5_row(gex_info, li, p as usize)?;
                         for j in 0..d.len() {
                             if gex_info.is_gex[li][ind[j] as usize] {
                                 let n = d[j] as usize;
@@ -905,27 +862,7 @@ pub fn proc_lvar_auto(
                             }
                         }
                     } else {
-                        let z1 = gex_info.h5_indptr[li][p as usize] as usize;
-                        let z2 = gex_info.h5_indptr[li][p as usize + 1] as usize; // is p+1 OK??
-                        let d: Vec<u32>;
-                        let ind: Vec<u32>;
-                        if ctl.gen_opt.h5_pre {
-                            d = h5_data[li].1[z1..z2].to_vec();
-                            ind = h5_data[li].2[z1..z2].to_vec();
-                        } else {
-                            d = d_readers[li]
-                                .as_ref()
-                                .unwrap()
-                                .read_slice(s![z1..z2])
-                                .unwrap()
-                                .to_vec();
-                            ind = ind_readers[li]
-                                .as_ref()
-                                .unwrap()
-                                .read_slice(s![z1..z2])
-                                .unwrap()
-                                .to_vec();
-                        }
+                        let (d, ind) = gex_access.h5_row(gex_info, li, p as usize)?;
                         for j in 0..d.len() {
                             if gex_info.is_gex[li][ind[j] as usize] {
                                 raw_count += d[j] as usize;
@@ -953,27 +890,7 @@ pub fn proc_lvar_auto(
                             }
                         }
                     } else {
-                        let z1 = gex_info.h5_indptr[li][p as usize] as usize;
-                        let z2 = gex_info.h5_indptr[li][p as usize + 1] as usize; // is p+1 OK??
-                        let d: Vec<u32>;
-                        let ind: Vec<u32>;
-                        if ctl.gen_opt.h5_pre {
-                            d = h5_data[li].1[z1..z2].to_vec();
-                            ind = h5_data[li].2[z1..z2].to_vec();
-                        } else {
-                            d = d_readers[li]
-                                .as_ref()
-                                .unwrap()
-                                .read_slice(s![z1..z2])
-                                .unwrap()
-                                .to_vec();
-                            ind = ind_readers[li]
-                                .as_ref()
-                                .unwrap()
-                                .read_slice(s![z1..z2])
-                                .unwrap()
-                                .to_vec();
-                        }
+                        let (d, ind) = gex_access.h5_row(gex_info, li, p as usize)?;
                         for j in 0..d.len() {
                             if gex_info.is_gex[li][ind[j] as usize] {
                                 let n = d[j] as usize;
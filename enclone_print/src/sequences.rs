@@ -0,0 +1,288 @@
+// Copyright (c) 2021 10X Genomics, Inc. All rights reserved.
+
+// Write the sequences of the final clonotypes, as requested by FASTA=, FASTA_AA=, CLUSTAL_AA=,
+// CLUSTAL_DNA=, PHYLIP_AA= and PHYLIP_DNA=.
+//
+// FASTA output has one record per chain per exact subclonotype, named
+// {clonotype}.{exact subclonotype}.{chain}, numbered from one in output order.  The sequence is
+// the V..J sequence, followed by the reference sequence of the constant region, if it is known.
+// FASTA_AA gives the translation of this.
+//
+// The other outputs are alignments, one per clonotype, with one row per exact subclonotype,
+// named {clonotype}.{exact subclonotype}.  A row is the concatenation of the V..J sequences of
+// its chains, in the column order given by the clonotype's ColInfo.mat, with deletions relative
+// to the reference shown as gaps.  Each chain is padded with gaps to the length of the longest
+// chain in its column, and a missing chain is all gaps.  If the target is stdout, the
+// alignments are written one after another.  Otherwise it is a tar file, with one member per
+// clonotype, named {clonotype}.clustal or {clonotype}.phylip.
+
+use amino::{aa_seq, codon_to_aa};
+use enclone_core::defs::{ColInfo, EncloneControl, ExactClonotype};
+use std::fmt::Write as _;
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use string_utils::strme;
+use vdj_ann::refx::RefData;
+
+#[derive(Clone, Copy)]
+enum AlignmentFormat {
+    Clustal,
+    Phylip,
+}
+
+// Open a file for writing, or stdout.
+
+fn open_output(path: &str, what: &str) -> Result<Box<dyn Write>, String> {
+    if path == "stdout" {
+        return Ok(Box::new(BufWriter::new(io::stdout())));
+    }
+    let f = File::create(path)
+        .map_err(|e| format!("\nUnable to open {what} file {path} for writing: {e}.\n"))?;
+    Ok(Box::new(BufWriter::new(f)))
+}
+
+fn write_fasta(
+    path: &str,
+    what: &str,
+    amino: bool,
+    exacts: &[Vec<usize>],
+    exact_clonotypes: &[ExactClonotype],
+    rsi: &[ColInfo],
+    refdata: &RefData,
+) -> Result<(), String> {
+    let mut out = open_output(path, what)?;
+    let err = |e: io::Error| format!("\nFailed to write {what} file {path}: {e}.\n");
+    for (i, (exacts, rsi)) in exacts.iter().zip(rsi.iter()).enumerate() {
+        for (u, &e) in exacts.iter().enumerate() {
+            let ex = &exact_clonotypes[e];
+            for (cx, col) in rsi.mat.iter().enumerate() {
+                let Some(m) = col[u] else {
+                    continue;
+                };
+                let mut seq = ex.share[m].seq.clone();
+                if let Some(c) = ex.share[m].c_ref_id {
+                    seq.append(&mut refdata.refs[c].to_ascii_vec());
+                }
+                if amino {
+                    seq = aa_seq(&seq, 0);
+                }
+                writeln!(out, ">{}.{}.{}\n{}", i + 1, u + 1, cx + 1, strme(&seq)).map_err(err)?;
+            }
+        }
+    }
+    out.flush().map_err(err)?;
+    Ok(())
+}
+
+// Translate a V..J sequence in which deletions are shown as gaps, that start on codon
+// boundaries.  A codon that contains a gap becomes a gap.
+
+fn translate_gapped(x: &[u8]) -> Vec<u8> {
+    x.chunks_exact(3)
+        .map(|c| {
+            if c.contains(&b'-') {
+                b'-'
+            } else {
+                codon_to_aa(c)
+            }
+        })
+        .collect()
+}
+
+// Form the aligned rows for a clonotype.
+
+fn alignment_rows(
+    id: usize,
+    exacts: &[usize],
+    exact_clonotypes: &[ExactClonotype],
+    rsi: &ColInfo,
+    amino: bool,
+) -> Vec<(String, Vec<u8>)> {
+    let chains: Vec<Vec<Option<Vec<u8>>>> = rsi
+        .mat
+        .iter()
+        .map(|col| {
+            col.iter()
+                .zip(exacts.iter())
+                .map(|(m, &e)| {
+                    m.map(|m| {
+                        let t = &exact_clonotypes[e].share[m];
+                        if amino {
+                            translate_gapped(&t.seq_del_amino)
+                        } else {
+                            t.seq_del.clone()
+                        }
+                    })
+                })
+                .collect()
+        })
+        .collect();
+    let widths: Vec<usize> = chains
+        .iter()
+        .map(|col| col.iter().flatten().map(Vec::len).max().unwrap_or(0))
+        .collect();
+    (0..exacts.len())
+        .map(|u| {
+            let mut row = Vec::<u8>::new();
+            for (col, &width) in chains.iter().zip(widths.iter()) {
+                let start = row.len();
+                if let Some(seq) = &col[u] {
+                    row.extend_from_slice(seq);
+                }
+                row.resize(start + width, b'-');
+            }
+            (format!("{id}.{}", u + 1), row)
+        })
+        .collect()
+}
+
+// Format an alignment in CLUSTAL format, in blocks of 60 columns.  Below each block is a line
+// marking with * the columns in which all rows have the same residue.
+
+fn clustal(rows: &[(String, Vec<u8>)]) -> String {
+    const BLOCK: usize = 60;
+    let mut s = "CLUSTAL W (1.83) multiple sequence alignment\n\n".to_string();
+    let width = rows.iter().map(|r| r.0.len()).max().unwrap_or(0) + 4;
+    let len = rows.first().map_or(0, |r| r.1.len());
+    for start in (0..len).step_by(BLOCK) {
+        let stop = (start + BLOCK).min(len);
+        s += "\n";
+        for (name, seq) in rows {
+            writeln!(s, "{name:<width$}{}", strme(&seq[start..stop])).unwrap();
+        }
+        let conserved: String = (start..stop)
+            .map(|p| {
+                let c = rows[0].1[p];
+                if c != b'-' && rows.iter().all(|r| r.1[p] == c) {
+                    '*'
+                } else {
+                    ' '
+                }
+            })
+            .collect();
+        writeln!(s, "{:width$}{conserved}", "").unwrap();
+    }
+    s
+}
+
+// Format an alignment in relaxed sequential PHYLIP format.
+
+fn phylip(rows: &[(String, Vec<u8>)]) -> String {
+    let len = rows.first().map_or(0, |r| r.1.len());
+    let mut s = format!("{} {len}\n", rows.len());
+    for (name, seq) in rows {
+        writeln!(s, "{name}  {}", strme(seq)).unwrap();
+    }
+    s
+}
+
+fn write_alignments(
+    path: &str,
+    what: &str,
+    amino: bool,
+    format: AlignmentFormat,
+    exacts: &[Vec<usize>],
+    exact_clonotypes: &[ExactClonotype],
+    rsi: &[ColInfo],
+) -> Result<(), String> {
+    let err = |e: io::Error| format!("\nFailed to write {what} file {path}: {e}.\n");
+    let (format_rows, suffix): (fn(&[(String, Vec<u8>)]) -> String, &str) = match format {
+        AlignmentFormat::Clustal => (clustal, "clustal"),
+        AlignmentFormat::Phylip => (phylip, "phylip"),
+    };
+    let alignments = exacts
+        .iter()
+        .zip(rsi.iter())
+        .enumerate()
+        .map(|(i, (exacts, rsi))| {
+            let rows = alignment_rows(i + 1, exacts, exact_clonotypes, rsi, amino);
+            (format!("{}.{suffix}", i + 1), format_rows(&rows))
+        });
+    if path == "stdout" {
+        let mut out = open_output(path, what)?;
+        for (_, alignment) in alignments {
+            writeln!(out, "{alignment}").map_err(err)?;
+        }
+        out.flush().map_err(err)?;
+    } else {
+        let f = File::create(path)
+            .map_err(|e| format!("\nUnable to open {what} file {path} for writing: {e}.\n"))?;
+        let mut tar = tar::Builder::new(BufWriter::new(f));
+        for (name, alignment) in alignments {
+            let mut header = tar::Header::new_gnu();
+            header.set_size(alignment.len() as u64);
+            header.set_mode(0o644);
+            tar.append_data(&mut header, name, alignment.as_bytes())
+                .map_err(err)?;
+        }
+        tar.into_inner().and_then(|mut f| f.flush()).map_err(err)?;
+    }
+    Ok(())
+}
+
+/// Write the requested sequence files for the given clonotypes, which are in output order.
+pub fn write_sequences(
+    ctl: &EncloneControl,
+    exacts: &[Vec<usize>],
+    exact_clonotypes: &[ExactClonotype],
+    rsi: &[ColInfo],
+    refdata: &RefData,
+) -> Result<(), String> {
+    let opt = &ctl.gen_opt;
+    for (path, what, amino) in [
+        (&opt.fasta_filename, "FASTA", false),
+        (&opt.fasta_aa_filename, "FASTA_AA", true),
+    ] {
+        if !path.is_empty() {
+            write_fasta(path, what, amino, exacts, exact_clonotypes, rsi, refdata)?;
+        }
+    }
+    for (path, what, amino, format) in [
+        (
+            &opt.clustal_aa,
+            "CLUSTAL_AA",
+            true,
+            AlignmentFormat::Clustal,
+        ),
+        (
+            &opt.clustal_dna,
+            "CLUSTAL_DNA",
+            false,
+            AlignmentFormat::Clustal,
+        ),
+        (&opt.phylip_aa, "PHYLIP_AA", true, AlignmentFormat::Phylip),
+        (
+            &opt.phylip_dna,
+            "PHYLIP_DNA",
+            false,
+            AlignmentFormat::Phylip,
+        ),
+    ] {
+        if !path.is_empty() {
+            write_alignments(path, what, amino, format, exacts, exact_clonotypes, rsi)?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{clustal, phylip, translate_gapped};
+
+    #[test]
+    fn test_alignment_formats() {
+        assert_eq!(translate_gapped(b"TGT---GCA"), b"C-A");
+        let rows = vec![
+            ("1.1".to_string(), b"CAS-W".to_vec()),
+            ("1.2".to_string(), b"CAR-W".to_vec()),
+        ];
+        assert_eq!(phylip(&rows), "2 5\n1.1  CAS-W\n1.2  CAR-W\n");
+        assert_eq!(
+            clustal(&rows),
+            "CLUSTAL W (1.83) multiple sequence alignment\n\n\n\
+             1.1    CAS-W\n\
+             1.2    CAR-W\n       \
+             **  *\n"
+        );
+    }
+}
@@ -0,0 +1,165 @@
+// Copyright (c) 2021 10X Genomics, Inc. All rights reserved.
+
+// Heatmap of the similarity matrix of exact subclonotypes, for SIM_MAT_PLOT.
+//
+// Each exact subclonotype has a vector of the values of the given variables, as computed for
+// the clonotype table.  If all values are numeric, the similarity of two exact subclonotypes is
+// the cosine similarity of their vectors, where for a per-cell variable the mean over the cells
+// is used.  Otherwise, for example for cdr3_aa1, the similarity is the mean, over the
+// variables, of the identity of the two values, which is one minus their edit distance divided
+// by the longer length.  A missing value counts as zero in a vector, and has identity zero to
+// any value.
+//
+// The exact subclonotypes are shown in output order, with lines separating clonotypes, and
+// similarity is colored by turbo on [0, 1], so negative cosine similarities show as zero.  Each
+// pair is drawn as a rectangle, so to bound the file size, only the first MAX_SIM_MAT exact
+// subclonotypes are shown.
+
+use crate::plot_xy::exact_value;
+use crate::svg::{escape, legend, legend_size, svg_start, turbo, write_svg};
+use enclone_core::defs::EncloneControl;
+use std::collections::HashMap;
+use std::fmt::Write as _;
+use triple_accel::levenshtein;
+
+const MAX_SIM_MAT: usize = 200;
+const MARGIN: f64 = 20.0;
+
+// The cosine similarity of two vectors.
+
+fn cosine(x: &[f64], y: &[f64]) -> f64 {
+    let dot = |x: &[f64], y: &[f64]| -> f64 { x.iter().zip(y.iter()).map(|(a, b)| a * b).sum() };
+    let (xx, yy) = (dot(x, x), dot(y, y));
+    if xx > 0.0 && yy > 0.0 {
+        dot(x, y) / (xx * yy).sqrt()
+    } else {
+        0.0
+    }
+}
+
+// The mean identity of two vectors of values, see above.
+
+fn identity(x: &[Option<&str>], y: &[Option<&str>]) -> f64 {
+    let id = |a: &Option<&str>, b: &Option<&str>| match (a, b) {
+        (Some(a), Some(b)) if a.is_empty() && b.is_empty() => 1.0,
+        (Some(a), Some(b)) => {
+            let d = levenshtein(a.as_bytes(), b.as_bytes());
+            1.0 - f64::from(d) / a.len().max(b.len()) as f64
+        }
+        _ => 0.0,
+    };
+    let total: f64 = x.iter().zip(y.iter()).map(|(a, b)| id(a, b)).sum();
+    total / x.len().max(1) as f64
+}
+
+/// Write the heatmap requested by SIM_MAT_PLOT, for the given clonotypes.
+pub fn plot_sim_mat(
+    ctl: &EncloneControl,
+    out_datas: &[Vec<HashMap<String, String>>],
+) -> Result<(), String> {
+    let vars = &ctl.plot_opt.sim_mat_plot_vars;
+
+    // Gather the values, and find the clonotype boundaries.
+
+    let total: usize = out_datas.iter().map(Vec::len).sum();
+    let xs: Vec<&HashMap<String, String>> = out_datas.iter().flatten().take(MAX_SIM_MAT).collect();
+    let mut bounds = Vec::<usize>::new();
+    for out_data in out_datas {
+        let b = bounds.last().copied().unwrap_or(0) + out_data.len();
+        if b >= xs.len() {
+            break;
+        }
+        bounds.push(b);
+    }
+    let numeric = xs.iter().all(|x| {
+        vars.iter()
+            .all(|v| !x.contains_key(v) || exact_value(x, v).is_some())
+    });
+    let nums: Vec<Vec<f64>> = xs
+        .iter()
+        .map(|x| {
+            vars.iter()
+                .map(|v| exact_value(x, v).unwrap_or(0.0))
+                .collect()
+        })
+        .collect();
+    let strs: Vec<Vec<Option<&str>>> = xs
+        .iter()
+        .map(|x| vars.iter().map(|v| x.get(v).map(String::as_str)).collect())
+        .collect();
+    let similarity = |i: usize, j: usize| {
+        if numeric {
+            cosine(&nums[i], &nums[j])
+        } else {
+            identity(&strs[i], &strs[j])
+        }
+    };
+
+    // Draw.
+
+    let n = xs.len();
+    let cell = (600.0 / n.max(1) as f64).clamp(1.0, 10.0);
+    let side = cell * n as f64;
+    let top = MARGIN + 30.0;
+    let entries = [
+        (turbo(0.0), "similarity = 0".to_string()),
+        (turbo(1.0), "similarity = 1".to_string()),
+    ];
+    let (lw, lh) = legend_size(&entries);
+    let mut s = svg_start(side + 3.0 * MARGIN + lw, top + side.max(lh) + MARGIN);
+    let mut title = format!("similarity of exact subclonotypes by {}", vars.join(", "));
+    if total > n {
+        write!(title, " (first {n} of {total})").unwrap();
+    }
+    writeln!(
+        s,
+        "<text x=\"{MARGIN}\" y=\"{}\" font-family=\"Arial\" font-size=\"16\">{}</text>",
+        MARGIN + 15.0,
+        escape(&title)
+    )
+    .unwrap();
+    for i in 0..n {
+        for j in 0..n {
+            writeln!(
+                s,
+                "<rect x=\"{:.2}\" y=\"{:.2}\" width=\"{cell:.2}\" height=\"{cell:.2}\" \
+                 style=\"fill:{}\" />",
+                MARGIN + cell * j as f64,
+                top + cell * i as f64,
+                turbo(similarity(i, j))
+            )
+            .unwrap();
+        }
+    }
+    for &b in &bounds {
+        let p = cell * b as f64;
+        writeln!(
+            s,
+            "<line x1=\"{MARGIN}\" y1=\"{:.2}\" x2=\"{:.2}\" y2=\"{:.2}\" stroke=\"white\" />\n\
+             <line x1=\"{:.2}\" y1=\"{top}\" x2=\"{:.2}\" y2=\"{:.2}\" stroke=\"white\" />",
+            top + p,
+            MARGIN + side,
+            top + p,
+            MARGIN + p,
+            MARGIN + p,
+            top + side
+        )
+        .unwrap();
+    }
+    s += &legend(side + 2.0 * MARGIN, top, &entries);
+    s += "</svg>\n";
+    write_svg(&ctl.plot_opt.sim_mat_plot_file, "SIM_MAT_PLOT plot", &s)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{cosine, identity};
+
+    #[test]
+    fn test_similarity() {
+        assert!((cosine(&[1.0, 0.0], &[2.0, 0.0]) - 1.0).abs() < 1e-9);
+        assert!(cosine(&[1.0, 0.0], &[0.0, 3.0]).abs() < 1e-9);
+        assert!((identity(&[Some("CARW")], &[Some("CASW")]) - 0.75).abs() < 1e-9);
+        assert_eq!(identity(&[Some("CARW")], &[None]), 0.0);
+    }
+}
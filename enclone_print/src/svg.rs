@@ -0,0 +1,119 @@
+// Copyright (c) 2021 10X Genomics, Inc. All rights reserved.
+
+// Helpers for writing plots as SVG.
+
+use std::fmt::Write as _;
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+
+/// Colors for categories, in the order they are assigned.
+pub(crate) const PALETTE: [&str; 20] = [
+    "#1f77b4", "#ff7f0e", "#2ca02c", "#d62728", "#9467bd", "#8c564b", "#e377c2", "#7f7f7f",
+    "#bcbd22", "#17becf", "#aec7e8", "#ffbb78", "#98df8a", "#ff9896", "#c5b0d5", "#c49c94",
+    "#f7b6d2", "#c7c7c7", "#dbdb8d", "#9edae5",
+];
+
+/// The turbo color for x in [0, 1], which is first rounded to one of 256 levels.  This uses the
+/// polynomial approximation to turbo.
+pub(crate) fn turbo(x: f64) -> String {
+    let x = (x.clamp(0.0, 1.0) * 255.0).round() / 255.0;
+    let poly = |c: [f64; 6]| -> u8 {
+        let v = c.iter().rev().fold(0.0, |v, &a| v * x + a);
+        (v.clamp(0.0, 1.0) * 255.0).round() as u8
+    };
+    let r = poly([
+        0.135_721_38,
+        4.615_392_6,
+        -42.660_322_58,
+        132.131_082_34,
+        -152.942_393_96,
+        59.286_379_43,
+    ]);
+    let g = poly([
+        0.091_402_61,
+        2.194_188_39,
+        4.842_966_58,
+        -14.185_033_33,
+        4.277_298_57,
+        2.829_566_04,
+    ]);
+    let b = poly([
+        0.106_673_3,
+        12.641_946_08,
+        -60.582_048_36,
+        110.362_767_71,
+        -89.903_109_12,
+        27.348_249_73,
+    ]);
+    format!("rgb({r},{g},{b})")
+}
+
+pub(crate) fn escape(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+/// The opening of an SVG document of the given size, with a white background.
+pub(crate) fn svg_start(width: f64, height: f64) -> String {
+    format!(
+        "<svg version=\"1.1\" baseProfile=\"full\" width=\"{width:.0}\" height=\"{height:.0}\" \
+         xmlns=\"http://www.w3.org/2000/svg\">\n\
+         <rect x=\"0\" y=\"0\" width=\"{width:.0}\" height=\"{height:.0}\" \
+         style=\"fill:white\" />\n"
+    )
+}
+
+/// The width and height of a legend having the given (color, label) entries.
+pub(crate) fn legend_size(entries: &[(String, String)]) -> (f64, f64) {
+    let chars = entries
+        .iter()
+        .map(|e| e.1.chars().count())
+        .max()
+        .unwrap_or(0);
+    (
+        40.0 + 7.5 * chars as f64,
+        20.0 * entries.len() as f64 + 15.0,
+    )
+}
+
+/// A legend having the given (color, label) entries, with its upper left corner at (x, y).
+pub(crate) fn legend(x: f64, y: f64, entries: &[(String, String)]) -> String {
+    let (width, height) = legend_size(entries);
+    let mut s = format!(
+        "<rect x=\"{x:.1}\" y=\"{y:.1}\" width=\"{width:.1}\" height=\"{height:.1}\" \
+         style=\"fill:white;stroke:black;stroke-width:1\" />\n"
+    );
+    for (k, (color, label)) in entries.iter().enumerate() {
+        let cy = y + 17.5 + 20.0 * k as f64;
+        writeln!(
+            s,
+            "<circle cx=\"{:.1}\" cy=\"{cy:.1}\" r=\"5\" style=\"fill:{}\" />\n\
+             <text x=\"{:.1}\" y=\"{:.1}\" font-family=\"Arial\" font-size=\"13\">{}</text>",
+            x + 15.0,
+            escape(color),
+            x + 27.0,
+            cy + 4.5,
+            escape(label)
+        )
+        .unwrap();
+    }
+    s
+}
+
+/// Write an SVG document to a file, or to stdout, if the path is stdout or stdouth.  Other
+/// targets, such as .png files, are rejected by proc_args_post.
+pub(crate) fn write_svg(path: &str, what: &str, svg: &str) -> Result<(), String> {
+    let err = |e: io::Error| format!("\nFailed to write {what} file {path}: {e}.\n");
+    let mut out: Box<dyn Write> = if path == "stdout" || path == "stdouth" {
+        Box::new(BufWriter::new(io::stdout()))
+    } else {
+        let f = File::create(path)
+            .map_err(|e| format!("\nUnable to open {what} file {path} for writing: {e}.\n"))?;
+        Box::new(BufWriter::new(f))
+    };
+    out.write_all(svg.as_bytes()).map_err(err)?;
+    out.flush().map_err(err)?;
+    Ok(())
+}
@@ -0,0 +1,240 @@
+// Copyright (c) 2021 10X Genomics, Inc. All rights reserved.
+
+// Build a tree for a clonotype, for TREE and NEWICK.
+//
+// The taxa are the germline and the exact subclonotypes.  The sequence of an exact subclonotype
+// is the concatenation of the V..J sequences of its chains, heavy (or TRB) chains first, and the
+// distance between two taxa is the sum, over the chains that both have, of the edit distances
+// between their V..J sequences.  The germline sequence for a chain is the donor reference V and
+// the reference J, joined by the junction of the first exact subclonotype having the chain, as
+// the junction of the common ancestor is unknown.  So distances to the germline count changes in
+// V and J only.
+//
+// The tree is made by neighbor joining, and then rooted at the germline.  Exact subclonotypes
+// are labeled by their number in the clonotype table.
+
+use enclone_core::defs::{ColInfo, ExactClonotype, TigData1};
+use std::fmt::Write;
+use triple_accel::levenshtein_exp;
+
+pub struct CloneTree {
+    // The children of each node, and the lengths of the edges to them.  Node 0 is the germline,
+    // node u + 1 is exact subclonotype u, and the remaining nodes are internal.
+    children: Vec<Vec<(usize, f64)>>,
+    nexacts: usize,
+}
+
+/// The germline sequence for a chain, as described above.
+pub fn germline(t: &TigData1) -> Vec<u8> {
+    let (vref, jref) = (t.vs.to_ascii_vec(), t.js.to_ascii_vec());
+    let jstart = t.seq.len().saturating_sub(jref.len());
+    let vstop = t.cdr3_start.min(vref.len()).min(jstart);
+    let mut g = vref[..vstop].to_vec();
+    g.extend_from_slice(&t.seq[vstop..jstart]);
+    g.extend_from_slice(&jref);
+    g
+}
+
+// Join the pair of active nodes that minimizes the neighbor joining criterion, until two remain,
+// and return the edges of the resulting unrooted tree.
+
+fn neighbor_join(dist: &[Vec<f64>]) -> Vec<Vec<(usize, f64)>> {
+    let mut d = dist.to_vec();
+    let mut adj = vec![Vec::<(usize, f64)>::new(); d.len()];
+    let mut active: Vec<usize> = (0..d.len()).collect();
+    while active.len() > 2 {
+        let m = active.len() as f64;
+        let r: Vec<f64> = active
+            .iter()
+            .map(|&i| active.iter().map(|&j| d[i][j]).sum())
+            .collect();
+        let mut best = (f64::INFINITY, 0, 0);
+        for a in 0..active.len() {
+            for b in a + 1..active.len() {
+                let q = (m - 2.0) * d[active[a]][active[b]] - r[a] - r[b];
+                if q < best.0 {
+                    best = (q, a, b);
+                }
+            }
+        }
+        let (_, a, b) = best;
+        let (i, j) = (active[a], active[b]);
+        let li = (0.5 * d[i][j] + (r[a] - r[b]) / (2.0 * (m - 2.0))).clamp(0.0, d[i][j]);
+        let lj = d[i][j] - li;
+        let k = d.len();
+        let mut dk: Vec<f64> = (0..k)
+            .map(|x| (0.5 * (d[i][x] + d[j][x] - d[i][j])).max(0.0))
+            .collect();
+        for (row, &x) in d.iter_mut().zip(dk.iter()) {
+            row.push(x);
+        }
+        dk.push(0.0);
+        d.push(dk);
+        adj.push(vec![(i, li), (j, lj)]);
+        adj[i].push((k, li));
+        adj[j].push((k, lj));
+        active.remove(b);
+        active.remove(a);
+        active.push(k);
+    }
+    if let [i, j] = active[..] {
+        adj[i].push((j, d[i][j]));
+        adj[j].push((i, d[i][j]));
+    }
+    adj
+}
+
+// Direct the edges of an unrooted tree away from the germline.
+
+fn root_at_germline(adj: &[Vec<(usize, f64)>]) -> Vec<Vec<(usize, f64)>> {
+    let mut children = vec![Vec::<(usize, f64)>::new(); adj.len()];
+    let mut stack = vec![(0, usize::MAX)];
+    while let Some((x, parent)) = stack.pop() {
+        for &(y, len) in &adj[x] {
+            if y != parent {
+                children[x].push((y, len));
+                stack.push((y, x));
+            }
+        }
+    }
+    children
+}
+
+// Format an edge length, without trailing zeros.
+
+fn edge_length(len: f64) -> String {
+    let s = format!("{len:.2}");
+    s.trim_end_matches('0').trim_end_matches('.').to_string()
+}
+
+impl CloneTree {
+    pub fn new(exacts: &[usize], exact_clonotypes: &[ExactClonotype], rsi: &ColInfo) -> Self {
+        let nexacts = exacts.len();
+        let mut cols: Vec<usize> = (0..rsi.mat.len()).collect();
+        cols.sort_by_key(|&cx| !rsi.left[cx]);
+
+        // Gather the sequences of each taxon, by chain.
+
+        let mut seqs = vec![vec![None; cols.len()]; nexacts + 1];
+        for (k, &cx) in cols.iter().enumerate() {
+            for (u, &e) in exacts.iter().enumerate() {
+                if let Some(m) = rsi.mat[cx][u] {
+                    let t = &exact_clonotypes[e].share[m];
+                    if seqs[0][k].is_none() {
+                        seqs[0][k] = Some(germline(t));
+                    }
+                    seqs[u + 1][k] = Some(t.seq.clone());
+                }
+            }
+        }
+        let mut dist = vec![vec![0.0; nexacts + 1]; nexacts + 1];
+        for x in 0..=nexacts {
+            for y in x + 1..=nexacts {
+                let d: u32 = seqs[x]
+                    .iter()
+                    .zip(seqs[y].iter())
+                    .filter_map(|(a, b)| Some(levenshtein_exp(a.as_ref()?, b.as_ref()?)))
+                    .sum();
+                dist[x][y] = f64::from(d);
+                dist[y][x] = f64::from(d);
+            }
+        }
+
+        CloneTree {
+            children: root_at_germline(&neighbor_join(&dist)),
+            nexacts,
+        }
+    }
+
+    fn label(&self, x: usize) -> Option<String> {
+        match x {
+            0 => Some("germline".to_string()),
+            x if x <= self.nexacts => Some(format!("{x}")),
+            _ => None,
+        }
+    }
+
+    fn newick_node(&self, x: usize, s: &mut String) {
+        if !self.children[x].is_empty() {
+            s.push('(');
+            for (k, &(y, len)) in self.children[x].iter().enumerate() {
+                if k > 0 {
+                    s.push(',');
+                }
+                self.newick_node(y, s);
+                write!(s, ":{}", edge_length(len)).unwrap();
+            }
+            s.push(')');
+        }
+        if let Some(label) = self.label(x) {
+            *s += &label;
+        }
+    }
+
+    /// The tree in Newick format.
+    pub fn newick(&self) -> String {
+        let mut s = String::new();
+        self.newick_node(0, &mut s);
+        s.push(';');
+        s
+    }
+
+    fn ascii_node(&self, x: usize, prefix: &str, annotations: &[String], s: &mut String) {
+        let n = self.children[x].len();
+        for (k, &(y, len)) in self.children[x].iter().enumerate() {
+            let (branch, extend) = if k + 1 == n {
+                ("└─", "    ")
+            } else {
+                ("├─", "│   ")
+            };
+            let label = self.label(y).unwrap_or_else(|| "•".to_string());
+            write!(s, "{prefix}{branch}{}─ {label}", edge_length(len)).unwrap();
+            if let Some(a) = y.checked_sub(1).and_then(|u| annotations.get(u)) {
+                if !a.is_empty() {
+                    write!(s, "  {a}").unwrap();
+                }
+            }
+            s.push('\n');
+            self.ascii_node(y, &format!("{prefix}{extend}"), annotations, s);
+        }
+    }
+
+    /// The tree drawn as text, with edges labeled by length.  The line for exact subclonotype
+    /// u is followed by annotations[u], if given.
+    pub fn ascii(&self, annotations: &[String]) -> String {
+        let mut s = "germline\n".to_string();
+        self.ascii_node(0, "", annotations, &mut s);
+        s
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{neighbor_join, root_at_germline, CloneTree};
+
+    #[test]
+    fn test_clone_tree() {
+        // Taxa 1 and 2 are close to each other, and far from the germline and taxon 3.
+
+        let dist = vec![
+            vec![0.0, 5.0, 6.0, 2.0],
+            vec![5.0, 0.0, 1.0, 5.0],
+            vec![6.0, 1.0, 0.0, 6.0],
+            vec![2.0, 5.0, 6.0, 0.0],
+        ];
+        let tree = CloneTree {
+            children: root_at_germline(&neighbor_join(&dist)),
+            nexacts: 3,
+        };
+        assert_eq!(tree.newick(), "((3:1,(1:0,2:1):4):1)germline;");
+        assert_eq!(
+            tree.ascii(&["a".to_string(), String::new(), "c".to_string()]),
+            "germline\n\
+             └─1─ •\n    \
+             ├─1─ 3  c\n    \
+             └─4─ •\n        \
+             ├─0─ 1  a\n        \
+             └─1─ 2\n"
+        );
+    }
+}
@@ -0,0 +1,44 @@
+// Copyright (c) 2021 10X Genomics, Inc. All rights reserved.
+
+// Compare the clonotypes in two enclone proto files.
+//
+// usage: proto_diff a.proto b.proto [summary.json]
+//
+// A human-readable report is written to stdout.  If a third argument is given, a JSON summary
+// of the differences is written to that file.  The exit status is 0 if there are no
+// differences, 1 if there are, and 2 if the comparison failed.
+
+use enclone_proto::proto_diff::{diff_protos, write_diff_report};
+use std::env;
+use std::fs::File;
+use std::io::{self, BufWriter};
+use std::process::exit;
+
+fn main() {
+    let args: Vec<String> = env::args().collect();
+    if args.len() < 3 || args.len() > 4 {
+        eprintln!("\nusage: proto_diff a.proto b.proto [summary.json]\n");
+        exit(2);
+    }
+    let diff = match diff_protos(&args[1], &args[2]) {
+        Ok(diff) => diff,
+        Err(e) => {
+            eprintln!("\nFailed to compare {} and {}: {e}\n", args[1], args[2]);
+            exit(2);
+        }
+    };
+    if let Err(e) = write_diff_report(&diff, BufWriter::new(io::stdout())) {
+        eprintln!("\nFailed to write report: {e}\n");
+        exit(2);
+    }
+    if let Some(path) = args.get(3) {
+        let result = File::create(path).map_err(|e| e.to_string()).and_then(|f| {
+            serde_json::to_writer_pretty(BufWriter::new(f), &diff).map_err(|e| e.to_string())
+        });
+        if let Err(e) = result {
+            eprintln!("\nFailed to write {path}: {e}\n");
+            exit(2);
+        }
+    }
+    exit(i32::from(!diff.is_empty()));
+}
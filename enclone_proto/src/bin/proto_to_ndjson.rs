@@ -0,0 +1,37 @@
+// Copyright (c) 2021 10X Genomics, Inc. All rights reserved.
+
+// Convert an enclone proto file to NDJSON.
+//
+// usage: proto_to_ndjson file.proto [out.ndjson]
+//
+// If no output file is given, the output is written to stdout.  The first line is the header
+// (version, metadata, and universal and donor references), and each following line is one
+// clonotype.
+
+use enclone_proto::json_io::proto_to_ndjson;
+use std::env;
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::process::exit;
+
+fn main() {
+    let args: Vec<String> = env::args().collect();
+    if args.len() < 2 || args.len() > 3 {
+        eprintln!("\nusage: proto_to_ndjson file.proto [out.ndjson]\n");
+        exit(1);
+    }
+    let out: Box<dyn Write> = match args.get(2) {
+        Some(path) => match File::create(path) {
+            Ok(f) => Box::new(BufWriter::new(f)),
+            Err(e) => {
+                eprintln!("\nUnable to create {path}: {e}\n");
+                exit(1);
+            }
+        },
+        None => Box::new(BufWriter::new(io::stdout())),
+    };
+    if let Err(e) = proto_to_ndjson(&args[1], out) {
+        eprintln!("\nFailed to convert {}: {e}\n", args[1]);
+        exit(1);
+    }
+}
@@ -431,6 +431,12 @@ pub struct Metadata {
     /// This will be empty for a single sample case
     #[prost(map = "uint32, message", tag = "3")]
     pub per_gem_well_info: ::std::collections::HashMap<u32, GemWellInfo>,
+    /// If barcodes were subsampled (SUBSAMPLE), the seed used to choose them
+    #[prost(uint64, optional, tag = "4")]
+    pub subsample_seed: ::core::option::Option<u64>,
+    /// If barcodes were subsampled, the fraction kept
+    #[prost(double, optional, tag = "5")]
+    pub subsample_fraction: ::core::option::Option<f64>,
 }
 /// Outputs from a single enclone run.
 ///
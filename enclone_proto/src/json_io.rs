@@ -0,0 +1,161 @@
+// Copyright (c) 2021 10X Genomics, Inc. All rights reserved.
+
+//!
+//! JSON and NDJSON export of the enclone outputs, for tools that cannot read the proto file.
+//!
+//! The NDJSON form has one record per line.  The first record is the header, which contains
+//! everything except the clonotypes.  It is followed by one record per clonotype, in the order
+//! in which they appear in the proto file.
+//!
+
+use crate::proto_io::{read_proto_until_clonotypes, ClonotypeIter, Error};
+use crate::types::{DonorReference, EncloneOutputs, Metadata, UniversalReference};
+use serde::Serialize;
+use std::io::Write;
+use std::path::Path;
+
+/// The first record in NDJSON output.
+#[derive(Serialize)]
+pub struct JsonHeader<'a> {
+    pub version: &'a str,
+    pub metadata: &'a Metadata,
+    pub universal_reference: &'a UniversalReference,
+    pub donor_reference: &'a DonorReference,
+    pub num_clonotypes: u32,
+}
+
+impl<'a> From<&'a EncloneOutputs> for JsonHeader<'a> {
+    fn from(outputs: &'a EncloneOutputs) -> Self {
+        JsonHeader {
+            version: &outputs.version,
+            metadata: &outputs.metadata,
+            universal_reference: &outputs.universal_reference,
+            donor_reference: &outputs.donor_reference,
+            num_clonotypes: outputs.num_clonotypes,
+        }
+    }
+}
+
+/// Write the enclone outputs as a single JSON object.
+pub fn write_json(outputs: &EncloneOutputs, writer: impl Write) -> Result<(), Error> {
+    serde_json::to_writer(writer, outputs)?;
+    Ok(())
+}
+
+/// Write the enclone outputs as NDJSON.
+pub fn write_ndjson(outputs: &EncloneOutputs, mut writer: impl Write) -> Result<(), Error> {
+    serde_json::to_writer(&mut writer, &JsonHeader::from(outputs))?;
+    writeln!(writer)?;
+    for cl in &outputs.clonotypes {
+        serde_json::to_writer(&mut writer, cl)?;
+        writeln!(writer)?;
+    }
+    Ok(())
+}
+
+/// Convert a proto file to NDJSON, streaming through the clonotypes rather than loading them
+/// all into memory.  Returns the number of clonotypes written.
+pub fn proto_to_ndjson(path: impl AsRef<Path>, mut writer: impl Write) -> Result<u32, Error> {
+    let (header, _) = read_proto_until_clonotypes(&path)?;
+    serde_json::to_writer(&mut writer, &JsonHeader::from(&header))?;
+    writeln!(writer)?;
+    let mut n = 0;
+    for cl in ClonotypeIter::from_file(&path)? {
+        serde_json::to_writer(&mut writer, &cl?)?;
+        writeln!(writer)?;
+        n += 1;
+    }
+    writer.flush()?;
+    Ok(n)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{proto_to_ndjson, write_ndjson, JsonHeader};
+    use crate::proto_io::{write_proto, ClonotypeIter};
+    use crate::types::{
+        Clonotype, ClonotypeChain, DonorReference, DonorReferenceItem, EncloneOutputs, Metadata,
+        UniversalReference, UniversalReferenceItem,
+    };
+    use serde_json::Value;
+
+    #[test]
+    fn test_proto_to_ndjson() {
+        let clonotype = |frequency: u32, cdr3: &str| Clonotype {
+            chains: vec![ClonotypeChain {
+                chain_type: "IGH".to_string(),
+                aa_sequence: cdr3.as_bytes().to_vec(),
+                ..Default::default()
+            }],
+            frequency,
+            ..Default::default()
+        };
+        let outputs = EncloneOutputs {
+            version: crate::PROTO_VERSION.to_string(),
+            metadata: Metadata {
+                donors: vec!["d1".to_string()],
+                subsample_seed: Some(7),
+                ..Default::default()
+            },
+            universal_reference: UniversalReference {
+                items: vec![UniversalReferenceItem {
+                    ref_idx: 3,
+                    display_name: "IGHV3-23".to_string(),
+                    nt_sequence: b"GAGGTG".to_vec(),
+                    ..Default::default()
+                }],
+            },
+            donor_reference: DonorReference {
+                items: vec![DonorReferenceItem {
+                    universal_idx: 3,
+                    display_name: "IGHV3-23".to_string(),
+                    ..Default::default()
+                }],
+            },
+            num_clonotypes: 2,
+            clonotypes: vec![clonotype(3, "CARDYW"), clonotype(1, "CAKGW")],
+        };
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("outputs.proto");
+        write_proto(outputs.clone(), &path).unwrap();
+        let mut ndjson = Vec::<u8>::new();
+        assert_eq!(proto_to_ndjson(&path, &mut ndjson).unwrap(), 2);
+        let lines: Vec<Value> = String::from_utf8(ndjson.clone())
+            .unwrap()
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        assert_eq!(lines.len(), 3);
+
+        // The header has everything but the clonotypes.
+
+        let header = &lines[0];
+        assert_eq!(header["version"], crate::PROTO_VERSION);
+        assert_eq!(header["num_clonotypes"], 2);
+        assert_eq!(header["metadata"]["donors"][0], "d1");
+        assert_eq!(header["metadata"]["subsample_seed"], 7);
+        assert_eq!(header["universal_reference"]["items"][0]["ref_idx"], 3);
+        assert_eq!(header["donor_reference"]["items"][0]["universal_idx"], 3);
+        assert!(header.get("clonotypes").is_none());
+        assert_eq!(
+            *header,
+            serde_json::to_value(JsonHeader::from(&outputs)).unwrap()
+        );
+
+        // Each following line is one clonotype, in file order.
+
+        let clonotypes: Vec<Value> = ClonotypeIter::from_file(&path)
+            .unwrap()
+            .map(|cl| serde_json::to_value(cl.unwrap()).unwrap())
+            .collect();
+        assert_eq!(lines[1..], clonotypes);
+        assert_eq!(lines[1]["frequency"], 3);
+        assert_eq!(lines[2]["frequency"], 1);
+
+        // Writing from memory gives the same output.
+
+        let mut from_memory = Vec::<u8>::new();
+        write_ndjson(&outputs, &mut from_memory).unwrap();
+        assert_eq!(from_memory, ndjson);
+    }
+}
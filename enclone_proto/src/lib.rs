@@ -2,7 +2,17 @@
 //! Definition of the proto file created by enclone and readers/writers for the proto file
 //!
 
+pub mod json_io;
+pub mod proto_diff;
+pub mod proto_index;
 pub mod proto_io;
 pub mod types;
+pub mod version;
 
-pub const PROTO_VERSION: &str = "1.0.0";
+// Version history:
+// 1.0.0: initial version.
+// 1.1.0: add the optional Metadata fields subsample_seed and subsample_fraction.  Files written
+//        by 1.0.x decode with these fields as None, and 1.0.x readers ignore them, so files
+//        with the same major version remain readable in both directions.
+
+pub const PROTO_VERSION: &str = "1.1.0";
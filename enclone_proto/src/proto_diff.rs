@@ -0,0 +1,431 @@
+// Copyright (c) 2021 10X Genomics, Inc. All rights reserved.
+
+//!
+//! Comparison of the clonotypes in two proto files, for example from runs of two versions of
+//! enclone, or runs with different filters.
+//!
+//! Clonotypes are matched by their cells.  Two clonotypes, one from each file, are related if
+//! they share a cell barcode.  A clonotype related to more than one clonotype in the other file
+//! has been split (looking from the first file) or merged (looking from the second).  A pair of
+//! clonotypes related only to each other is matched, and for these we report cells that were
+//! added or lost, and changes to the chain annotations, meaning the V, J and C reference indices
+//! and the CDR3 amino acid sequence.
+//!
+//! Clonotypes are referred to by their index in the file, starting at zero.
+//!
+
+use crate::proto_io::{read_proto_until_clonotypes, ClonotypeIter, Error};
+use crate::types::{Clonotype, ClonotypeChain, UniversalReference};
+use serde::Serialize;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::io::Write;
+use std::path::Path;
+
+/// The annotation of one chain of a clonotype, as compared between files.
+#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+pub struct ChainSummary {
+    pub chain_type: String,
+    pub v_idx: u32,
+    pub j_idx: u32,
+    pub c_idx: Option<u32>,
+    pub v_name: String,
+    pub j_name: String,
+    pub c_name: Option<String>,
+    pub cdr3_aa: String,
+}
+
+impl ChainSummary {
+    fn new(chain: &ClonotypeChain, uref: &UniversalReference) -> ChainSummary {
+        let name = |idx: u32| {
+            uref.items
+                .get(idx as usize)
+                .map_or_else(|| format!("#{idx}"), |x| x.display_name.clone())
+        };
+        let aa_pos = |nt_pos: u32| (nt_pos.saturating_sub(chain.v_start) / 3) as usize;
+        let (start, stop) = (aa_pos(chain.cdr3_start), aa_pos(chain.cdr3_end));
+        let cdr3_aa = chain
+            .aa_sequence
+            .get(start..stop)
+            .map(|x| String::from_utf8_lossy(x).into_owned())
+            .unwrap_or_default();
+        ChainSummary {
+            chain_type: chain.chain_type.clone(),
+            v_idx: chain.v_idx,
+            j_idx: chain.j_idx,
+            c_idx: chain.c_idx,
+            v_name: name(chain.v_idx),
+            j_name: name(chain.j_idx),
+            c_name: chain.c_idx.map(name),
+            cdr3_aa,
+        }
+    }
+}
+
+impl std::fmt::Display for ChainSummary {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let c = self.c_name.as_deref().unwrap_or("-");
+        write!(
+            f,
+            "{} {} {} {} {}",
+            self.chain_type, self.v_name, self.j_name, c, self.cdr3_aa
+        )
+    }
+}
+
+// What is needed from each clonotype.  The chains are sorted so that their order does not
+// matter.
+
+struct ClonotypeSummary {
+    barcodes: Vec<String>,
+    chains: Vec<ChainSummary>,
+}
+
+impl ClonotypeSummary {
+    fn new(cl: &Clonotype, uref: &UniversalReference) -> ClonotypeSummary {
+        let barcodes = cl
+            .exact_clonotypes
+            .iter()
+            .flat_map(|ex| ex.cell_barcodes.iter().cloned())
+            .collect();
+        let mut chains: Vec<_> = cl
+            .chains
+            .iter()
+            .map(|c| ChainSummary::new(c, uref))
+            .collect();
+        chains.sort();
+        ClonotypeSummary { barcodes, chains }
+    }
+}
+
+fn read_summaries(path: &Path) -> Result<Vec<ClonotypeSummary>, Error> {
+    let (header, _) = read_proto_until_clonotypes(path)?;
+    ClonotypeIter::from_file(path)?
+        .map(|cl| Ok(ClonotypeSummary::new(&cl?, &header.universal_reference)))
+        .collect()
+}
+
+/// A pair of clonotypes related only to each other, which differ.
+#[derive(Clone, Debug, Serialize)]
+pub struct ChangedClonotype {
+    pub a: usize,
+    pub b: usize,
+    pub cells_added: Vec<String>,
+    pub cells_lost: Vec<String>,
+    /// The chains in each file, if they differ.
+    pub chains: Option<(Vec<ChainSummary>, Vec<ChainSummary>)>,
+}
+
+/// A clonotype related to more than one clonotype in the other file, together with those
+/// clonotypes and the number of cells shared with each.
+#[derive(Clone, Debug, Serialize)]
+pub struct Regrouping {
+    pub from: usize,
+    pub into: Vec<(usize, usize)>,
+}
+
+/// The differences between two proto files a and b.
+#[derive(Clone, Debug, Default, Serialize)]
+pub struct ProtoDiff {
+    pub clonotypes_a: usize,
+    pub clonotypes_b: usize,
+    pub cells_a: usize,
+    pub cells_b: usize,
+    /// Number of cells in b but not a, and in a but not b.
+    pub cells_added: usize,
+    pub cells_lost: usize,
+    /// Number of matched pairs having the same cells and chains.
+    pub unchanged: usize,
+    pub changed: Vec<ChangedClonotype>,
+    /// Clonotypes in a that are split across several clonotypes in b.
+    pub splits: Vec<Regrouping>,
+    /// Clonotypes in b that merge several clonotypes in a.
+    pub merges: Vec<Regrouping>,
+    /// Clonotypes in a sharing no cells with b, and in b sharing no cells with a.
+    pub lost: Vec<usize>,
+    pub new: Vec<usize>,
+}
+
+impl ProtoDiff {
+    pub fn is_empty(&self) -> bool {
+        self.cells_added == 0
+            && self.cells_lost == 0
+            && self.changed.is_empty()
+            && self.splits.is_empty()
+            && self.merges.is_empty()
+            && self.lost.is_empty()
+            && self.new.is_empty()
+    }
+}
+
+// Find, for each clonotype in x, the clonotypes in y that it shares cells with, and the number
+// of cells shared.
+
+fn relate(
+    x: &[ClonotypeSummary],
+    y_of_barcode: &HashMap<&str, usize>,
+) -> Vec<BTreeMap<usize, usize>> {
+    x.iter()
+        .map(|cl| {
+            let mut to = BTreeMap::<usize, usize>::new();
+            for b in &cl.barcodes {
+                if let Some(&j) = y_of_barcode.get(b.as_str()) {
+                    *to.entry(j).or_default() += 1;
+                }
+            }
+            to
+        })
+        .collect()
+}
+
+fn barcode_map(x: &[ClonotypeSummary]) -> HashMap<&str, usize> {
+    let mut m = HashMap::new();
+    for (i, cl) in x.iter().enumerate() {
+        for b in &cl.barcodes {
+            m.insert(b.as_str(), i);
+        }
+    }
+    m
+}
+
+fn diff_summaries(a: &[ClonotypeSummary], b: &[ClonotypeSummary]) -> ProtoDiff {
+    let (a_of_barcode, b_of_barcode) = (barcode_map(a), barcode_map(b));
+    let a_to_b = relate(a, &b_of_barcode);
+    let b_to_a = relate(b, &a_of_barcode);
+    let mut diff = ProtoDiff {
+        clonotypes_a: a.len(),
+        clonotypes_b: b.len(),
+        cells_a: a_of_barcode.len(),
+        cells_b: b_of_barcode.len(),
+        cells_added: b_of_barcode
+            .keys()
+            .filter(|x| !a_of_barcode.contains_key(*x))
+            .count(),
+        cells_lost: a_of_barcode
+            .keys()
+            .filter(|x| !b_of_barcode.contains_key(*x))
+            .count(),
+        ..Default::default()
+    };
+    let regroupings = |rel: &[BTreeMap<usize, usize>]| -> Vec<Regrouping> {
+        rel.iter()
+            .enumerate()
+            .filter(|(_, to)| to.len() > 1)
+            .map(|(i, to)| Regrouping {
+                from: i,
+                into: to.iter().map(|(&j, &n)| (j, n)).collect(),
+            })
+            .collect()
+    };
+    diff.splits = regroupings(&a_to_b);
+    diff.merges = regroupings(&b_to_a);
+    diff.lost = (0..a.len()).filter(|&i| a_to_b[i].is_empty()).collect();
+    diff.new = (0..b.len()).filter(|&j| b_to_a[j].is_empty()).collect();
+
+    // Compare matched pairs.
+
+    for (i, to) in a_to_b.iter().enumerate() {
+        if to.len() != 1 {
+            continue;
+        }
+        let j = *to.keys().next().unwrap();
+        if b_to_a[j].len() != 1 {
+            continue;
+        }
+        let (x, y) = (&a[i], &b[j]);
+        let xs: HashSet<&String> = x.barcodes.iter().collect();
+        let ys: HashSet<&String> = y.barcodes.iter().collect();
+        let mut cells_added: Vec<String> = y
+            .barcodes
+            .iter()
+            .filter(|c| !xs.contains(c))
+            .cloned()
+            .collect();
+        let mut cells_lost: Vec<String> = x
+            .barcodes
+            .iter()
+            .filter(|c| !ys.contains(c))
+            .cloned()
+            .collect();
+        cells_added.sort();
+        cells_lost.sort();
+        let chains = (x.chains != y.chains).then(|| (x.chains.clone(), y.chains.clone()));
+        if cells_added.is_empty() && cells_lost.is_empty() && chains.is_none() {
+            diff.unchanged += 1;
+        } else {
+            diff.changed.push(ChangedClonotype {
+                a: i,
+                b: j,
+                cells_added,
+                cells_lost,
+                chains,
+            });
+        }
+    }
+    diff
+}
+
+/// Compare the clonotypes in two proto files.
+pub fn diff_protos(a: impl AsRef<Path>, b: impl AsRef<Path>) -> Result<ProtoDiff, Error> {
+    let a = read_summaries(a.as_ref())?;
+    let b = read_summaries(b.as_ref())?;
+    Ok(diff_summaries(&a, &b))
+}
+
+/// Write a human-readable report of the differences.
+pub fn write_diff_report(diff: &ProtoDiff, mut w: impl Write) -> Result<(), Error> {
+    writeln!(
+        w,
+        "clonotypes: {} in a, {} in b",
+        diff.clonotypes_a, diff.clonotypes_b
+    )?;
+    writeln!(w, "cells: {} in a, {} in b", diff.cells_a, diff.cells_b)?;
+    writeln!(
+        w,
+        "cells added: {}, cells lost: {}",
+        diff.cells_added, diff.cells_lost
+    )?;
+    writeln!(
+        w,
+        "matched clonotypes: {} unchanged, {} changed",
+        diff.unchanged,
+        diff.changed.len()
+    )?;
+    writeln!(
+        w,
+        "splits: {}, merges: {}, lost clonotypes: {}, new clonotypes: {}",
+        diff.splits.len(),
+        diff.merges.len(),
+        diff.lost.len(),
+        diff.new.len()
+    )?;
+    let list = |x: &[(usize, usize)], file: &str| -> String {
+        x.iter()
+            .map(|(j, n)| format!("{file}[{j}] ({n} cells)"))
+            .collect::<Vec<_>>()
+            .join(", ")
+    };
+    for s in &diff.splits {
+        writeln!(w, "\nsplit: a[{}] -> {}", s.from, list(&s.into, "b"))?;
+    }
+    for m in &diff.merges {
+        writeln!(w, "\nmerge: {} -> b[{}]", list(&m.into, "a"), m.from)?;
+    }
+    for c in &diff.changed {
+        writeln!(w, "\nchanged: a[{}] -> b[{}]", c.a, c.b)?;
+        if !c.cells_added.is_empty() {
+            writeln!(w, "    cells added: {}", c.cells_added.join(" "))?;
+        }
+        if !c.cells_lost.is_empty() {
+            writeln!(w, "    cells lost: {}", c.cells_lost.join(" "))?;
+        }
+        if let Some((x, y)) = &c.chains {
+            for chain in x {
+                writeln!(w, "    a chain: {chain}")?;
+            }
+            for chain in y {
+                writeln!(w, "    b chain: {chain}")?;
+            }
+        }
+    }
+    if !diff.lost.is_empty() {
+        let lost: Vec<String> = diff.lost.iter().map(|i| format!("a[{i}]")).collect();
+        writeln!(w, "\nlost: {}", lost.join(" "))?;
+    }
+    if !diff.new.is_empty() {
+        let new: Vec<String> = diff.new.iter().map(|j| format!("b[{j}]")).collect();
+        writeln!(w, "\nnew: {}", new.join(" "))?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{diff_summaries, write_diff_report, ChainSummary, ClonotypeSummary};
+
+    fn cl(barcodes: &[&str]) -> ClonotypeSummary {
+        ClonotypeSummary {
+            barcodes: barcodes.iter().map(ToString::to_string).collect(),
+            chains: Vec::new(),
+        }
+    }
+
+    fn chain(v_idx: u32, cdr3_aa: &str) -> ChainSummary {
+        ChainSummary {
+            chain_type: "IGH".to_string(),
+            v_idx,
+            j_idx: 2,
+            c_idx: Some(3),
+            v_name: format!("IGHV{v_idx}"),
+            j_name: "IGHJ4".to_string(),
+            c_name: Some("IGHM".to_string()),
+            cdr3_aa: cdr3_aa.to_string(),
+        }
+    }
+
+    #[test]
+    fn test_diff_summaries() {
+        let a = [
+            cl(&["1", "2", "3"]),
+            cl(&["4", "5"]),
+            cl(&["6"]),
+            cl(&["9"]),
+        ];
+        let b = [
+            cl(&["1", "2"]),
+            cl(&["3"]),
+            cl(&["4", "5", "6"]),
+            cl(&["7", "8"]),
+        ];
+        let diff = diff_summaries(&a, &b);
+        assert_eq!(diff.cells_added, 2);
+        assert_eq!(diff.cells_lost, 1);
+        assert_eq!(diff.splits.len(), 1);
+        assert_eq!(diff.splits[0].into, vec![(0, 2), (1, 1)]);
+        assert_eq!(diff.merges.len(), 1);
+        assert_eq!(diff.merges[0].into, vec![(1, 2), (2, 1)]);
+        assert_eq!(diff.lost, vec![3]);
+        assert_eq!(diff.new, vec![3]);
+        assert_eq!(diff.unchanged, 0);
+        assert!(diff.changed.is_empty());
+    }
+
+    #[test]
+    fn test_changed_chains() {
+        let with_chains = |barcodes: &[&str], chains: Vec<ChainSummary>| ClonotypeSummary {
+            chains,
+            ..cl(barcodes)
+        };
+        let a = [
+            with_chains(&["1", "2"], vec![chain(1, "CARDYW")]),
+            with_chains(&["3"], vec![chain(1, "CAKW")]),
+        ];
+        let b = [
+            with_chains(&["1", "2", "4"], vec![chain(5, "CARDFW")]),
+            with_chains(&["3"], vec![chain(1, "CAKW")]),
+        ];
+        let diff = diff_summaries(&a, &b);
+        assert_eq!(diff.unchanged, 1);
+        assert_eq!(diff.changed.len(), 1);
+        let c = &diff.changed[0];
+        assert_eq!((c.a, c.b), (0, 0));
+        assert_eq!(c.cells_added, ["4"]);
+        assert!(c.cells_lost.is_empty());
+        assert_eq!(
+            c.chains,
+            Some((vec![chain(1, "CARDYW")], vec![chain(5, "CARDFW")]))
+        );
+        let mut report = Vec::<u8>::new();
+        write_diff_report(&diff, &mut report).unwrap();
+        let report = String::from_utf8(report).unwrap();
+        assert!(report.contains("matched clonotypes: 1 unchanged, 1 changed\n"));
+        let changed = [
+            "",
+            "changed: a[0] -> b[0]",
+            "    cells added: 4",
+            "    a chain: IGH IGHV1 IGHJ4 IGHM CARDYW",
+            "    b chain: IGH IGHV5 IGHJ4 IGHM CARDFW",
+            "",
+        ];
+        assert!(report.ends_with(&changed.join("\n")));
+    }
+}
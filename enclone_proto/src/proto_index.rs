@@ -0,0 +1,208 @@
+// Copyright (c) 2021 10X Genomics, Inc. All rights reserved.
+
+//!
+//! Random access to the clonotypes in a proto file.
+//!
+//! If asked to, by `write_proto_indexed` or `ProtoStreamWriter::with_index`, the writer puts a
+//! sidecar index `{path}.idx` next to `{path}`, holding the byte offset of each clonotype.  The
+//! proto file itself is unchanged, so existing readers are unaffected.
+//!
+//! ## Index Format
+//! ```text
+//! +-----------+-------------------+-------------+------------------------------+
+//! | Magic     | Proto file length | Count (N)   | Offset of clonotype 0..N-1   |
+//! | [8 bytes] | [u64]             | [u32]       | [N x u64]                    |
+//! +-----------+-------------------+-------------+------------------------------+
+//! ```
+//! All integers are big endian.  The proto file length is used to detect an index that does
+//! not belong to the proto file next to it.  If the index is missing or does not match, it is
+//! rebuilt in memory by skipping through the proto file once.
+//!
+
+use crate::proto_io::{read_header, Error, ProtoReader};
+use crate::types::{Clonotype, EncloneOutputs};
+use byteorder::{BigEndian, ReadBytesExt, WriteBytesExt};
+use std::ffi::OsString;
+use std::fs::File;
+use std::io::{BufReader, BufWriter, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+
+const INDEX_MAGIC: &[u8; 8] = b"ENCLIDX1";
+
+/// The path of the sidecar index for a proto file.
+pub fn index_path(path: impl AsRef<Path>) -> PathBuf {
+    let mut p = OsString::from(path.as_ref().as_os_str());
+    p.push(".idx");
+    PathBuf::from(p)
+}
+
+/// Write the sidecar index for the proto file at `path`, whose length is `proto_len`.
+pub fn write_index(path: impl AsRef<Path>, proto_len: u64, offsets: &[u64]) -> Result<(), Error> {
+    let mut w = BufWriter::new(File::create(index_path(path))?);
+    w.write_all(INDEX_MAGIC)?;
+    w.write_u64::<BigEndian>(proto_len)?;
+    w.write_u32::<BigEndian>(offsets.len() as u32)?;
+    for &x in offsets {
+        w.write_u64::<BigEndian>(x)?;
+    }
+    w.flush()?;
+    Ok(())
+}
+
+// Read the sidecar index, returning None if it is absent or does not match the proto file.
+
+fn read_index(path: &Path, num_clonotypes: u32) -> Option<Vec<u64>> {
+    let proto_len = std::fs::metadata(path).ok()?.len();
+    let mut r = BufReader::new(File::open(index_path(path)).ok()?);
+    let mut magic = [0; 8];
+    r.read_exact(&mut magic).ok()?;
+    if &magic != INDEX_MAGIC
+        || r.read_u64::<BigEndian>().ok()? != proto_len
+        || r.read_u32::<BigEndian>().ok()? != num_clonotypes
+    {
+        return None;
+    }
+    (0..num_clonotypes)
+        .map(|_| r.read_u64::<BigEndian>().ok())
+        .collect()
+}
+
+/// Reader that fetches any clonotype by its index in the file, without reading the ones before.
+pub struct ProtoRandomReader<R: Read + Seek> {
+    header: EncloneOutputs,
+    offsets: Vec<u64>,
+    proto_reader: ProtoReader<R>,
+}
+
+impl ProtoRandomReader<BufReader<File>> {
+    /// Open a proto file, using its sidecar index if there is a valid one.
+    pub fn open(path: impl AsRef<Path>) -> Result<Self, Error> {
+        let path = path.as_ref();
+        let mut proto_reader = ProtoReader::from_reader(BufReader::new(File::open(path)?));
+        let header = read_header(&mut proto_reader)?;
+        let offsets = read_index(path, header.num_clonotypes);
+        ProtoRandomReader::with_header(header, proto_reader, offsets)
+    }
+}
+
+impl<R: Read + Seek> ProtoRandomReader<R> {
+    /// Create from a reader positioned at the start of a proto file, building the index by
+    /// scanning the file.
+    pub fn from_reader(reader: R) -> Result<Self, Error> {
+        let mut proto_reader = ProtoReader::from_reader(reader);
+        let header = read_header(&mut proto_reader)?;
+        ProtoRandomReader::with_header(header, proto_reader, None)
+    }
+
+    fn with_header(
+        header: EncloneOutputs,
+        mut proto_reader: ProtoReader<R>,
+        offsets: Option<Vec<u64>>,
+    ) -> Result<Self, Error> {
+        let offsets = match offsets {
+            Some(offsets) => offsets,
+            None => {
+                // Build the index.  The reader is positioned at the first clonotype.
+                let mut offsets = Vec::with_capacity(header.num_clonotypes as usize);
+                for _ in 0..header.num_clonotypes {
+                    offsets.push(proto_reader.get_mut().stream_position()?);
+                    proto_reader.skip()?;
+                }
+                offsets
+            }
+        };
+        Ok(ProtoRandomReader {
+            header,
+            offsets,
+            proto_reader,
+        })
+    }
+
+    /// The fields before the clonotypes.  The clonotypes vector is empty.
+    pub fn header(&self) -> &EncloneOutputs {
+        &self.header
+    }
+
+    /// The byte offset of each clonotype.
+    pub fn offsets(&self) -> &[u64] {
+        &self.offsets
+    }
+
+    pub fn len(&self) -> usize {
+        self.offsets.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.offsets.is_empty()
+    }
+
+    /// Read clonotype n.
+    pub fn get(&mut self, n: usize) -> Result<Clonotype, Error> {
+        let Some(&offset) = self.offsets.get(n) else {
+            return Err(Error::OutOfRange {
+                index: n,
+                len: self.offsets.len(),
+            });
+        };
+        self.proto_reader.get_mut().seek(SeekFrom::Start(offset))?;
+        self.proto_reader.read_and_decode()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{index_path, ProtoRandomReader};
+    use crate::proto_io::{write_proto, write_proto_indexed, Error};
+    use crate::types::{Clonotype, EncloneOutputs};
+    use std::fs;
+    use std::io::Cursor;
+
+    #[test]
+    fn test_random_reader() {
+        let outputs = |frequencies: &[u32]| EncloneOutputs {
+            version: crate::PROTO_VERSION.to_string(),
+            clonotypes: frequencies
+                .iter()
+                .map(|&frequency| Clonotype {
+                    frequency,
+                    ..Default::default()
+                })
+                .collect(),
+            ..Default::default()
+        };
+        let dir = tempfile::tempdir().unwrap();
+        let indexed = dir.path().join("random_indexed.proto");
+        let plain = dir.path().join("random_plain.proto");
+
+        // With the sidecar index.
+
+        write_proto_indexed(outputs(&[5, 3, 2]), &indexed).unwrap();
+        assert!(index_path(&indexed).exists());
+        let mut r = ProtoRandomReader::open(&indexed).unwrap();
+        assert_eq!(r.len(), 3);
+        assert_eq!(r.get(2).unwrap().frequency, 2);
+        assert_eq!(r.get(0).unwrap().frequency, 5);
+        assert!(matches!(
+            r.get(3),
+            Err(Error::OutOfRange { index: 3, len: 3 })
+        ));
+
+        // Without an index, it is built by scanning, and gives the same offsets.
+
+        write_proto(outputs(&[5, 3, 2]), &plain).unwrap();
+        assert!(!index_path(&plain).exists());
+        let mut s = ProtoRandomReader::open(&plain).unwrap();
+        assert_eq!(s.offsets(), r.offsets());
+        assert_eq!(s.get(1).unwrap().frequency, 3);
+        let mut t = ProtoRandomReader::from_reader(Cursor::new(fs::read(&plain).unwrap())).unwrap();
+        assert_eq!(t.get(2).unwrap().frequency, 2);
+
+        // An index left over from a different file is ignored.
+
+        fs::copy(index_path(&indexed), index_path(&plain)).unwrap();
+        write_proto(outputs(&[300, 200, 100, 1]), &plain).unwrap();
+        let mut s = ProtoRandomReader::open(&plain).unwrap();
+        assert_eq!(s.len(), 4);
+        assert_eq!(s.get(3).unwrap().frequency, 1);
+    }
+}
@@ -19,12 +19,14 @@
 //! - `Length` is an unsigned 32 bit integer stored in **Big endian** order.
 //! - If there are multiple messages, they are stored consecutively following the same format.
 
+use crate::proto_index::write_index;
 use crate::types::{Clonotype, EncloneOutputs};
+use crate::version::ProtoVersion;
 use byteorder::{BigEndian, ReadBytesExt, WriteBytesExt};
 use prost::Message;
 use std::fs::File;
 use std::io::{BufReader, BufWriter, Read, Write};
-use std::path::Path;
+use std::path::{Path, PathBuf};
 
 const BUFFER_CAPACITY: usize = 1_000_000;
 
@@ -41,6 +43,30 @@ pub enum Error {
 
     #[error(transparent)]
     ProtoEncode(#[from] prost::EncodeError),
+
+    #[error(transparent)]
+    Json(#[from] serde_json::Error),
+
+    #[error("Unable to parse proto version string {0:?}")]
+    BadVersion(String),
+
+    #[error(
+        "This file was written with proto version {found}, but this reader only supports \
+         versions up to {supported}.  Please use a newer version of enclone to read it."
+    )]
+    IncompatibleVersion {
+        found: ProtoVersion,
+        supported: ProtoVersion,
+    },
+
+    #[error("Clonotype {index} requested, but there are only {len}")]
+    OutOfRange { index: usize, len: usize },
+
+    #[error("The file says it has {declared} clonotypes, but {found} are present")]
+    CountMismatch { declared: u32, found: u32 },
+
+    #[error("The file has data after the last clonotype that is not a clonotype")]
+    TrailingData,
 }
 
 /// A helper struct to write a length delimited protobuf encoded message into the inner `writer`.
@@ -115,6 +141,10 @@ impl<R: Read> ProtoReader<R> {
         let decoded_len = self.decode_buffer.as_slice().read_u32::<BigEndian>()?;
         self.read_exact(decoded_len as usize)
     }
+    /// Mutable access to the inner reader, e.g. to seek.
+    pub fn get_mut(&mut self) -> &mut R {
+        &mut self.reader
+    }
     pub fn read_and_decode<M>(&mut self) -> Result<M, Error>
     where
         M: Message + Default,
@@ -159,36 +189,123 @@ impl<R: Read> ProtoReader<R> {
 ///     +------------+----------------------------------------+
 /// ```
 /// The newlines are only showed for illustration
+///
+/// No index is written, see `write_proto_indexed`.
 pub fn write_proto(enclone_outputs: EncloneOutputs, path: impl AsRef<Path>) -> Result<(), Error> {
-    let writer = BufWriter::new(File::create(path)?);
-    let mut proto_writer = ProtoWriter::with_writer(writer);
-
-    // Write the version
-    proto_writer.encode_and_write(enclone_outputs.version)?;
-    // Write the metadata
-    proto_writer.encode_and_write(enclone_outputs.metadata)?;
-    // Write the universal reference
-    proto_writer.encode_and_write(enclone_outputs.universal_reference)?;
-    // Write the donor reference
-    proto_writer.encode_and_write(enclone_outputs.donor_reference)?;
-    // Write the number of clonotypes. Not bothering to write this raw
-    proto_writer.encode_and_write(enclone_outputs.clonotypes.len() as u32)?;
-    for cl in enclone_outputs.clonotypes {
-        proto_writer.encode_and_write(cl)?;
-    }
-    Ok(())
+    write_proto_impl(enclone_outputs, path, false)
 }
 
-/// A read that mirrors the write above. The fields until the list of clonotypes are read here.
-/// The clonotypes are assigned an empty vector.
-pub fn read_proto_until_clonotypes(
+/// As `write_proto`, but also write the byte offset of each clonotype to the sidecar index file
+/// `{path}.idx`, see `proto_index`.
+pub fn write_proto_indexed(
+    enclone_outputs: EncloneOutputs,
     path: impl AsRef<Path>,
-) -> Result<(EncloneOutputs, ProtoReader<impl Read>), Error> {
-    let reader = BufReader::new(File::open(path)?);
-    let mut proto_reader = ProtoReader::from_reader(reader);
+) -> Result<(), Error> {
+    write_proto_impl(enclone_outputs, path, true)
+}
+
+fn write_proto_impl(
+    mut enclone_outputs: EncloneOutputs,
+    path: impl AsRef<Path>,
+    index: bool,
+) -> Result<(), Error> {
+    let clonotypes = std::mem::take(&mut enclone_outputs.clonotypes);
+    enclone_outputs.num_clonotypes = clonotypes.len() as u32;
+    let mut writer = ProtoStreamWriter::create(path, enclone_outputs)?;
+    if index {
+        writer = writer.with_index();
+    }
+    for cl in clonotypes {
+        writer.write_clonotype(cl)?;
+    }
+    writer.finish()
+}
+
+/// Writer for a proto file whose clonotypes are supplied one at a time, so that they need not
+/// all be held in memory.  The fields before the clonotypes are written when it is created.
+/// Because the number of clonotypes is written before them, it must be known up front, and
+/// `finish` checks that exactly that many were written.  The file layout is the same as for
+/// `write_proto`.  A sidecar index is written only if `with_index` is called.
+pub struct ProtoStreamWriter {
+    path: PathBuf,
+    index: bool,
+    proto_writer: ProtoWriter<BufWriter<File>>,
+    pos: u64,
+    num_clonotypes: u32,
+    offsets: Vec<u64>,
+}
+
+impl ProtoStreamWriter {
+    /// Create the file and write the fields of `header` before the clonotypes.  The clonotypes
+    /// in `header` are ignored; `header.num_clonotypes` must be the number that will be written.
+    pub fn create(path: impl AsRef<Path>, header: EncloneOutputs) -> Result<Self, Error> {
+        let path = path.as_ref().to_path_buf();
+        let mut proto_writer = ProtoWriter::with_writer(BufWriter::new(File::create(&path)?));
+        let mut pos = 0;
+        // Write the version
+        pos += proto_writer.encode_and_write(header.version)?;
+        // Write the metadata
+        pos += proto_writer.encode_and_write(header.metadata)?;
+        // Write the universal reference
+        pos += proto_writer.encode_and_write(header.universal_reference)?;
+        // Write the donor reference
+        pos += proto_writer.encode_and_write(header.donor_reference)?;
+        // Write the number of clonotypes. Not bothering to write this raw
+        pos += proto_writer.encode_and_write(header.num_clonotypes)?;
+        Ok(ProtoStreamWriter {
+            path,
+            index: false,
+            proto_writer,
+            pos: pos as u64,
+            num_clonotypes: header.num_clonotypes,
+            offsets: Vec::with_capacity(header.num_clonotypes as usize),
+        })
+    }
+
+    /// Also write the sidecar index `{path}.idx` when finished, see `proto_index`.
+    pub fn with_index(mut self) -> Self {
+        self.index = true;
+        self
+    }
 
-    // Read the version
-    let version = proto_reader.read_and_decode()?;
+    /// Append the next clonotype.
+    pub fn write_clonotype(&mut self, cl: Clonotype) -> Result<(), Error> {
+        if self.offsets.len() == self.num_clonotypes as usize {
+            return Err(Error::CountMismatch {
+                declared: self.num_clonotypes,
+                found: self.num_clonotypes + 1,
+            });
+        }
+        self.offsets.push(self.pos);
+        self.pos += self.proto_writer.encode_and_write(cl)? as u64;
+        Ok(())
+    }
+
+    /// Flush the file, and write its index if requested.  Fails if the number of clonotypes
+    /// written is not the number declared.
+    pub fn finish(self) -> Result<(), Error> {
+        if self.offsets.len() != self.num_clonotypes as usize {
+            return Err(Error::CountMismatch {
+                declared: self.num_clonotypes,
+                found: self.offsets.len() as u32,
+            });
+        }
+        self.proto_writer.finish().flush()?;
+        if self.index {
+            write_index(&self.path, self.pos, &self.offsets)?;
+        }
+        Ok(())
+    }
+}
+
+// Read the fields before the clonotypes, checking the version.
+
+pub(crate) fn read_header<R: Read>(
+    proto_reader: &mut ProtoReader<R>,
+) -> Result<EncloneOutputs, Error> {
+    // Read and check the version
+    let version: String = proto_reader.read_and_decode()?;
+    ProtoVersion::negotiate(&version)?;
     // Read the metadata
     let metadata = proto_reader.read_and_decode()?;
     // Read the universal reference
@@ -198,17 +315,26 @@ pub fn read_proto_until_clonotypes(
     // Number of clonotypes
     let num_clonotypes: u32 = proto_reader.read_and_decode()?;
 
-    Ok((
-        EncloneOutputs {
-            version,
-            metadata,
-            universal_reference,
-            donor_reference,
-            num_clonotypes,
-            clonotypes: Vec::new(),
-        },
-        proto_reader,
-    ))
+    Ok(EncloneOutputs {
+        version,
+        metadata,
+        universal_reference,
+        donor_reference,
+        num_clonotypes,
+        clonotypes: Vec::new(),
+    })
+}
+
+/// A read that mirrors the write above. The fields until the list of clonotypes are read here.
+/// The clonotypes are assigned an empty vector.  Fails if the file was written with an
+/// incompatible version.
+pub fn read_proto_until_clonotypes(
+    path: impl AsRef<Path>,
+) -> Result<(EncloneOutputs, ProtoReader<impl Read>), Error> {
+    let reader = BufReader::new(File::open(path)?);
+    let mut proto_reader = ProtoReader::from_reader(reader);
+    let output = read_header(&mut proto_reader)?;
+    Ok((output, proto_reader))
 }
 
 /// A read that mirrors the write above. It is possible to stream through the
@@ -223,7 +349,8 @@ pub fn read_proto(path: impl AsRef<Path>) -> Result<EncloneOutputs, Error> {
     Ok(output)
 }
 
-/// Iterator over clonotypes
+/// Iterator over clonotypes.  Each item is a Result, so that a corrupt or truncated file is
+/// reported rather than causing a panic.  Iteration stops after the first error.
 pub struct ClonotypeIter<R: Read> {
     index: u32,
     num_clonotypes: u32,
@@ -239,8 +366,10 @@ impl ClonotypeIter<BufReader<File>> {
 impl<R: Read> ClonotypeIter<R> {
     pub fn from_reader(reader: R) -> Result<Self, Error> {
         let mut proto_reader = ProtoReader::from_reader(reader);
-        // Skip version, metadata, universal reference, donor reference
-        for _ in 0..4 {
+        // Check the version, then skip metadata, universal reference, donor reference
+        let version: String = proto_reader.read_and_decode()?;
+        ProtoVersion::negotiate(&version)?;
+        for _ in 0..3 {
             proto_reader.skip()?;
         }
         let num_clonotypes: u32 = proto_reader.read_and_decode()?;
@@ -250,21 +379,133 @@ impl<R: Read> ClonotypeIter<R> {
             proto_reader,
         })
     }
+
+    /// The number of clonotypes the file says it has.
+    pub fn num_clonotypes(&self) -> u32 {
+        self.num_clonotypes
+    }
+
+    /// Convenience wrapper that yields clonotypes directly, and panics if one cannot be read.
+    pub fn unwrapped(self) -> impl Iterator<Item = Clonotype> {
+        self.map(|cl| match cl {
+            Ok(cl) => cl,
+            Err(e) => panic!("Failed to decode clonotype due to {e}"),
+        })
+    }
 }
 
 impl<R: Read> Iterator for ClonotypeIter<R> {
-    type Item = Clonotype;
+    type Item = Result<Clonotype, Error>;
 
     fn next(&mut self) -> Option<Self::Item> {
-        if self.index < self.num_clonotypes {
-            let cl = match self.proto_reader.read_and_decode() {
-                Ok(c) => c,
-                Err(e) => panic!("Failed to decode clonotype due to {e}"),
-            };
-            self.index += 1;
-            Some(cl)
-        } else {
-            None
+        if self.index >= self.num_clonotypes {
+            return None;
+        }
+        match self.proto_reader.read_and_decode() {
+            Ok(cl) => {
+                self.index += 1;
+                Some(Ok(cl))
+            }
+            Err(e) => {
+                self.index = self.num_clonotypes;
+                Some(Err(e))
+            }
         }
     }
 }
+
+/// Check that a proto file is complete and well formed: every clonotype decodes, the number of
+/// clonotypes present is the number declared in the file, and nothing follows the last one.
+/// Returns the number of clonotypes.
+pub fn verify_proto(path: impl AsRef<Path>) -> Result<u32, Error> {
+    // A length prefix that is missing entirely means the file ended at a message boundary.
+
+    let at_end = |e: &Error| {
+        matches!(
+            e,
+            Error::Truncated {
+                expected: 4,
+                got: 0
+            }
+        )
+    };
+    let mut iter = ClonotypeIter::from_file(path)?;
+    let declared = iter.num_clonotypes;
+    let mut found = 0;
+    for cl in iter.by_ref() {
+        match cl {
+            Ok(_) => found += 1,
+            Err(e) if at_end(&e) => return Err(Error::CountMismatch { declared, found }),
+            Err(e) => return Err(e),
+        }
+    }
+
+    // Anything left over is either extra clonotypes or garbage.
+
+    loop {
+        match iter.proto_reader.skip() {
+            Ok(()) => found += 1,
+            Err(e) if at_end(&e) => break,
+            Err(_) => return Err(Error::TrailingData),
+        }
+    }
+    if found != declared {
+        return Err(Error::CountMismatch { declared, found });
+    }
+    Ok(declared)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{verify_proto, write_proto, ClonotypeIter, Error};
+    use crate::types::{Clonotype, EncloneOutputs};
+    use std::fs::{self, OpenOptions};
+    use std::io::Write;
+
+    #[test]
+    fn test_verify_proto() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("verify.proto");
+        let outputs = EncloneOutputs {
+            version: crate::PROTO_VERSION.to_string(),
+            num_clonotypes: 2,
+            clonotypes: vec![
+                Clonotype {
+                    frequency: 3,
+                    ..Default::default()
+                },
+                Clonotype {
+                    frequency: 2,
+                    ..Default::default()
+                },
+            ],
+            ..Default::default()
+        };
+        write_proto(outputs, &path).unwrap();
+        assert_eq!(verify_proto(&path).unwrap(), 2);
+
+        // Trailing garbage.
+
+        OpenOptions::new()
+            .append(true)
+            .open(&path)
+            .unwrap()
+            .write_all(b"xyz")
+            .unwrap();
+        assert!(matches!(verify_proto(&path), Err(Error::TrailingData)));
+
+        // Truncated in the middle of the last clonotype.
+
+        let len = fs::metadata(&path).unwrap().len();
+        OpenOptions::new()
+            .write(true)
+            .open(&path)
+            .unwrap()
+            .set_len(len - 4)
+            .unwrap();
+        assert!(verify_proto(&path).is_err());
+        let results: Vec<_> = ClonotypeIter::from_file(&path).unwrap().collect();
+        assert_eq!(results.len(), 2);
+        assert!(results[0].is_ok() && results[1].is_err());
+    }
+}
@@ -0,0 +1,123 @@
+// Copyright (c) 2021 10X Genomics, Inc. All rights reserved.
+
+//!
+//! Versioning of the proto file.
+//!
+//! The first message in a proto file is the version string of the format it was written with.
+//! A reader accepts files whose major version is at most that of `PROTO_VERSION`, and rejects
+//! files written by a newer major version, whose layout it cannot know.
+//!
+//! Files written with an older version are read as is.  Fields that did not exist when a file
+//! was written decode to their protobuf defaults: optional fields are None, repeated fields are
+//! empty, and numeric fields, such as `dna_percent` and `aa_percent` in files written before
+//! 1.0, are zero.  No values are rewritten, so a reader of old files should treat those defaults
+//! as unknown.  When a field is added to the format, the minor version should be bumped.
+//!
+
+use crate::proto_io::Error;
+use crate::PROTO_VERSION;
+use std::fmt;
+
+/// A parsed version string, of the form major.minor.patch.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub struct ProtoVersion {
+    pub major: u32,
+    pub minor: u32,
+    pub patch: u32,
+}
+
+impl fmt::Display for ProtoVersion {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}.{}.{}", self.major, self.minor, self.patch)
+    }
+}
+
+impl ProtoVersion {
+    pub fn parse(version: &str) -> Result<ProtoVersion, Error> {
+        let bad = || Error::BadVersion(version.to_string());
+        let mut fields = version.split('.').map(str::parse::<u32>);
+        let mut next =
+            || -> Result<u32, Error> { fields.next().ok_or_else(bad)?.map_err(|_| bad()) };
+        let v = ProtoVersion {
+            major: next()?,
+            minor: next()?,
+            patch: next()?,
+        };
+        if fields.next().is_some() {
+            return Err(bad());
+        }
+        Ok(v)
+    }
+
+    /// The version that this code writes.
+    pub fn current() -> ProtoVersion {
+        ProtoVersion::parse(PROTO_VERSION).unwrap()
+    }
+
+    /// Check that a file with this version can be read.
+    pub fn check(&self) -> Result<(), Error> {
+        let current = ProtoVersion::current();
+        if self.major > current.major {
+            return Err(Error::IncompatibleVersion {
+                found: *self,
+                supported: current,
+            });
+        }
+        Ok(())
+    }
+
+    /// Parse and check a version string read from a file.
+    pub fn negotiate(version: &str) -> Result<ProtoVersion, Error> {
+        let v = ProtoVersion::parse(version)?;
+        v.check()?;
+        Ok(v)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::ProtoVersion;
+    use crate::types::ExactSubClonotypeChain;
+    use prost::Message;
+
+    #[test]
+    fn test_version_negotiation() {
+        assert!(ProtoVersion::negotiate(crate::PROTO_VERSION).is_ok());
+        assert!(ProtoVersion::negotiate("0.9.3").is_ok());
+        assert!(ProtoVersion::negotiate("1.7.0").is_ok());
+        assert!(ProtoVersion::negotiate("2.0.0").is_err());
+        assert!(ProtoVersion::parse("1.0").is_err());
+        assert!(ProtoVersion::parse("1.0.0.0").is_err());
+        assert!(ProtoVersion::parse("1.x.0").is_err());
+    }
+
+    // A chain as written by an older version, lacking the fields added since.
+
+    #[derive(Clone, PartialEq, Message)]
+    struct OldChain {
+        #[prost(bytes = "vec", required, tag = "1")]
+        nt_sequence: Vec<u8>,
+        #[prost(uint32, required, tag = "3")]
+        v_start: u32,
+        #[prost(uint32, repeated, packed = "false", tag = "8")]
+        umi_counts: Vec<u32>,
+    }
+
+    #[test]
+    fn test_old_chain_defaults() {
+        let old = OldChain {
+            nt_sequence: b"ACGT".to_vec(),
+            v_start: 7,
+            umi_counts: vec![3, 5],
+        };
+        let chain = ExactSubClonotypeChain::decode(old.encode_to_vec().as_slice()).unwrap();
+        assert_eq!(chain.nt_sequence, b"ACGT");
+        assert_eq!(chain.v_start, 7);
+        assert_eq!(chain.umi_counts, [3, 5]);
+        assert_eq!(chain.fwr1_start, None);
+        assert_eq!(chain.c_region_idx, None);
+        assert!(chain.read_counts.is_empty());
+        assert_eq!(chain.dna_percent, 0.0);
+        assert_eq!(chain.aa_percent, 0.0);
+    }
+}
@@ -0,0 +1,94 @@
+// Copyright (c) 2021 10X Genomics, Inc. All rights reserved.
+
+// Errors arising from validation of the arguments passed to main_enclone_ranger.  Rather than
+// stopping at the first problem, we collect all of them, so that the caller gets one report.
+
+use std::fmt;
+
+/// A single problem with the arguments passed to `main_enclone_ranger`.
+#[derive(Debug, thiserror::Error)]
+pub enum RangerArgError {
+    #[error("illegal argument {0} passed to main_enclone_ranger")]
+    UnknownArg(String),
+
+    #[error("required argument {0} not passed to main_enclone_ranger")]
+    MissingArg(&'static str),
+
+    #[error("argument {0} passed to main_enclone_ranger more than once")]
+    DuplicateArg(String),
+
+    #[error("arguments {0} and {1} passed to main_enclone_ranger cannot be used together")]
+    ConflictingArgs(&'static str, &'static str),
+
+    #[error("argument {0} passed to main_enclone_ranger requires {1}")]
+    RequiresArg(&'static str, &'static str),
+
+    #[error("malformed value {value:?} for argument {arg}: {reason}")]
+    MalformedValue {
+        arg: String,
+        value: String,
+        reason: &'static str,
+    },
+
+    #[error("unable to read REF file {path}: {source}")]
+    UnreadableRef {
+        path: String,
+        #[source]
+        source: std::io::Error,
+    },
+}
+
+/// All the problems found with the arguments passed to `main_enclone_ranger`.
+#[derive(Debug, Default)]
+pub struct RangerArgErrors(pub Vec<RangerArgError>);
+
+impl RangerArgErrors {
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+    pub fn push(&mut self, e: RangerArgError) {
+        self.0.push(e);
+    }
+    pub fn iter(&self) -> impl Iterator<Item = &RangerArgError> {
+        self.0.iter()
+    }
+}
+
+impl fmt::Display for RangerArgErrors {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} problem(s) with arguments to main_enclone_ranger:",
+            self.0.len()
+        )?;
+        for e in &self.0 {
+            write!(f, "\n  {e}")?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for RangerArgErrors {}
+
+/// Error returned by the ranger entry points.  Argument problems are kept structured; errors from
+/// the rest of enclone are passed through as the strings that enclone produces.
+#[derive(Debug, thiserror::Error)]
+pub enum RangerError {
+    #[error(transparent)]
+    Args(#[from] RangerArgErrors),
+
+    #[error("{0}")]
+    Enclone(String),
+}
+
+impl From<RangerArgError> for RangerError {
+    fn from(e: RangerArgError) -> Self {
+        RangerError::Args(RangerArgErrors(vec![e]))
+    }
+}
+
+impl From<String> for RangerError {
+    fn from(e: String) -> Self {
+        RangerError::Enclone(e)
+    }
+}
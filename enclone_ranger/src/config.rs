@@ -0,0 +1,627 @@
+// Copyright (c) 2021 10X Genomics, Inc. All rights reserved.
+
+// Typed configuration for the cellranger entry point.  A RangerConfig holds exactly the
+// arguments that main_enclone_ranger accepts.  It can be rendered as the equivalent argument
+// list (for logging, or to hand to proc_args), and parsed back from one.
+
+use crate::arg_error::{RangerArgErrors, RangerError};
+use crate::main_enclone::check_ranger_args;
+use crate::USING_PAGER;
+use enclone_args::proc_args::proc_args;
+use enclone_core::defs::EncloneControl;
+use std::sync::atomic::Ordering::SeqCst;
+use std::time::Instant;
+
+/// The VDJ input, which also determines the chain mode.  The value is the dataset path, or for
+/// `Meta`, the path of the META file.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum RangerInput {
+    Bcr(String),
+    Tcr(String),
+    /// Gamma/delta TCR.  This implies `GAMMA_DELTA`.
+    TcrGd(String),
+    Meta(String),
+}
+
+/// The default filters that cellranger may turn off.  Each field is `true` if the filter is on,
+/// which is the default.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct RangerFilters {
+    pub umi: bool,          // NUMI turns off
+    pub umi_ratio: bool,    // NUMI_RATIO turns off
+    pub graph: bool,        // NGRAPH_FILTER turns off
+    pub weak_chains: bool,  // NWEAK_CHAINS turns off
+    pub foursie_kill: bool, // NFOURSIE_KILL turns off
+    pub doublet: bool,      // NDOUBLET turns off
+    pub signature: bool,    // NSIG turns off
+}
+
+impl Default for RangerFilters {
+    fn default() -> Self {
+        RangerFilters {
+            umi: true,
+            umi_ratio: true,
+            graph: true,
+            weak_chains: true,
+            foursie_kill: true,
+            doublet: true,
+            signature: true,
+        }
+    }
+}
+
+impl RangerFilters {
+    // Pairs (argument that turns the filter off, filter state).
+
+    fn args(&self) -> [(&'static str, bool); 7] {
+        [
+            ("NUMI", self.umi),
+            ("NUMI_RATIO", self.umi_ratio),
+            ("NGRAPH_FILTER", self.graph),
+            ("NWEAK_CHAINS", self.weak_chains),
+            ("NFOURSIE_KILL", self.foursie_kill),
+            ("NDOUBLET", self.doublet),
+            ("NSIG", self.signature),
+        ]
+    }
+
+    fn turn_off(&mut self, arg: &str) {
+        let f = match arg {
+            "NUMI" => &mut self.umi,
+            "NUMI_RATIO" => &mut self.umi_ratio,
+            "NGRAPH_FILTER" => &mut self.graph,
+            "NWEAK_CHAINS" => &mut self.weak_chains,
+            "NFOURSIE_KILL" => &mut self.foursie_kill,
+            "NDOUBLET" => &mut self.doublet,
+            "NSIG" => &mut self.signature,
+            _ => return,
+        };
+        *f = false;
+    }
+}
+
+/// Everything that may be passed to the ranger entry point.  Construct with
+/// `RangerConfig::builder`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct RangerConfig {
+    pub input: RangerInput,
+    pub pre: Vec<String>,
+    pub reference: String,
+    pub donor_ref_file: String,
+    pub proto: String,
+    /// Write a sidecar index next to the proto file, for random access to its clonotypes.
+    pub proto_index: bool,
+    pub proto_metadata: Option<String>,
+    pub fate_file: Option<String>,
+    /// AIRR Rearrangement TSV output path.
+    pub airr: Option<String>,
+    pub max_cores: usize,
+    pub split_max_chains: Option<usize>,
+    pub nopretty: bool,
+    pub filters: RangerFilters,
+    pub parseable: Option<RangerParseable>,
+    pub sequences: RangerSequences,
+    /// Return the tree of each clonotype in the run summary, in Newick format.  This is set by
+    /// NEWICK, or by TREE, with or without a value, as the values only annotate printed trees.
+    pub trees: bool,
+    pub plot: Option<RangerPlot>,
+    pub split_plot: Option<RangerSplitPlot>,
+    /// The value of PLOTXY_EXACT, which is xvar,yvar,path or xvar,yvar,path,sym.
+    pub plot_xy_exact: Option<String>,
+    /// The value of SIM_MAT_PLOT, which is path,var1,...,varn.
+    pub sim_mat_plot: Option<String>,
+}
+
+/// Parseable output, written as a clonotype table next to the proto file.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct RangerParseable {
+    /// Output path.  The table is TSV if this ends in .tsv, and otherwise CSV.
+    pub pout: String,
+    /// Columns to write, or all columns if empty.
+    pub pcols: Vec<String>,
+    /// Names to use in the header line in place of pcols, if nonempty.
+    pub pcols_show: Vec<String>,
+    /// Write one line per barcode rather than per exact subclonotype.
+    pub pcell: bool,
+    pub pno_header: bool,
+}
+
+/// Sequence output paths, each written only if given.  The alignment outputs may be stdout,
+/// and otherwise must end in .tar.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct RangerSequences {
+    pub fasta: Option<String>,
+    pub fasta_aa: Option<String>,
+    pub clustal_aa: Option<String>,
+    pub clustal_dna: Option<String>,
+    pub phylip_aa: Option<String>,
+    pub phylip_dna: Option<String>,
+}
+
+impl RangerSequences {
+    // Pairs (argument, path).
+
+    fn args(&self) -> [(&'static str, &Option<String>); 6] {
+        [
+            ("FASTA", &self.fasta),
+            ("FASTA_AA", &self.fasta_aa),
+            ("CLUSTAL_AA", &self.clustal_aa),
+            ("CLUSTAL_DNA", &self.clustal_dna),
+            ("PHYLIP_AA", &self.phylip_aa),
+            ("PHYLIP_DNA", &self.phylip_dna),
+        ]
+    }
+
+    fn set(&mut self, arg: &str, path: &str) {
+        let f = match arg {
+            "FASTA" => &mut self.fasta,
+            "FASTA_AA" => &mut self.fasta_aa,
+            "CLUSTAL_AA" => &mut self.clustal_aa,
+            "CLUSTAL_DNA" => &mut self.clustal_dna,
+            "PHYLIP_AA" => &mut self.phylip_aa,
+            "PHYLIP_DNA" => &mut self.phylip_dna,
+            _ => return,
+        };
+        *f = Some(path.to_string());
+    }
+}
+
+/// A plot of the clonotypes, given by the argument that requests it, whose value is as for
+/// enclone.  The plot is written as SVG.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum RangerPlot {
+    Plot(String),
+    Plot2(String),
+    Honey(String),
+    ByIsotype(String),
+    ByMark(String),
+}
+
+impl RangerPlot {
+    fn arg(&self) -> String {
+        match self {
+            RangerPlot::Plot(v) => format!("PLOT={v}"),
+            RangerPlot::Plot2(v) => format!("PLOT2={v}"),
+            RangerPlot::Honey(v) => format!("HONEY={v}"),
+            RangerPlot::ByIsotype(v) => format!("PLOT_BY_ISOTYPE={v}"),
+            RangerPlot::ByMark(v) => format!("PLOT_BY_MARK={v}"),
+        }
+    }
+}
+
+/// Make one plot for each dataset (SPLIT_PLOT_BY_DATASET) or origin (SPLIT_PLOT_BY_ORIGIN).
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RangerSplitPlot {
+    Dataset,
+    Origin,
+}
+
+pub struct RangerConfigBuilder {
+    config: RangerConfig,
+}
+
+impl RangerConfigBuilder {
+    /// Add a directory to PRE.
+    pub fn pre(mut self, dir: impl Into<String>) -> Self {
+        self.config.pre.push(dir.into());
+        self
+    }
+    pub fn proto_index(mut self) -> Self {
+        self.config.proto_index = true;
+        self
+    }
+    pub fn proto_metadata(mut self, path: impl Into<String>) -> Self {
+        self.config.proto_metadata = Some(path.into());
+        self
+    }
+    pub fn fate_file(mut self, path: impl Into<String>) -> Self {
+        self.config.fate_file = Some(path.into());
+        self
+    }
+    pub fn airr(mut self, path: impl Into<String>) -> Self {
+        self.config.airr = Some(path.into());
+        self
+    }
+    pub fn max_cores(mut self, n: usize) -> Self {
+        self.config.max_cores = n;
+        self
+    }
+    pub fn split_max_chains(mut self, n: usize) -> Self {
+        self.config.split_max_chains = Some(n);
+        self
+    }
+    pub fn nopretty(mut self) -> Self {
+        self.config.nopretty = true;
+        self
+    }
+    pub fn filters(mut self, filters: RangerFilters) -> Self {
+        self.config.filters = filters;
+        self
+    }
+    pub fn parseable(mut self, parseable: RangerParseable) -> Self {
+        self.config.parseable = Some(parseable);
+        self
+    }
+    pub fn sequences(mut self, sequences: RangerSequences) -> Self {
+        self.config.sequences = sequences;
+        self
+    }
+    pub fn trees(mut self) -> Self {
+        self.config.trees = true;
+        self
+    }
+    pub fn plot(mut self, plot: RangerPlot) -> Self {
+        self.config.plot = Some(plot);
+        self
+    }
+    pub fn split_plot(mut self, split: RangerSplitPlot) -> Self {
+        self.config.split_plot = Some(split);
+        self
+    }
+    pub fn plot_xy_exact(mut self, value: impl Into<String>) -> Self {
+        self.config.plot_xy_exact = Some(value.into());
+        self
+    }
+    pub fn sim_mat_plot(mut self, value: impl Into<String>) -> Self {
+        self.config.sim_mat_plot = Some(value.into());
+        self
+    }
+    pub fn build(self) -> RangerConfig {
+        self.config
+    }
+}
+
+impl RangerConfig {
+    /// Start a configuration from the arguments that every run needs.  MAX_CORES defaults to
+    /// one and PRE to the empty list.
+    pub fn builder(
+        input: RangerInput,
+        reference: impl Into<String>,
+        donor_ref_file: impl Into<String>,
+        proto: impl Into<String>,
+    ) -> RangerConfigBuilder {
+        RangerConfigBuilder {
+            config: RangerConfig {
+                input,
+                pre: Vec::new(),
+                reference: reference.into(),
+                donor_ref_file: donor_ref_file.into(),
+                proto: proto.into(),
+                proto_index: false,
+                proto_metadata: None,
+                fate_file: None,
+                airr: None,
+                max_cores: 1,
+                split_max_chains: None,
+                nopretty: false,
+                filters: RangerFilters::default(),
+                parseable: None,
+                sequences: RangerSequences::default(),
+                trees: false,
+                plot: None,
+                split_plot: None,
+                plot_xy_exact: None,
+                sim_mat_plot: None,
+            },
+        }
+    }
+
+    /// Render as the argument list that main_enclone_ranger would be given, including the
+    /// program name as the first entry.
+    pub fn to_args(&self) -> Vec<String> {
+        let mut args = vec!["enclone".to_string(), "CELLRANGER".to_string()];
+        if !self.pre.is_empty() {
+            args.push(format!("PRE={}", self.pre.join(",")));
+        }
+        args.push(match &self.input {
+            RangerInput::Bcr(p) => format!("BCR={p}"),
+            RangerInput::Tcr(p) => format!("TCR={p}"),
+            RangerInput::TcrGd(p) => format!("TCRGD={p}"),
+            RangerInput::Meta(p) => format!("META={p}"),
+        });
+        if matches!(self.input, RangerInput::TcrGd(_)) {
+            args.push("GAMMA_DELTA".to_string());
+        }
+        args.push(format!("REF={}", self.reference));
+        args.push(format!("DONOR_REF_FILE={}", self.donor_ref_file));
+        args.push(format!("PROTO={}", self.proto));
+        if self.proto_index {
+            args.push("PROTO_INDEX".to_string());
+        }
+        if let Some(m) = &self.proto_metadata {
+            args.push(format!("PROTO_METADATA={m}"));
+        }
+        if let Some(f) = &self.fate_file {
+            args.push(format!("FATE_FILE={f}"));
+        }
+        if let Some(a) = &self.airr {
+            args.push(format!("AIRR={a}"));
+        }
+        args.push(format!("MAX_CORES={}", self.max_cores));
+        if let Some(n) = self.split_max_chains {
+            args.push(format!("SPLIT_MAX_CHAINS={n}"));
+        }
+        for (arg, on) in self.filters.args() {
+            if !on {
+                args.push(arg.to_string());
+            }
+        }
+        if let Some(p) = &self.parseable {
+            args.push(format!("POUT={}", p.pout));
+            if !p.pcols.is_empty() {
+                args.push(format!("PCOLS={}", p.pcols.join(",")));
+            }
+            if !p.pcols_show.is_empty() {
+                args.push(format!("PCOLS_SHOW={}", p.pcols_show.join(",")));
+            }
+            if p.pcell {
+                args.push("PCELL".to_string());
+            }
+            if p.pno_header {
+                args.push("PNO_HEADER".to_string());
+            }
+        }
+        for (arg, path) in self.sequences.args() {
+            if let Some(path) = path {
+                args.push(format!("{arg}={path}"));
+            }
+        }
+        if self.trees {
+            args.push("NEWICK".to_string());
+        }
+        if let Some(plot) = &self.plot {
+            args.push(plot.arg());
+        }
+        match self.split_plot {
+            Some(RangerSplitPlot::Dataset) => args.push("SPLIT_PLOT_BY_DATASET".to_string()),
+            Some(RangerSplitPlot::Origin) => args.push("SPLIT_PLOT_BY_ORIGIN".to_string()),
+            None => {}
+        }
+        if let Some(v) = &self.plot_xy_exact {
+            args.push(format!("PLOTXY_EXACT={v}"));
+        }
+        if let Some(v) = &self.sim_mat_plot {
+            args.push(format!("SIM_MAT_PLOT={v}"));
+        }
+        args.push("FORCE_EXTERNAL".to_string());
+        args.push("NOPAGER".to_string());
+        args.push("NOPRINT".to_string());
+        if self.nopretty {
+            args.push("NOPRETTY".to_string());
+        }
+        args
+    }
+
+    /// Parse an argument list of the form accepted by main_enclone_ranger.  The first entry is
+    /// the program name and is ignored.  The arguments are validated by check_ranger_args.  This
+    /// is the inverse of `to_args`.
+    pub fn from_args(args: &[String]) -> Result<RangerConfig, RangerArgErrors> {
+        check_ranger_args(args)?;
+        let mut input = None;
+        let mut pre = Vec::<String>::new();
+        let (mut reference, mut donor_ref_file, mut proto) = (None, None, None);
+        let (mut proto_metadata, mut fate_file, mut airr) = (None, None, None);
+        let mut max_cores = 1;
+        let mut split_max_chains = None;
+        let (mut proto_index, mut nopretty) = (false, false);
+        let mut filters = RangerFilters::default();
+        let mut parseable = RangerParseable::default();
+        let mut pout = false;
+        let mut sequences = RangerSequences::default();
+        let mut trees = false;
+        let (mut plot, mut split_plot) = (None, None);
+        let (mut plot_xy_exact, mut sim_mat_plot) = (None, None);
+        for arg in args.iter().skip(1) {
+            let (key, value) = arg.split_once('=').unwrap_or((arg.as_str(), ""));
+            let list = || value.split(',').map(str::to_string).collect();
+            match key {
+                "BCR" => input = Some(RangerInput::Bcr(value.to_string())),
+                "TCR" => input = Some(RangerInput::Tcr(value.to_string())),
+                "TCRGD" => input = Some(RangerInput::TcrGd(value.to_string())),
+                "META" => input = Some(RangerInput::Meta(value.to_string())),
+                "PRE" => pre = list(),
+                "REF" => reference = Some(value.to_string()),
+                "DONOR_REF_FILE" => donor_ref_file = Some(value.to_string()),
+                "PROTO" => proto = Some(value.to_string()),
+                "PROTO_INDEX" => proto_index = true,
+                "PROTO_METADATA" => proto_metadata = Some(value.to_string()),
+                "FATE_FILE" => fate_file = Some(value.to_string()),
+                "AIRR" => airr = Some(value.to_string()),
+                "MAX_CORES" => max_cores = value.parse().unwrap(),
+                "SPLIT_MAX_CHAINS" => split_max_chains = Some(value.parse().unwrap()),
+                "NOPRETTY" => nopretty = true,
+                "POUT" => {
+                    pout = true;
+                    parseable.pout = value.to_string();
+                }
+                "PCOLS" => parseable.pcols = list(),
+                "PCOLS_SHOW" => parseable.pcols_show = list(),
+                "PCELL" => parseable.pcell = true,
+                "PNO_HEADER" => parseable.pno_header = true,
+                "TREE" | "NEWICK" => trees = true,
+                "PLOT" => plot = Some(RangerPlot::Plot(value.to_string())),
+                "PLOT2" => plot = Some(RangerPlot::Plot2(value.to_string())),
+                "HONEY" => plot = Some(RangerPlot::Honey(value.to_string())),
+                "PLOT_BY_ISOTYPE" => plot = Some(RangerPlot::ByIsotype(value.to_string())),
+                "PLOT_BY_MARK" => plot = Some(RangerPlot::ByMark(value.to_string())),
+                "SPLIT_PLOT_BY_DATASET" => split_plot = Some(RangerSplitPlot::Dataset),
+                "SPLIT_PLOT_BY_ORIGIN" => split_plot = Some(RangerSplitPlot::Origin),
+                "PLOTXY_EXACT" => plot_xy_exact = Some(value.to_string()),
+                "SIM_MAT_PLOT" => sim_mat_plot = Some(value.to_string()),
+                "FASTA" | "FASTA_AA" | "CLUSTAL_AA" | "CLUSTAL_DNA" | "PHYLIP_AA"
+                | "PHYLIP_DNA" => sequences.set(key, value),
+                "NUMI" | "NUMI_RATIO" | "NGRAPH_FILTER" | "NWEAK_CHAINS" | "NFOURSIE_KILL"
+                | "NDOUBLET" | "NSIG" => filters.turn_off(key),
+                // Implied by TCRGD, which check_ranger_args requires it to be passed with.
+                "GAMMA_DELTA" => {}
+                // Always passed.
+                "CELLRANGER" | "FORCE_EXTERNAL" | "NOPAGER" | "NOPRINT" => {}
+                _ => unreachable!("argument {key} is accepted by check_ranger_args"),
+            }
+        }
+        Ok(RangerConfig {
+            input: input.unwrap(),
+            pre,
+            reference: reference.unwrap(),
+            donor_ref_file: donor_ref_file.unwrap(),
+            proto: proto.unwrap(),
+            proto_index,
+            proto_metadata,
+            fate_file,
+            airr,
+            max_cores,
+            split_max_chains,
+            nopretty,
+            filters,
+            parseable: pout.then_some(parseable),
+            sequences,
+            trees,
+            plot,
+            split_plot,
+            plot_xy_exact,
+            sim_mat_plot,
+        })
+    }
+
+    /// Produce the control structure for this run.  The typed arguments are passed through
+    /// proc_args, so that the defaults that enclone sets, and the checks that it makes, are
+    /// exactly those of the string interface.
+    pub fn to_control(&self) -> Result<EncloneControl, RangerError> {
+        let mut ctl = EncloneControl::default();
+        ctl.gen_opt.cellranger = true;
+        ctl.gen_opt.internal_run = false;
+        ctl.gen_opt.pre = self.pre.clone();
+        ctl.start_time = Some(Instant::now());
+        ctl.gen_opt.cpu_all_start = 0;
+        ctl.gen_opt.cpu_this_start = 0;
+        ctl.gen_opt.nopager = true;
+        ctl.pretty = true;
+        ctl.gen_opt.h5 = true;
+        USING_PAGER.store(false, SeqCst);
+        proc_args(&mut ctl, &self.to_args())?;
+        Ok(ctl)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        RangerConfig, RangerFilters, RangerInput, RangerParseable, RangerPlot, RangerSequences,
+        RangerSplitPlot,
+    };
+
+    #[test]
+    fn test_args_round_trip() {
+        let minimal = RangerConfig::builder(
+            RangerInput::Bcr("outs".to_string()),
+            "ref.fa",
+            "donor_ref.fa",
+            "clones.proto",
+        )
+        .build();
+        assert!(!minimal.to_args().iter().any(|x| x.starts_with("PRE")));
+        let full = RangerConfig::builder(
+            RangerInput::TcrGd("outs".to_string()),
+            "ref.fa",
+            "donor_ref.fa",
+            "clones.proto",
+        )
+        .pre("/mnt/a")
+        .pre("/mnt/b")
+        .proto_index()
+        .proto_metadata("meta.json")
+        .fate_file("fate.json")
+        .airr("airr.tsv")
+        .max_cores(8)
+        .split_max_chains(4)
+        .nopretty()
+        .filters(RangerFilters {
+            umi: false,
+            signature: false,
+            ..RangerFilters::default()
+        })
+        .parseable(RangerParseable {
+            pout: "clones.tsv".to_string(),
+            pcols: vec!["clonotype_id".to_string(), "n".to_string()],
+            pcols_show: vec!["id".to_string(), "cells".to_string()],
+            pcell: true,
+            pno_header: true,
+        })
+        .sequences(RangerSequences {
+            fasta: Some("clones.fasta".to_string()),
+            clustal_dna: Some("clones.tar".to_string()),
+            phylip_aa: Some("stdout".to_string()),
+            ..RangerSequences::default()
+        })
+        .trees()
+        .plot(RangerPlot::Honey("out=plot.svg,color=var,u".to_string()))
+        .split_plot(RangerSplitPlot::Dataset)
+        .plot_xy_exact("u1,u2,xy.svg,sym")
+        .sim_mat_plot("sim.svg,cdr3_aa1,cdr3_aa2")
+        .build();
+        for c in [minimal, full] {
+            assert_eq!(RangerConfig::from_args(&c.to_args()).unwrap(), c);
+        }
+
+        // Every argument is kept, so parsing an argument list and rendering it gives the same
+        // arguments.
+
+        let args: Vec<String> = [
+            "enclone",
+            "CELLRANGER",
+            "TCRGD=outs",
+            "GAMMA_DELTA",
+            "REF=ref.fa",
+            "DONOR_REF_FILE=donor_ref.fa",
+            "PROTO=clones.proto",
+            "NDOUBLET",
+            "MAX_CORES=2",
+            "FORCE_EXTERNAL",
+            "NOPAGER",
+            "NOPRINT",
+        ]
+        .map(str::to_string)
+        .to_vec();
+        let mut sorted = args.clone();
+        sorted.sort();
+        let mut round_trip = RangerConfig::from_args(&args).unwrap().to_args();
+        round_trip.sort();
+        assert_eq!(round_trip, sorted);
+
+        // GAMMA_DELTA without TCRGD, conflicting inputs and repeated arguments are rejected.
+
+        let errors = |extra: &[&str]| -> Vec<String> {
+            let mut args = args.clone();
+            args.retain(|x| x != "GAMMA_DELTA" && !x.starts_with("TCRGD="));
+            args.extend(extra.iter().map(ToString::to_string));
+            match RangerConfig::from_args(&args) {
+                Ok(_) => Vec::new(),
+                Err(e) => e.iter().map(ToString::to_string).collect(),
+            }
+        };
+        assert!(errors(&["TCR=outs"]).is_empty());
+        assert_eq!(
+            errors(&["TCR=outs", "GAMMA_DELTA"]),
+            ["argument GAMMA_DELTA passed to main_enclone_ranger requires TCRGD"]
+        );
+        assert_eq!(
+            errors(&["TCRGD=outs"]),
+            ["argument TCRGD passed to main_enclone_ranger requires GAMMA_DELTA"]
+        );
+        assert_eq!(
+            errors(&["BCR=outs", "TCR=outs"]),
+            ["arguments BCR and TCR passed to main_enclone_ranger cannot be used together"]
+        );
+        assert_eq!(
+            errors(&["TCR=outs", "PROTO=other.proto"]),
+            ["argument PROTO passed to main_enclone_ranger more than once"]
+        );
+        assert_eq!(
+            errors(&["TCR=outs", "HONEY=out=a.svg,u", "PLOT_BY_ISOTYPE=b.svg"]),
+            [
+                "arguments HONEY and PLOT_BY_ISOTYPE passed to main_enclone_ranger cannot be \
+                 used together"
+            ]
+        );
+    }
+}
@@ -1,7 +1,10 @@
 // Copyright (c) 2021 10x Genomics, Inc. All rights reserved.
 
+pub mod arg_error;
+pub mod config;
 pub mod main_enclone;
 pub mod stop;
+pub mod summary;
 
 use std::sync::atomic::AtomicBool;
 
@@ -4,106 +4,249 @@
 // be passed are limited.  The code here is simplified and could be further simplified.
 
 use self::refx::{make_vdj_ref_data_core, RefData};
+use crate::arg_error::{RangerArgError, RangerArgErrors, RangerError};
+use crate::config::RangerConfig;
 use crate::stop::main_enclone_stop_ranger;
-use crate::USING_PAGER;
+use crate::summary::RangerRunSummary;
 use enclone::innate::species;
 use enclone_args::load_gex::get_gex_info;
-use enclone_args::proc_args::proc_args;
-use enclone_core::defs::EncloneControl;
 use enclone_core::enclone_structs::EncloneSetup;
+use enclone_core::gex_access::GexMatrixAccess;
 use enclone_stuff::start::main_enclone_start;
-use std::sync::atomic::Ordering::SeqCst;
 use std::{
     collections::HashMap,
     fs::File,
     io::{BufRead, BufReader},
     time::Instant,
 };
-use string_utils::TextUtils;
 use vdj_ann::refx;
 
-pub fn main_enclone_ranger(args: &[String]) -> Result<(), String> {
-    const REQUIRED_ARGS: [&str; 9] = [
-        "CELLRANGER",
-        "DONOR_REF_FILE",
-        "FORCE_EXTERNAL",
-        "MAX_CORES",
-        "NOPAGER",
-        "NOPRINT",
-        "PRE",
-        "PROTO",
-        "REF",
-    ];
-    const ALLOWED_ARGS: [&str; 16] = [
-        "BCR",
-        "META",
-        "NOPRETTY",
-        "PROTO_METADATA",
-        "TCR",
-        "TCRGD",
-        "GAMMA_DELTA",
-        "FATE_FILE",
-        "NUMI",
-        "NUMI_RATIO",
-        "NGRAPH_FILTER",
-        "NWEAK_CHAINS",
-        "NFOURSIE_KILL",
-        "NDOUBLET",
-        "NSIG",
-        "SPLIT_MAX_CHAINS",
-    ];
+const REQUIRED_ARGS: [&str; 8] = [
+    "CELLRANGER",
+    "DONOR_REF_FILE",
+    "FORCE_EXTERNAL",
+    "MAX_CORES",
+    "NOPAGER",
+    "NOPRINT",
+    "PROTO",
+    "REF",
+];
+const ALLOWED_ARGS: [&str; 41] = [
+    "AIRR",
+    "BCR",
+    "META",
+    "NOPRETTY",
+    "PROTO_INDEX",
+    "PROTO_METADATA",
+    "TCR",
+    "TCRGD",
+    "GAMMA_DELTA",
+    "FATE_FILE",
+    "NUMI",
+    "NUMI_RATIO",
+    "NGRAPH_FILTER",
+    "NWEAK_CHAINS",
+    "NFOURSIE_KILL",
+    "NDOUBLET",
+    "NSIG",
+    "SPLIT_MAX_CHAINS",
+    "POUT",
+    "PCOLS",
+    "PCOLS_SHOW",
+    "PCELL",
+    "PNO_HEADER",
+    "PRE",
+    "FASTA",
+    "FASTA_AA",
+    "CLUSTAL_AA",
+    "CLUSTAL_DNA",
+    "PHYLIP_AA",
+    "PHYLIP_DNA",
+    "TREE",
+    "NEWICK",
+    "PLOT",
+    "PLOT2",
+    "HONEY",
+    "PLOT_BY_ISOTYPE",
+    "PLOT_BY_MARK",
+    "SPLIT_PLOT_BY_DATASET",
+    "SPLIT_PLOT_BY_ORIGIN",
+    "PLOTXY_EXACT",
+    "SIM_MAT_PLOT",
+];
+
+// Arguments that give the VDJ input, one of which must be passed.
+
+const INPUT_ARGS: [&str; 4] = ["BCR", "META", "TCR", "TCRGD"];
+
+// Sets of arguments of which at most one may be passed, because each sets the same thing.
+
+const EXCLUSIVE_ARGS: [&[&str]; 3] = [
+    &INPUT_ARGS,
+    &["PLOT", "PLOT2", "HONEY", "PLOT_BY_ISOTYPE", "PLOT_BY_MARK"],
+    &["SPLIT_PLOT_BY_DATASET", "SPLIT_PLOT_BY_ORIGIN"],
+];
+
+// Arguments that must be given as KEY=VALUE with a nonempty value.
+
+const VALUE_ARGS: [&str; 26] = [
+    "AIRR",
+    "CLUSTAL_AA",
+    "CLUSTAL_DNA",
+    "DONOR_REF_FILE",
+    "FASTA",
+    "FASTA_AA",
+    "FATE_FILE",
+    "HONEY",
+    "MAX_CORES",
+    "META",
+    "PCOLS",
+    "PCOLS_SHOW",
+    "PHYLIP_AA",
+    "PHYLIP_DNA",
+    "PLOT",
+    "PLOT2",
+    "PLOT_BY_ISOTYPE",
+    "PLOT_BY_MARK",
+    "PLOTXY_EXACT",
+    "POUT",
+    "PRE",
+    "PROTO",
+    "PROTO_METADATA",
+    "REF",
+    "SIM_MAT_PLOT",
+    "SPLIT_MAX_CHAINS",
+];
+
+// Arguments whose value must be a nonnegative integer.
+
+const USIZE_ARGS: [&str; 2] = ["MAX_CORES", "SPLIT_MAX_CHAINS"];
+
+// Arguments that are flags, and so may not have a value.
+
+const FLAG_ARGS: [&str; 19] = [
+    "CELLRANGER",
+    "FORCE_EXTERNAL",
+    "GAMMA_DELTA",
+    "NDOUBLET",
+    "NEWICK",
+    "NFOURSIE_KILL",
+    "NGRAPH_FILTER",
+    "NOPAGER",
+    "NOPRETTY",
+    "NOPRINT",
+    "NSIG",
+    "NUMI",
+    "NUMI_RATIO",
+    "NWEAK_CHAINS",
+    "PCELL",
+    "PNO_HEADER",
+    "PROTO_INDEX",
+    "SPLIT_PLOT_BY_DATASET",
+    "SPLIT_PLOT_BY_ORIGIN",
+];
+
+/// Check the arguments that are passed to main_enclone_ranger, returning every problem found,
+/// not just the first.  As for enclone itself, the first argument is the program name and is
+/// ignored.  This is the only validation of the arguments: `RangerConfig::from_args` calls it,
+/// and then parses what it has accepted.  Each argument may be passed at most once, and
+/// GAMMA_DELTA must be passed exactly when TCRGD is.
+pub fn check_ranger_args(args: &[String]) -> Result<(), RangerArgErrors> {
+    let mut errors = RangerArgErrors::default();
     let mut found = [false; REQUIRED_ARGS.len()];
+    let mut input = false;
+    let mut seen = Vec::<&str>::new();
     for arg in args.iter().skip(1) {
-        let mut arg = arg.as_str();
-        if arg.contains('=') {
-            arg = arg.before("=");
+        let (key, value) = match arg.split_once('=') {
+            Some((key, value)) => (key, Some(value)),
+            None => (arg.as_str(), None),
+        };
+        input = input || INPUT_ARGS.contains(&key);
+        if seen.contains(&key) {
+            errors.push(RangerArgError::DuplicateArg(key.to_string()));
         }
+        seen.push(key);
         let mut ok = false;
         for (f, &x) in found.iter_mut().zip(REQUIRED_ARGS.iter()) {
-            if arg == x {
+            if key == x {
                 ok = true;
                 *f = true;
             }
         }
-        ok = ok || ALLOWED_ARGS.contains(&arg);
+        ok = ok || ALLOWED_ARGS.contains(&key);
         if !ok {
-            panic!("Illegal argument {arg} passed to main_enclone_ranger.");
+            errors.push(RangerArgError::UnknownArg(arg.clone()));
+            continue;
+        }
+        let malformed = |reason| RangerArgError::MalformedValue {
+            arg: key.to_string(),
+            value: value.unwrap_or_default().to_string(),
+            reason,
+        };
+        if VALUE_ARGS.contains(&key) && value.unwrap_or_default().is_empty() {
+            errors.push(malformed("a nonempty value is required"));
+        } else if USIZE_ARGS.contains(&key) && value.unwrap_or_default().parse::<usize>().is_err() {
+            errors.push(malformed("expected a nonnegative integer"));
+        } else if FLAG_ARGS.contains(&key) && value.is_some() {
+            errors.push(malformed("this argument does not take a value"));
         }
     }
-    for (found, arg) in found.into_iter().zip(REQUIRED_ARGS.into_iter()) {
+    for (found, arg) in found.into_iter().zip(REQUIRED_ARGS) {
         if !found {
-            panic!("Required argument {arg} not passed to main_enclone_ranger");
+            errors.push(RangerArgError::MissingArg(arg));
+        }
+    }
+    if !input {
+        errors.push(RangerArgError::MissingArg("BCR or TCR or TCRGD or META"));
+    }
+    for group in EXCLUSIVE_ARGS {
+        let passed: Vec<&str> = group.iter().copied().filter(|x| seen.contains(x)).collect();
+        for &other in passed.iter().skip(1) {
+            errors.push(RangerArgError::ConflictingArgs(passed[0], other));
+        }
+    }
+    for (x, y) in [("GAMMA_DELTA", "TCRGD"), ("TCRGD", "GAMMA_DELTA")] {
+        if seen.contains(&x) && !seen.contains(&y) {
+            errors.push(RangerArgError::RequiresArg(x, y));
         }
     }
-    let setup = main_enclone_setup_ranger(args)?;
+    if errors.is_empty() {
+        Ok(())
+    } else {
+        Err(errors)
+    }
+}
+
+pub fn main_enclone_ranger(args: &[String]) -> Result<RangerRunSummary, RangerError> {
+    main_enclone_ranger_config(&RangerConfig::from_args(args)?)
+}
+
+pub fn main_enclone_ranger_config(config: &RangerConfig) -> Result<RangerRunSummary, RangerError> {
+    let mut stage_times = Vec::new();
+    let t = Instant::now();
+    let setup = main_enclone_setup_ranger_config(config)?;
+    stage_times.push(("setup", t.elapsed().as_secs_f64()));
+    let t = Instant::now();
     let inter = main_enclone_start(setup)?;
-    main_enclone_stop_ranger(inter)
+    stage_times.push(("start", t.elapsed().as_secs_f64()));
+    let t = Instant::now();
+    let mut summary = main_enclone_stop_ranger(inter)?;
+    stage_times.push(("stop", t.elapsed().as_secs_f64()));
+    summary.stage_times = stage_times;
+    Ok(summary)
 }
 
-pub fn main_enclone_setup_ranger(args: &[String]) -> Result<EncloneSetup, String> {
-    let tall = Instant::now();
+pub fn main_enclone_setup_ranger(args: &[String]) -> Result<EncloneSetup, RangerError> {
+    main_enclone_setup_ranger_config(&RangerConfig::from_args(args)?)
+}
 
+pub fn main_enclone_setup_ranger_config(
+    config: &RangerConfig,
+) -> Result<EncloneSetup, RangerError> {
     // Set up stuff, read args, etc.
 
-    let mut ctl = EncloneControl::default();
-    ctl.gen_opt.cellranger = true;
-    ctl.gen_opt.internal_run = false;
-    for arg in args.iter().skip(1) {
-        if arg.starts_with("PRE=") {
-            ctl.gen_opt.pre.clear();
-            ctl.gen_opt
-                .pre
-                .extend(arg.after("PRE=").split(',').map(str::to_string));
-        }
-    }
-    ctl.start_time = Some(tall);
-    ctl.gen_opt.cpu_all_start = 0;
-    ctl.gen_opt.cpu_this_start = 0;
-    ctl.gen_opt.nopager = true;
-    ctl.pretty = true;
-    ctl.gen_opt.h5 = true;
-    USING_PAGER.store(false, SeqCst);
-    proc_args(&mut ctl, args)?;
+    let mut ctl = config.to_control()?;
+    let tall = ctl.start_time.unwrap();
 
     // Get gene expression and feature barcode counts.
 
@@ -113,10 +256,13 @@ pub fn main_enclone_setup_ranger(args: &[String]) -> Result<EncloneSetup, String
 
     let mut refx = String::new();
     let ann = "contig_annotations.json";
-    let fx = File::open(&ctl.gen_opt.refname);
-    let f = BufReader::new(fx.unwrap());
+    let unreadable_ref = |source| RangerArgError::UnreadableRef {
+        path: ctl.gen_opt.refname.clone(),
+        source,
+    };
+    let f = BufReader::new(File::open(&ctl.gen_opt.refname).map_err(unreadable_ref)?);
     for line in f.lines() {
-        let s = line.unwrap();
+        let s = line.map_err(unreadable_ref)?;
         refx += &s;
         refx += "\n";
     }
@@ -149,6 +295,7 @@ pub fn main_enclone_setup_ranger(args: &[String]) -> Result<EncloneSetup, String
     // Return.
 
     Ok(EncloneSetup {
+        gex_access: GexMatrixAccess::new(&ctl),
         ctl,
         refdata,
         ann: ann.to_string(),
@@ -158,3 +305,345 @@ pub fn main_enclone_setup_ranger(args: &[String]) -> Result<EncloneSetup, String
         to_ref_index,
     })
 }
+
+#[cfg(test)]
+mod tests {
+    use super::{check_ranger_args, main_enclone_ranger, main_enclone_setup_ranger};
+    use crate::arg_error::RangerArgError;
+    use crate::summary::RangerRunSummary;
+    use enclone_print::tree::germline;
+    use enclone_stuff::start::main_enclone_start;
+    use serde_json::{json, Value};
+    use std::collections::BTreeSet;
+    use std::fs::{read_to_string, write};
+    use std::path::Path;
+
+    const DATA: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/../enclone_args/tests/data");
+
+    // A complete argument list, with the given arguments appended.
+
+    fn args(extra: &[&str]) -> Vec<String> {
+        [
+            "enclone",
+            "CELLRANGER",
+            "PRE=/mnt/vdj",
+            "BCR=outs",
+            "REF=ref.fa",
+            "DONOR_REF_FILE=donor_ref.fa",
+            "PROTO=clones.proto",
+            "MAX_CORES=4",
+            "FORCE_EXTERNAL",
+            "NOPAGER",
+            "NOPRINT",
+        ]
+        .iter()
+        .chain(extra)
+        .map(|&x| x.to_string())
+        .collect()
+    }
+
+    fn errors(args: &[String]) -> Vec<String> {
+        match check_ranger_args(args) {
+            Ok(()) => Vec::new(),
+            Err(e) => e.iter().map(ToString::to_string).collect(),
+        }
+    }
+
+    #[test]
+    fn test_check_ranger_args() {
+        assert!(check_ranger_args(&args(&["NOPRETTY", "SPLIT_MAX_CHAINS=5"])).is_ok());
+        assert!(check_ranger_args(&args(&["TREE=u", "NEWICK"])).is_ok());
+        assert!(check_ranger_args(&args(&[
+            "HONEY=out=p.svg,color=var,u",
+            "SPLIT_PLOT_BY_ORIGIN"
+        ]))
+        .is_ok());
+
+        // Unknown, and flag with a value.
+
+        let e = check_ranger_args(&args(&["BOGUS=1", "NOPRETTY=yes"])).unwrap_err();
+        let e: Vec<&RangerArgError> = e.iter().collect();
+        assert_eq!(e.len(), 2);
+        assert!(matches!(e[0], RangerArgError::UnknownArg(x) if x == "BOGUS=1"));
+        assert!(matches!(
+            e[1],
+            RangerArgError::MalformedValue { arg, value, .. } if arg == "NOPRETTY" && value == "yes"
+        ));
+
+        // Missing.
+
+        let mut missing = args(&[]);
+        missing.retain(|x| !x.starts_with("REF=") && x != "NOPAGER");
+        let e = check_ranger_args(&missing).unwrap_err();
+        let e: Vec<&RangerArgError> = e.iter().collect();
+        assert_eq!(e.len(), 2);
+        assert!(matches!(e[0], RangerArgError::MissingArg("NOPAGER")));
+        assert!(matches!(e[1], RangerArgError::MissingArg("REF")));
+
+        // Malformed values, all reported at once.
+
+        let mut bad = args(&["SPLIT_MAX_CHAINS=-1", "POUT", "FATE_FILE="]);
+        bad.retain(|x| x != "MAX_CORES=4");
+        bad.push("MAX_CORES=four".to_string());
+        let e = errors(&bad);
+        assert_eq!(e.len(), 4);
+        assert_eq!(
+            e[0],
+            "malformed value \"-1\" for argument SPLIT_MAX_CHAINS: expected a nonnegative integer"
+        );
+        assert_eq!(
+            e[1],
+            "malformed value \"\" for argument POUT: a nonempty value is required"
+        );
+        assert!(e[2].starts_with("malformed value \"\" for argument FATE_FILE"));
+        assert!(e[3].starts_with("malformed value \"four\" for argument MAX_CORES"));
+    }
+    // The arguments to run on the test dataset, writing to dir, with the given arguments
+    // appended.  The filters are turned off, because the dataset has only four cells.
+
+    fn run_args(dir: &Path, extra: &[String]) -> Vec<String> {
+        let mut args = vec![
+            "enclone".to_string(),
+            "CELLRANGER".to_string(),
+            format!("BCR={DATA}"),
+            format!("REF={DATA}/ref.fa"),
+            format!("DONOR_REF_FILE={}", dir.join("donor_ref.fa").display()),
+            format!("PROTO={}", dir.join("clones.proto").display()),
+            "MAX_CORES=1".to_string(),
+        ];
+        for arg in [
+            "NUMI",
+            "NUMI_RATIO",
+            "NGRAPH_FILTER",
+            "NWEAK_CHAINS",
+            "NFOURSIE_KILL",
+            "NDOUBLET",
+            "NSIG",
+            "FORCE_EXTERNAL",
+            "NOPAGER",
+            "NOPRINT",
+        ] {
+            args.push(arg.to_string());
+        }
+        args.extend(extra.iter().cloned());
+        args
+    }
+
+    fn run(dir: &Path, extra: &[String]) -> RangerRunSummary {
+        main_enclone_ranger(&run_args(dir, extra)).unwrap()
+    }
+
+    #[test]
+    fn test_fasta() {
+        let dir = tempfile::tempdir().unwrap();
+        let fasta = dir.path().join("clones.fasta");
+        let fasta_aa = dir.path().join("clones_aa.fasta");
+        let summary = run(
+            dir.path(),
+            &[
+                format!("FASTA={}", fasta.display()),
+                format!("FASTA_AA={}", fasta_aa.display()),
+            ],
+        );
+
+        // Each record is named {clonotype}.{exact subclonotype}.{chain}, and every clonotype
+        // has records.
+
+        let fasta = read_to_string(fasta).unwrap();
+        let lines: Vec<&str> = fasta.lines().collect();
+        assert!(!lines.is_empty() && lines.len() % 2 == 0);
+        let mut clonotypes = Vec::<usize>::new();
+        for record in lines.chunks(2) {
+            let name: Vec<usize> = record[0]
+                .strip_prefix('>')
+                .unwrap()
+                .split('.')
+                .map(|x| x.parse().unwrap())
+                .collect();
+            assert_eq!(name.len(), 3);
+            clonotypes.push(name[0]);
+            assert!(record[1].bytes().all(|b| b"ACGT".contains(&b)));
+        }
+        clonotypes.dedup();
+        assert_eq!(clonotypes, (1..=summary.clonotypes).collect::<Vec<_>>());
+
+        // The translations contain the CDR3s of the dataset.
+
+        let fasta_aa = read_to_string(fasta_aa).unwrap();
+        assert_eq!(fasta_aa.lines().count(), lines.len());
+        for cdr3 in [
+            "CAKDRGYSSNYFDYW",
+            "CQQSYSTPRWTF",
+            "CAKEGSTNYFDYW",
+            "CQQSYSTPRRWTF",
+        ] {
+            assert!(fasta_aa.contains(cdr3));
+        }
+    }
+    #[test]
+    fn test_trees() {
+        // Of the chains built from the dataset, only the heavy chain of one cell differs from
+        // the germline, by one base.
+
+        let dir = tempfile::tempdir().unwrap();
+        let setup = main_enclone_setup_ranger(&run_args(dir.path(), &[])).unwrap();
+        let inter = main_enclone_start(setup).unwrap();
+        let mut diffs = 0;
+        for t in inter.ex.exact_clonotypes.iter().flat_map(|ex| &ex.share) {
+            let g = germline(t);
+            assert_eq!(g.len(), t.seq.len());
+            diffs += g.iter().zip(&t.seq).filter(|(a, b)| a != b).count();
+        }
+        assert_eq!(diffs, 1);
+
+        // That cell is in a clonotype with two unmutated cells, which form the first exact
+        // subclonotype.  The fourth cell is a clonotype by itself.
+
+        let summary = run(dir.path(), &["NEWICK".to_string()]);
+        assert_eq!(summary.trees, ["((1:0,2:1):0)germline;", "(1:0)germline;"]);
+        assert!(run(dir.path(), &[]).trees.is_empty());
+    }
+    #[test]
+    fn test_plots() {
+        let dir = tempfile::tempdir().unwrap();
+        let plot = dir.path().join("plot.svg");
+        run(dir.path(), &[format!("PLOT_BY_ISOTYPE={}", plot.display())]);
+        let svg = read_to_string(plot).unwrap();
+        assert!(svg.starts_with("<svg") && svg.trim_end().ends_with("</svg>"));
+
+        // Other targets are rejected with the arguments.
+
+        let png = format!("PLOT_BY_ISOTYPE={}", dir.path().join("plot.png").display());
+        let e = main_enclone_ranger(&run_args(dir.path(), &[png])).unwrap_err();
+        assert!(e.to_string().contains("can only be written as SVG"));
+    }
+    #[test]
+    fn test_exact_plots() {
+        let dir = tempfile::tempdir().unwrap();
+        let (xy, sim) = (dir.path().join("xy.svg"), dir.path().join("sim.svg"));
+        run(
+            dir.path(),
+            &[
+                format!("PLOTXY_EXACT=u1,u2,{},sym", xy.display()),
+                format!("SIM_MAT_PLOT={},cdr3_aa1,cdr3_aa2", sim.display()),
+            ],
+        );
+
+        // There is one point per exact subclonotype, and one rectangle per pair, besides the
+        // background and the legend.
+
+        let xy = read_to_string(xy).unwrap();
+        let n = xy.matches("<circle").count();
+        assert!(n >= 2);
+        let sim = read_to_string(sim).unwrap();
+        assert!(sim.contains("similarity of exact subclonotypes by cdr3_aa1, cdr3_aa2"));
+        assert_eq!(sim.matches("<rect").count(), n * n + 2);
+    }
+    #[test]
+    fn test_doublets() {
+        // Make five copies of each of the two cells of the dataset that share no CDR3s, and a
+        // cell having the heavy chain of the first and the light chain of the second, which
+        // appears to be a doublet of them.
+
+        let json = read_to_string(format!("{DATA}/contig_annotations.json")).unwrap();
+        let contigs: Vec<Value> = serde_json::from_str(&json).unwrap();
+        let of = |barcode: &str| -> Vec<Value> {
+            contigs
+                .iter()
+                .filter(|c| c["barcode"] == barcode)
+                .cloned()
+                .collect()
+        };
+        let (x, y) = (of("AAACCTGAGCGATAGC-1"), of("TTGCCGTCATGACGGA-1"));
+        let cells: Vec<[&Value; 2]> = (0..5)
+            .map(|_| [&x[0], &x[1]])
+            .chain((0..5).map(|_| [&y[0], &y[1]]))
+            .chain([[&x[0], &y[1]]])
+            .collect();
+
+        // Give each cell a barcode whose halves are both unique, so that the cells are not
+        // taken to be gel bead contamination.
+
+        let mut barcodes = Vec::<String>::new();
+        let mut tigs = Vec::<Value>::new();
+        for (n, cell) in cells.iter().enumerate() {
+            let (a, b) = (&"ACGT"[n / 4..n / 4 + 1], &"ACGT"[n % 4..n % 4 + 1]);
+            let (first, last) = (format!("{a}{b}").repeat(4), format!("{b}{a}").repeat(4));
+            let barcode = format!("{first}{last}-1");
+            for (k, &tig) in cell.iter().enumerate() {
+                let mut tig = tig.clone();
+                tig["barcode"] = json!(barcode);
+                tig["contig_name"] = json!(format!("{barcode}_contig_{}", k + 1));
+                tigs.push(tig);
+            }
+            barcodes.push(barcode);
+        }
+        let dir = tempfile::tempdir().unwrap();
+        let data = dir.path().join("data");
+        std::fs::create_dir(&data).unwrap();
+        write(
+            data.join("contig_annotations.json"),
+            serde_json::to_string_pretty(&tigs).unwrap(),
+        )
+        .unwrap();
+
+        // Run with doublet filtering on, writing the barcodes of the cells in clonotypes.
+
+        let (fate_file, pout) = (dir.path().join("fate.json"), dir.path().join("cells.csv"));
+        let mut args = run_args(
+            dir.path(),
+            &[
+                format!("FATE_FILE={}", fate_file.display()),
+                format!("POUT={}", pout.display()),
+                "PCOLS=barcode".to_string(),
+                "PCELL".to_string(),
+            ],
+        );
+        args.retain(|arg| arg != "NDOUBLET");
+        for arg in args.iter_mut() {
+            if arg.starts_with("BCR=") {
+                *arg = format!("BCR={}", data.display());
+            }
+        }
+        let summary = main_enclone_ranger(&args).unwrap();
+        assert_eq!(summary.fates.get("DOUBLET"), Some(&1));
+
+        // Every barcode not in a clonotype has a fate, and the doublet is the only one.
+
+        let pout = read_to_string(pout).unwrap();
+        let kept: BTreeSet<&str> = pout.lines().skip(1).collect();
+        let fates: Value = serde_json::from_str(&read_to_string(fate_file).unwrap()).unwrap();
+        let fates = &fates["datasets"][0]["fates"];
+        let lost: Vec<&String> = barcodes
+            .iter()
+            .filter(|bc| !kept.contains(bc.as_str()))
+            .collect();
+        assert_eq!(lost, [&barcodes[10]]);
+        for bc in lost {
+            assert!(fates.get(bc).is_some(), "{bc} has no fate");
+        }
+
+        // The doublet fate records the two cells it appears to be made of.
+
+        let doublet = &fates[&barcodes[10]];
+        assert_eq!(doublet["fate"], "Doublet");
+        let exact: BTreeSet<&str> = doublet["exact_subclonotype"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|x| x.as_str().unwrap())
+            .collect();
+        assert_eq!(exact, BTreeSet::from(["CAKDRGYSSNYFDYW", "CQQSYSTPRRWTF"]));
+        let pair: BTreeSet<&str> = ["pair1", "pair2"]
+            .iter()
+            .map(|p| doublet["evidence"][p].as_str().unwrap())
+            .collect();
+        assert_eq!(
+            pair,
+            BTreeSet::from([
+                "CAKDRGYSSNYFDYW,CQQSYSTPRWTF",
+                "CAKEGSTNYFDYW,CQQSYSTPRRWTF"
+            ])
+        );
+    }
+}
@@ -1,13 +1,22 @@
 // Copyright (c) 2021 10X Genomics, Inc. All rights reserved.
 
+use crate::summary::RangerRunSummary;
 use enclone_core::defs::ColInfo;
 use enclone_core::enclone_structs::EncloneIntermediates;
+use enclone_print::airr::write_airr;
+use enclone_print::group::group_clonotypes;
+use enclone_print::honeycomb::plot_honeycomb;
+use enclone_print::parseable::write_parseable;
+use enclone_print::plot_xy::plot_xy;
 use enclone_print::print_clonotypes::print_clonotypes;
-use hdf5::Reader;
-use rayon::prelude::*;
+use enclone_print::sequences::write_sequences;
+use enclone_print::sim_mat_plot::plot_sim_mat;
+use enclone_print::tree::CloneTree;
 use std::collections::HashMap;
 
-pub fn main_enclone_stop_ranger(mut inter: EncloneIntermediates) -> Result<(), String> {
+pub fn main_enclone_stop_ranger(
+    mut inter: EncloneIntermediates,
+) -> Result<RangerRunSummary, String> {
     // Unpack inputs.
 
     let to_bc = &inter.ex.to_bc;
@@ -25,36 +34,6 @@ pub fn main_enclone_stop_ranger(mut inter: EncloneIntermediates) -> Result<(), S
     let is_bcr = inter.ex.is_bcr;
     let allele_data = &inter.ex.allele_data;
 
-    // Load the GEX and FB data.  This is quite horrible: the code and computation are duplicated
-    // verbatim in fcell.rs.
-
-    let mut d_readers = Vec::<Option<Reader>>::new();
-    let mut ind_readers = Vec::<Option<Reader>>::new();
-    for li in 0..ctl.origin_info.n() {
-        if !ctl.origin_info.gex_path[li].is_empty() && !gex_info.gex_matrices[li].initialized() {
-            let x = gex_info.h5_data[li].as_ref();
-            d_readers.push(Some(x.unwrap().as_reader()));
-            ind_readers.push(Some(gex_info.h5_indices[li].as_ref().unwrap().as_reader()));
-        } else {
-            d_readers.push(None);
-            ind_readers.push(None);
-        }
-    }
-    let mut h5_data = Vec::<(usize, Vec<u32>, Vec<u32>)>::new();
-    for li in 0..ctl.origin_info.n() {
-        h5_data.push((li, Vec::new(), Vec::new()));
-    }
-    h5_data.par_iter_mut().for_each(|res| {
-        let li = res.0;
-        if !ctl.origin_info.gex_path[li].is_empty()
-            && !gex_info.gex_matrices[li].initialized()
-            && ctl.gen_opt.h5_pre
-        {
-            res.1 = d_readers[li].as_ref().unwrap().read_raw().unwrap();
-            res.2 = ind_readers[li].as_ref().unwrap().read_raw().unwrap();
-        }
-    });
-
     // Find and print clonotypes.  (But we don't actually print them here.)
 
     let mut pics = Vec::<String>::new();
@@ -77,9 +56,7 @@ pub fn main_enclone_stop_ranger(mut inter: EncloneIntermediates) -> Result<(), S
         raw_joins,
         gex_info,
         vdj_cells,
-        &d_readers,
-        &ind_readers,
-        &h5_data,
+        &inter.setup.gex_access,
         &mut pics,
         &mut exacts,
         &mut in_center,
@@ -90,5 +67,52 @@ pub fn main_enclone_stop_ranger(mut inter: EncloneIntermediates) -> Result<(), S
         fate,
         allele_data,
     )?;
-    Ok(())
+
+    // Group clonotypes.
+
+    let groups = group_clonotypes(ctl, &exacts, exact_clonotypes, &rsi, &in_center, refdata);
+
+    // Write parseable output.
+
+    if !ctl.parseable_opt.pout.is_empty() {
+        write_parseable(ctl, &exacts, exact_clonotypes, &rsi, &out_datas, &groups)?;
+    }
+
+    // Write AIRR output.
+
+    if !ctl.gen_opt.airr.is_empty() {
+        write_airr(ctl, &exacts, exact_clonotypes, refdata, drefs)?;
+    }
+
+    // Write FASTA, CLUSTAL and PHYLIP output.
+
+    write_sequences(ctl, &exacts, exact_clonotypes, &rsi, refdata)?;
+
+    // Make the honeycomb plot.
+
+    if !ctl.plot_opt.plot_file.is_empty() {
+        plot_honeycomb(ctl, &exacts, exact_clonotypes, &out_datas, refdata)?;
+    }
+
+    // Make the PLOTXY_EXACT and SIM_MAT_PLOT plots.
+
+    if !ctl.plot_opt.plot_xy_filename.is_empty() {
+        plot_xy(ctl, &exacts, exact_clonotypes, &out_datas)?;
+    }
+    if !ctl.plot_opt.sim_mat_plot_file.is_empty() {
+        plot_sim_mat(ctl, &out_datas)?;
+    }
+
+    // Summarize.  The trees for TREE and NEWICK are returned here, as the text output that
+    // would show them is not written.
+
+    let mut summary = RangerRunSummary::new(ctl, exact_clonotypes, &exacts, &rsi, fate, groups);
+    if ctl.gen_opt.tree_on || ctl.gen_opt.newick {
+        summary.trees = exacts
+            .iter()
+            .zip(rsi.iter())
+            .map(|(exacts, rsi)| CloneTree::new(exacts, exact_clonotypes, rsi).newick())
+            .collect();
+    }
+    Ok(summary)
 }
@@ -0,0 +1,142 @@
+// Copyright (c) 2021 10X Genomics, Inc. All rights reserved.
+
+// Summary of a ranger run, computed from what print_clonotypes returns, so that metrics can be
+// reported without rereading the proto file.
+
+use enclone_core::barcode_fate::FateRecord;
+use enclone_core::defs::{ColInfo, EncloneControl, ExactClonotype};
+use serde::Serialize;
+use std::collections::{BTreeMap, HashMap};
+
+#[derive(Clone, Debug, Default, Serialize)]
+pub struct RangerRunSummary {
+    pub clonotypes: usize,
+    pub cells: usize,
+    /// Number of cells in each dataset, in dataset order, keyed by dataset id.
+    pub cells_by_dataset: Vec<(String, usize)>,
+    /// Number of barcodes having each fate, keyed by the fate label.
+    pub fates: BTreeMap<&'static str, usize>,
+    /// Map from number of chains to number of clonotypes having that many chains.
+    pub chains: BTreeMap<usize, usize>,
+    /// The clonotype groups, each given by the indices of its clonotypes in output order.
+    pub groups: Vec<Vec<usize>>,
+    /// The tree of each clonotype, in output order, in Newick format, if TREE or NEWICK was
+    /// given.  These are set by main_enclone_stop_ranger.
+    pub trees: Vec<String>,
+    /// Wall time in seconds for each stage of the run (setup, start and stop), in the order they
+    /// ran.  These are recorded by main_enclone_ranger_config.
+    pub stage_times: Vec<(&'static str, f64)>,
+}
+
+impl RangerRunSummary {
+    pub fn new(
+        ctl: &EncloneControl,
+        exact_clonotypes: &[ExactClonotype],
+        exacts: &[Vec<usize>],
+        rsi: &[ColInfo],
+        fate: &[HashMap<String, FateRecord>],
+        groups: Vec<Vec<usize>>,
+    ) -> RangerRunSummary {
+        let mut cells_by_dataset = vec![0; ctl.origin_info.n()];
+        for e in exacts.iter().flatten() {
+            for clone in &exact_clonotypes[*e].clones {
+                cells_by_dataset[clone[0].dataset_index] += 1;
+            }
+        }
+        let mut fates = BTreeMap::<&'static str, usize>::new();
+        for f in fate.iter().flat_map(HashMap::values) {
+            *fates.entry(f.label()).or_default() += 1;
+        }
+        let mut chains = BTreeMap::<usize, usize>::new();
+        for x in rsi {
+            *chains.entry(x.mat.len()).or_default() += 1;
+        }
+        RangerRunSummary {
+            clonotypes: exacts.len(),
+            cells: cells_by_dataset.iter().sum(),
+            cells_by_dataset: ctl
+                .origin_info
+                .dataset_id
+                .iter()
+                .cloned()
+                .zip(cells_by_dataset)
+                .collect(),
+            fates,
+            chains,
+            groups,
+            trees: Vec::new(),
+            stage_times: Vec::new(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::RangerRunSummary;
+    use enclone_core::barcode_fate::{BarcodeFate, FateRecord};
+    use enclone_core::defs::{ColInfo, EncloneControl, ExactClonotype, TigData0};
+    use std::collections::HashMap;
+
+    #[test]
+    fn test_run_summary() {
+        let mut ctl = EncloneControl::default();
+        ctl.origin_info.dataset_path = vec!["a".to_string(), "b".to_string()];
+        ctl.origin_info.dataset_id = vec!["A".to_string(), "B".to_string()];
+
+        // Exact subclonotypes having cells from datasets {0, 1} and {1, 1, 1}.
+
+        let exact = |datasets: &[usize]| ExactClonotype {
+            share: Vec::new(),
+            clones: datasets
+                .iter()
+                .map(|&dataset_index| {
+                    vec![TigData0 {
+                        dataset_index,
+                        ..TigData0::default()
+                    }]
+                })
+                .collect(),
+        };
+        let exact_clonotypes = vec![exact(&[0, 1]), exact(&[1, 1, 1]), exact(&[0])];
+
+        // Two clonotypes, one having two chains and the other three.  The third exact
+        // subclonotype is not in a clonotype, so its cell is not counted.
+
+        let exacts = vec![vec![1], vec![0]];
+        let rsi = [2, 3].map(|n| ColInfo {
+            mat: vec![Vec::new(); n],
+            ..ColInfo::default()
+        });
+        let fate = vec![
+            HashMap::from([
+                ("AAA-1".to_string(), FateRecord::from(BarcodeFate::Umi)),
+                ("CCC-1".to_string(), FateRecord::from(BarcodeFate::Umi)),
+            ]),
+            HashMap::from([("GGG-1".to_string(), FateRecord::from(BarcodeFate::Qual))]),
+        ];
+        let summary = RangerRunSummary::new(
+            &ctl,
+            &exact_clonotypes,
+            &exacts,
+            &rsi,
+            &fate,
+            vec![vec![0, 1]],
+        );
+        assert_eq!(summary.clonotypes, 2);
+        assert_eq!(summary.cells, 5);
+        assert_eq!(
+            summary.cells_by_dataset,
+            [("A".to_string(), 1), ("B".to_string(), 4)]
+        );
+        assert_eq!(
+            summary.fates.into_iter().collect::<Vec<_>>(),
+            [("QUAL", 1), ("UMI", 2)]
+        );
+        assert_eq!(
+            summary.chains.into_iter().collect::<Vec<_>>(),
+            [(2, 1), (3, 1)]
+        );
+        assert_eq!(summary.groups, [vec![0, 1]]);
+        assert!(summary.stage_times.is_empty());
+    }
+}
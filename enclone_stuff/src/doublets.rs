@@ -1,11 +1,10 @@
 // Copyright (c) 2021 10X Genomics, Inc. All rights reserved.
 
-// Delete exact subclonotypes that appear to represent doublets.
-//
-// THIS FILTER DOESN'T PROPERLY TRACK FATE.
+// Delete exact subclonotypes that appear to represent doublets.  The fate of each deleted cell
+// records the pair of pure subclonotypes that it appears to be a doublet of.
 
 use enclone_core::{
-    barcode_fate::BarcodeFate,
+    barcode_fate::{BarcodeFate, FateRecord},
     defs::{CloneInfo, EncloneControl, ExactClonotype},
 };
 use enclone_print::define_mat::{define_mat, setup_define_mat};
@@ -16,7 +15,7 @@ use rayon::prelude::*;
 use std::collections::HashMap;
 use std::time::Instant;
 use vdj_ann::refx::RefData;
-use vector_utils::{bin_member, erase_if, next_diff, next_diff1_2, sort_sync2};
+use vector_utils::{bin_member, erase_if, next_diff, next_diff1_2, sort_sync2, unique_sort};
 
 pub fn delete_doublets(
     orbits: &mut Vec<Vec<i32>>,
@@ -29,7 +28,7 @@ pub fn delete_doublets(
     raw_joins: &[Vec<usize>],
     refdata: &RefData,
     dref: &[DonorReferenceItem],
-    fate: &mut [HashMap<String, BarcodeFate>],
+    fate: &mut [HashMap<String, FateRecord>],
 ) {
     if ctl.clono_filt_opt_def.doublet {
         let t = Instant::now();
@@ -187,9 +186,18 @@ pub fn delete_doublets(
         }
         ctl.perf_stats(&t, "doublet filtering trips");
 
-        // Delete some of the third members of the triples.
+        // Delete some of the third members of the triples.  A pure subclonotype may be the third
+        // member of more than one triple, in which case the first is recorded as its fate.
 
         let t = Instant::now();
+        let pure_cdr3s = |p: &[usize]| {
+            let mut cdr3s: Vec<String> = p
+                .iter()
+                .flat_map(|&id| exact_clonotypes[id].share.iter().map(|s| s.cdr3_aa.clone()))
+                .collect();
+            unique_sort(&mut cdr3s);
+            cdr3s.join(",")
+        };
         let mut to_delete = vec![false; exact_clonotypes.len()];
         for (v1, v2, v0) in trips {
             let verbose = false;
@@ -223,8 +231,21 @@ pub fn delete_doublets(
                     println!("[{}] {}", u + 1, cdrs.iter().format(","));
                 }
             }
-            for m in pures[v0].iter() {
-                to_delete[*m] = true;
+            let (pair1, pair2) = (pure_cdr3s(&pures[v1]), pure_cdr3s(&pures[v2]));
+            for &m in &pures[v0] {
+                if to_delete[m] {
+                    continue;
+                }
+                to_delete[m] = true;
+                for clone in &exact_clonotypes[m].clones {
+                    fate[clone[0].dataset_index].insert(
+                        clone[0].barcode.clone(),
+                        FateRecord::from(BarcodeFate::Doublet)
+                            .exact(&exact_clonotypes[m].share)
+                            .evidence("pair1", &pair1)
+                            .evidence("pair2", &pair2),
+                    );
+                }
             }
         }
         let mut orbits2 = Vec::<Vec<i32>>::new();
@@ -235,13 +256,6 @@ pub fn delete_doublets(
                 let id = info[o[j] as usize].clonotype_index;
                 if to_delete[id] {
                     del2[j] = true;
-                    let x: &CloneInfo = &info[o[j] as usize];
-                    let ex = &exact_clonotypes[x.clonotype_index];
-                    for k in 0..ex.ncells() {
-                        let li = ex.clones[k][0].dataset_index;
-                        let bc = &ex.clones[k][0].barcode;
-                        fate[li].insert(bc.clone(), BarcodeFate::Doublet);
-                    }
                 }
             }
             erase_if(&mut o, &del2);
@@ -3,7 +3,7 @@
 // Filter B cells based on UMI counts.
 
 use enclone_core::{
-    barcode_fate::BarcodeFate,
+    barcode_fate::{BarcodeFate, FateRecord},
     defs::{CloneInfo, EncloneControl, ExactClonotype},
 };
 use equiv::EquivRel;
@@ -18,7 +18,7 @@ pub fn filter_umi(
     ctl: &EncloneControl,
     exact_clonotypes: &mut [ExactClonotype],
     info: &[CloneInfo],
-    fate: &mut [HashMap<String, BarcodeFate>],
+    fate: &mut [HashMap<String, FateRecord>],
 ) {
     let (mut is_tcr, mut is_bcr) = (true, true);
     if ctl.gen_opt.tcr {
@@ -129,6 +129,7 @@ pub fn filter_umi(
                         let x: &CloneInfo = &info[o[j] as usize];
                         let ex = &mut exact_clonotypes[x.clonotype_index];
                         let mut to_delete = vec![false; ex.ncells()];
+                        let mut umitots = vec![0; ex.ncells()];
                         let mut ex_sum = 0;
                         for (k, (clone, d)) in
                             ex.clones.iter_mut().zip(to_delete.iter_mut()).enumerate()
@@ -144,6 +145,7 @@ pub fn filter_umi(
                                     }
                                 }
                                 let umitot = umish + umisl;
+                                umitots[k] = umitot;
                                 if pass == 1 {
                                     ex_sum += umitot;
                                 }
@@ -187,8 +189,14 @@ pub fn filter_umi(
                         if pass == 3 {
                             for i in 0..ex.clones.len() {
                                 if to_delete[i] {
-                                    fate[ex.clones[i][0].dataset_index]
-                                        .insert(ex.clones[i][0].barcode.clone(), BarcodeFate::Umi);
+                                    let li = ex.clones[i][0].dataset_index;
+                                    fate[li].insert(
+                                        ex.clones[i][0].barcode.clone(),
+                                        FateRecord::from(BarcodeFate::Umi)
+                                            .exact(&ex.share)
+                                            .evidence("umis", umitots[i])
+                                            .evidence("min_umis", format!("{:.2}", umin[li])),
+                                    );
                                 }
                             }
                             if ctl.clono_filt_opt_def.umi_filt {
@@ -249,10 +257,13 @@ pub fn filter_umi(
                 let mut to_deletex = vec![false; o.len()];
                 let mut z = Vec::<(Vec<u8>, usize, usize, usize, usize)>::new();
                 let mut to_delete = Vec::<Vec<bool>>::new();
+                // (chain UMIs, cell UMIs) that caused each deletion:
+                let mut ratios = Vec::<Vec<(usize, usize)>>::new();
                 for j in 0..o.len() {
                     let x: &CloneInfo = &info[o[j] as usize];
                     let ex = &mut exact_clonotypes[x.clonotype_index];
                     to_delete.push(vec![false; ex.ncells()]);
+                    ratios.push(vec![(0, 0); ex.ncells()]);
                     for k in 0..ex.ncells() {
                         let mut tot = 0;
                         for m in 0..ex.clones[k].len() {
@@ -276,6 +287,7 @@ pub fn filter_umi(
                     for l in j..k {
                         if z[j].1 >= MIN_UMI_RATIO * z[l].4 {
                             to_delete[z[l].2][z[l].3] = true;
+                            ratios[z[l].2][z[l].3] = (z[j].1, z[l].4);
                         }
                     }
                     j = k;
@@ -295,8 +307,13 @@ pub fn filter_umi(
                     if pass == 2 {
                         for i in 0..ex.clones.len() {
                             if to_delete[j][i] {
-                                fate[ex.clones[i][0].dataset_index]
-                                    .insert(ex.clones[i][0].barcode.clone(), BarcodeFate::UmiRatio);
+                                fate[ex.clones[i][0].dataset_index].insert(
+                                    ex.clones[i][0].barcode.clone(),
+                                    FateRecord::from(BarcodeFate::UmiRatio)
+                                        .exact(&ex.share)
+                                        .evidence("dominant_chain_umis", ratios[j][i].0)
+                                        .evidence("cell_umis", ratios[j][i].1),
+                                );
                             }
                         }
                         if ctl.clono_filt_opt_def.umi_ratio_filt {
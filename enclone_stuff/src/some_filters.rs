@@ -4,7 +4,7 @@ use crate::doublets::delete_doublets;
 use crate::merge_onesies::merge_onesies;
 use crate::split_orbits::split_orbits;
 use crate::weak_chains::weak_chains;
-use enclone_core::barcode_fate::BarcodeFate;
+use enclone_core::barcode_fate::{BarcodeFate, FateRecord};
 use enclone_core::defs::{CloneInfo, EncloneControl, ExactClonotype};
 use enclone_print::define_mat::{define_mat, setup_define_mat};
 use enclone_print::print_utils3::define_column_info;
@@ -13,7 +13,7 @@ use equiv::EquivRel;
 use qd::Double;
 use rayon::prelude::*;
 use std::cmp::max;
-use std::collections::{HashMap, HashSet};
+use std::collections::HashMap;
 use std::time::Instant;
 use vdj_ann::refx::RefData;
 use vector_utils::{erase_if, next_diff1_2, unique_sort};
@@ -29,7 +29,7 @@ pub fn some_filters(
     raw_joins: &[Vec<usize>],
     eq: &EquivRel,
     disintegrated: &[bool],
-    fate: &mut [HashMap<String, BarcodeFate>],
+    fate: &mut [HashMap<String, FateRecord>],
     refdata: &RefData,
     dref: &[DonorReferenceItem],
 ) {
@@ -57,7 +57,7 @@ pub fn some_filters(
 
     let tsig = Instant::now();
     const SIG_MULT: usize = 20;
-    let mut results = Vec::<(usize, Vec<(usize, String, BarcodeFate)>, Vec<usize>)>::new();
+    let mut results = Vec::<(usize, Vec<(usize, String, FateRecord)>, Vec<usize>)>::new();
     for i in 0..orbits.len() {
         results.push((i, Vec::new(), Vec::new()));
     }
@@ -117,7 +117,7 @@ pub fn some_filters(
 
         // Decide which signatures to delete.
 
-        let mut dels = HashSet::<Vec<usize>>::new();
+        let mut dels = HashMap::<Vec<usize>, (usize, usize)>::new(); // {signature: (cells, n2)}
         for i in 0..freq.len() {
             let mut n2 = 0;
             for j in 0..freq.len() {
@@ -134,7 +134,7 @@ pub fn some_filters(
                 }
             }
             if n2 > SIG_MULT * freq[i].0 {
-                dels.insert(freq[i].1.clone());
+                dels.insert(freq[i].1.clone(), (freq[i].0, n2));
                 /*
                 msg += &mut format!("delete {}\n", freq[i].1.iter().format(",")); // XXXXXXXXXX
                 */
@@ -150,14 +150,20 @@ pub fn some_filters(
                     t.push(col);
                 }
             }
-            if dels.contains(&t) && ctl.clono_filt_opt_def.signature {
+            if let Some(&(cells, n2)) = dels.get(&t) {
+                if !ctl.clono_filt_opt_def.signature {
+                    continue;
+                }
                 res.2.push(exacts[u]);
                 let ex = &exact_clonotypes[exacts[u]];
                 for i in 0..ex.ncells() {
                     res.1.push((
                         ex.clones[i][0].dataset_index,
                         ex.clones[i][0].barcode.clone(),
-                        BarcodeFate::Signature,
+                        FateRecord::from(BarcodeFate::Signature)
+                            .exact(&ex.share)
+                            .evidence("signature_cells", cells)
+                            .evidence("sharing_cells", n2),
                     ));
                 }
             }
@@ -250,7 +256,7 @@ pub fn some_filters(
     // accounting for all the cells in all the exact subclonotypes, never occurs as Q60
     // doesn't occur as Q40 twice, and disagrees with the reference.
 
-    let mut results = Vec::<(usize, Vec<(usize, String, BarcodeFate)>, Vec<usize>)>::new();
+    let mut results = Vec::<(usize, Vec<(usize, String, FateRecord)>, Vec<usize>)>::new();
     for i in 0..orbits.len() {
         results.push((i, Vec::new(), Vec::new()));
     }
@@ -395,7 +401,7 @@ pub fn some_filters(
                 }
             }
             if !q60 && q40 < 2 {
-                let u = vquals[j].4;
+                let (col, p, b, _, u) = vquals[j];
                 if ctl.clono_filt_opt.qual_filter {
                     res.2.push(exacts[u]);
                 }
@@ -404,7 +410,12 @@ pub fn some_filters(
                     res.1.push((
                         ex.clones[i][0].dataset_index,
                         ex.clones[i][0].barcode.clone(),
-                        BarcodeFate::Qual,
+                        FateRecord::from(BarcodeFate::Qual)
+                            .exact(&ex.share)
+                            .evidence("chain", col + 1)
+                            .evidence("position", p)
+                            .evidence("base", b as char)
+                            .evidence("q40_count", q40),
                     ));
                 }
             }
@@ -19,7 +19,8 @@ use enclone::misc1::{cross_filter, lookup_heavy_chain_reuse};
 use enclone::misc2::{check_for_barcode_reuse, find_exact_subclonotypes, search_for_shm_indels};
 use enclone::misc3::sort_tig_bc;
 use enclone_args::read_json::parse_json_annotations_files;
-use enclone_core::barcode_fate::BarcodeFate;
+use enclone_args::vdj_source::VdjSource;
+use enclone_core::barcode_fate::{BarcodeFate, FateRecord};
 use enclone_core::defs::{AlleleData, CloneInfo, TigData};
 use enclone_core::enclone_structs::{EncloneExacts, EncloneIntermediates, EncloneSetup};
 use enclone_core::hcomp::heavy_complexity;
@@ -102,6 +103,17 @@ pub fn stirling2_ratio_table_double(n_max: usize) -> Vec<Vec<Double>> {
 // ▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓
 
 pub fn main_enclone_start(setup: EncloneSetup) -> Result<EncloneIntermediates, String> {
+    let sources = VdjSource::files(&setup.ctl);
+    main_enclone_start_from(setup, &sources)
+}
+
+// Same as main_enclone_start, but the contig annotations for each dataset are taken from the
+// given sources, which are in dataset order, rather than read from the dataset directories.
+
+pub fn main_enclone_start_from(
+    setup: EncloneSetup,
+    sources: &[VdjSource],
+) -> Result<EncloneIntermediates, String> {
     let tr = Instant::now();
     let ctl = &setup.ctl;
     let gex_info = &setup.gex_info;
@@ -125,9 +137,10 @@ pub fn main_enclone_start(setup: EncloneSetup) -> Result<EncloneIntermediates, S
     let mut vdj_cells = Vec::<Vec<String>>::new();
     let mut gex_cells = Vec::<Vec<String>>::new();
     let mut gex_cells_specified = Vec::<bool>::new();
-    let mut fate = vec![HashMap::<String, BarcodeFate>::new(); ctl.origin_info.n()];
+    let mut fate = vec![HashMap::<String, FateRecord>::new(); ctl.origin_info.n()];
     parse_json_annotations_files(
         ctl,
+        sources,
         &mut tig_bc,
         refdata,
         to_ref_index,
@@ -192,7 +205,7 @@ pub fn main_enclone_start(setup: EncloneSetup) -> Result<EncloneIntermediates, S
             let bc = &tigi[0].barcode;
             let li = tigi[0].dataset_index;
             if !bin_member(&vdj_cells[li], bc) {
-                fate[li].insert(bc.clone(), BarcodeFate::NotAsmCell);
+                fate[li].insert(bc.clone(), BarcodeFate::NotAsmCell.into());
             }
         }
     }
@@ -258,8 +271,10 @@ pub fn main_enclone_start(setup: EncloneSetup) -> Result<EncloneIntermediates, S
                         if bin_member(&twosies, &p) {
                             *d = true;
                             for clone in &ex.clones {
-                                fate[clone[0].dataset_index]
-                                    .insert(clone[0].barcode.clone(), BarcodeFate::FoursieKill);
+                                fate[clone[0].dataset_index].insert(
+                                    clone[0].barcode.clone(),
+                                    FateRecord::from(BarcodeFate::FoursieKill).exact(&ex.share),
+                                );
                             }
                         }
                     }
@@ -285,6 +300,14 @@ pub fn main_enclone_start(setup: EncloneSetup) -> Result<EncloneIntermediates, S
             }
             if heavies > 1 {
                 to_delete[i] = true;
+                for clone in &ex.clones {
+                    fate[clone[0].dataset_index].insert(
+                        clone[0].barcode.clone(),
+                        FateRecord::from(BarcodeFate::TooManyHeavies)
+                            .exact(&ex.share)
+                            .evidence("heavies", heavies),
+                    );
+                }
             }
         }
         erase_if(&mut exact_clonotypes, &to_delete);
@@ -512,12 +535,18 @@ pub fn main_enclone_start(setup: EncloneSetup) -> Result<EncloneIntermediates, S
                     if ctl.gen_opt.cellranger {
                         if gex_cells_specified[li] && !bin_member(&gex_cells[li], bc) {
                             *d = true;
-                            fate[li].insert(bc.clone(), BarcodeFate::NotGexCell);
+                            fate[li].insert(
+                                bc.clone(),
+                                FateRecord::from(BarcodeFate::NotGexCell).exact(&ex.share),
+                            );
                         }
                     } else if !ctl.origin_info.gex_path[li].is_empty() {
                         let gbc = &gex_info.gex_cell_barcodes[li];
                         if !bin_member(gbc, bc) {
-                            fate[li].insert(bc.clone(), BarcodeFate::NotGexCell);
+                            fate[li].insert(
+                                bc.clone(),
+                                FateRecord::from(BarcodeFate::NotGexCell).exact(&ex.share),
+                            );
                             if !ctl.clono_filt_opt_def.ngex {
                                 *d = true;
                             }
@@ -539,11 +568,19 @@ pub fn main_enclone_start(setup: EncloneSetup) -> Result<EncloneIntermediates, S
 
     // Filter using constraints imposed by FCELL.
 
-    filter_by_fcell(ctl, &mut orbits, info, &mut exact_clonotypes, gex_info)?;
+    filter_by_fcell(
+        ctl,
+        &mut orbits,
+        info,
+        &mut exact_clonotypes,
+        gex_info,
+        &setup.gex_access,
+    )?;
     ctl.perf_stats(&tumi, "umi filtering and such");
 
     // Break up clonotypes containing a large number of chains. These are
-    // very likely to be false merges
+    // very likely to be false merges.  Every cell is kept, in one of the
+    // pieces, so there is no fate to record.
     let mut orbits: Vec<Vec<i32>> = orbits
         .into_iter()
         .flat_map(|orbit| {
@@ -4,7 +4,7 @@
 // look like junk.  Preliminary heuristic.
 
 use enclone_core::{
-    barcode_fate::BarcodeFate,
+    barcode_fate::{BarcodeFate, FateRecord},
     defs::{CloneInfo, EncloneControl, ExactClonotype},
 };
 use enclone_print::define_mat::{define_mat, setup_define_mat};
@@ -24,13 +24,13 @@ pub fn weak_chains(
     exact_clonotypes: &[ExactClonotype],
     info: &[CloneInfo],
     raw_joins: &[Vec<usize>],
-    fate: &mut [HashMap<String, BarcodeFate>],
+    fate: &mut [HashMap<String, FateRecord>],
     refdata: &RefData,
     dref: &[DonorReferenceItem],
 ) {
     // Note mat calculation duplicated with print_clonotypes and also doublet detection.
 
-    let mut results = Vec::<(usize, Vec<(usize, String, BarcodeFate)>, Vec<usize>)>::new();
+    let mut results = Vec::<(usize, Vec<(usize, String, FateRecord)>, Vec<usize>)>::new();
     for i in 0..orbits.len() {
         results.push((i, Vec::new(), Vec::new()));
     }
@@ -84,7 +84,10 @@ pub fn weak_chains(
                             res.1.push((
                                 ex.clones[i][0].dataset_index,
                                 ex.clones[i][0].barcode.clone(),
-                                BarcodeFate::WeakChains,
+                                FateRecord::from(BarcodeFate::WeakChains)
+                                    .exact(&ex.share)
+                                    .evidence("chain_cells", ncells[j])
+                                    .evidence("total_cells", total_cells),
                             ));
                         }
                     }
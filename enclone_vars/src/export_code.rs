@@ -684,18 +684,18 @@ pub fn export_code(level: usize) -> Vec<(String, String)> {
         // This file is auto-generated by the crate enclone_vars, please do not edit.
 
         use amino::*;
+        use enclone_core::barcode_fate::FateRecord;
         use enclone_core::defs::*;
+        use enclone_core::gex_access::GexMatrixAccess;
         use enclone_core::median::*;
         use enclone_proto::types::*;
         use itertools::Itertools;
-        use ndarray::s;
         use regex::Regex;
         use std::cmp::{max, min};
         use std::collections::HashMap;
         use string_utils::*;
         use vdj_ann::refx::RefData;
         use vector_utils::*;
-        use hdf5::Reader;
 
         pub fn proc_lvar_auto(
             i: usize,
@@ -712,7 +712,7 @@ pub fn export_code(level: usize) -> Vec<(String, String)> {
             stats: &mut Vec<(String, Vec<String>)>,
             lvars: &Vec<String>,
             row: &mut Vec<String>,
-            fate: &Vec<HashMap<String, String>>,
+            fate: &Vec<HashMap<String, FateRecord>>,
             dref: &Vec<DonorReferenceItem>,
             varmat: &Vec<Vec<Vec<u8>>>,
             fp: &Vec<Vec<usize>>,
@@ -725,9 +725,7 @@ pub fn export_code(level: usize) -> Vec<(String, String)> {
             gex_counts_unsorted: &Vec<usize>,
             gex_fcounts_unsorted: &Vec<f64>,
             n_gexs: &Vec<usize>,
-            d_readers: &Vec<Option<Reader>>,
-            ind_readers: &Vec<Option<Reader>>,
-            h5_data: &Vec<(usize, Vec<u32>, Vec<u32>)>,
+            gex_access: &GexMatrixAccess,
             alt_bcs: &Vec<String>,
         ) -> Result<bool, String> {
 